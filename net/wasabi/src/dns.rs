@@ -0,0 +1,34 @@
+use alloc::format;
+use alloc::vec::Vec;
+use noli::net::{lookup_host, IpV4Addr};
+use saba_core::dns::{AddressFamily, DnsRecord, DnsResolver};
+use saba_core::error::Error;
+
+/// `lookup_host` はTTLを返さないので、すべてのレコードをこの秒数だけキャッシュする
+const DEFAULT_TTL: u64 = 60;
+
+pub struct WasabiDnsResolver {}
+
+impl WasabiDnsResolver {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl DnsResolver for WasabiDnsResolver {
+    type Addr = IpV4Addr;
+
+    fn lookup(&self, host: &str) -> Result<Vec<DnsRecord<Self::Addr>>, Error> {
+        match lookup_host(host) {
+            Ok(ips) => Ok(ips
+                .into_iter()
+                .map(|addr| DnsRecord {
+                    addr,
+                    family: AddressFamily::Ipv4,
+                    ttl: DEFAULT_TTL,
+                })
+                .collect()),
+            Err(e) => Err(Error::Network(format!("Failed to find IP adresses: {:#?}", e))),
+        }
+    }
+}
@@ -1,49 +1,97 @@
+use crate::dns::WasabiDnsResolver;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use noli::net::{lookup_host, SocketAddr, TcpStream};
+use core::cell::RefCell;
+use noli::net::{SocketAddr, TcpStream};
+use saba_core::dns::CachedDnsResolver;
 use saba_core::error::Error;
-use saba_core::http::HttpResponse;
+use saba_core::http::{is_body_less_status, is_interim_status, CancelToken, FetchObserver, HttpRequest, HttpResponse};
 
-pub struct HttpClient {}
+/// 名前解決の結果をキャッシュしてよい最長の秒数
+const MAX_DNS_TTL: u64 = 300;
+
+pub struct HttpClient {
+    dns: RefCell<CachedDnsResolver<WasabiDnsResolver>>,
+    /// 現在時刻を秒で返す。名前解決のキャッシュの期限を決めるのに使う
+    clock: fn() -> u64,
+}
 
 impl HttpClient {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(clock: fn() -> u64) -> Self {
+        Self {
+            dns: RefCell::new(CachedDnsResolver::new(WasabiDnsResolver::new(), MAX_DNS_TTL)),
+            clock,
+        }
     }
 
     pub fn get(&self, host: String, port: u16, path: String) -> Result<HttpResponse, Error> {
-        let ips = match lookup_host(&host) {
-            Ok(ips) => ips,
-            Err(e) => { return Err(Error::Network(format!("Failed to find IP adresses: {:#?}", e))) }
-        };
+        self.send(&Self::request("GET", &host, port, &path), None, None)
+    }
 
-        if ips.len() < 1 {
-            return Err(Error::Network("Failed to find IP addresses".to_string()));
-        }
+    pub fn get_with_observer(
+        &self,
+        host: String,
+        port: u16,
+        path: String,
+        observer: &mut dyn FetchObserver,
+    ) -> Result<HttpResponse, Error> {
+        self.send(&Self::request("GET", &host, port, &path), Some(observer), None)
+    }
 
-        let socket_addr: SocketAddr = (ips[0], port).into();
+    pub fn head(&self, host: String, port: u16, path: String) -> Result<HttpResponse, Error> {
+        self.send(&Self::request("HEAD", &host, port, &path), None, None)
+    }
 
-        let mut stream = match TcpStream::connect(socket_addr) {
-            Ok(stream) => stream,
-            Err(_) => { return Err(Error::Network("Failed to connect to TCP stream".to_string())) }
-        };
+    fn request(method: &str, host: &str, port: u16, path: &str) -> HttpRequest {
+        let mut request = HttpRequest::new(method, host, port, path);
+        request.add_header("Accept", "text/html");
+        // レスポンスの終わりはコネクションが閉じられることで判断する
+        request.add_header("Connection", "close");
+        request
+    }
+
+    pub fn send(
+        &self,
+        request: &HttpRequest,
+        mut observer: Option<&mut dyn FetchObserver>,
+        cancel_token: Option<&CancelToken>,
+    ) -> Result<HttpResponse, Error> {
+        let is_cancelled = || cancel_token.is_some_and(|t| t.is_cancelled());
 
-        let mut request = String::from("GET /");
-        request.push_str(&path);
-        request.push_str(" HTTP/1.1\n");
+        let ips = self.dns.borrow_mut().resolve(&request.host(), (self.clock)())?;
 
-        request.push_str(&format!("Host: {}\n", host));
-        request.push_str(&format!("Accept: text/html\n"));
-        request.push_str(&format!("Connection: close\n"));
-        request.push_str("\n");
+        // 並べられた順にアドレスを試し、最初に接続できたものを使う
+        let mut stream = None;
+        for ip in ips {
+            let socket_addr: SocketAddr = (ip, request.port()).into();
+            if let Ok(s) = TcpStream::connect(socket_addr) {
+                stream = Some(s);
+                break;
+            }
+        }
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => { return Err(Error::Network("Failed to connect to TCP stream".to_string())) }
+        };
+
+        if is_cancelled() {
+            return Err(Error::Cancelled);
+        }
 
-        let _bytes_written = match stream.write(request.as_bytes()) {
+        let request_string = request.serialize();
+        let _bytes_written = match stream.write(request_string.as_bytes()) {
             Ok(bytes) => bytes,
             Err(_) => { return Err(Error::Network("Failed to send a request to TCP stream".to_string())) }
         };
 
+        let expects_body = request.method() != "HEAD";
         let mut received = Vec::new();
+        let mut content_length = None;
+        let mut headers_notified = false;
+        let mut header_end = None;
+        // ヘッダの終わりを探し終えた位置。次に受信したときはその続きから探す
+        let mut scanned = 0;
         loop {
             let mut buf = [0u8; 4096];
             let bytes_read = match stream.read(&mut buf) {
@@ -51,15 +99,102 @@ impl HttpClient {
                 Err(_) => { return Err(Error::Network("Failed to receive a request from TCP stream".to_string())) }
             };
 
+            // 中断された場合は受信済みのデータを破棄する
+            if is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
             if bytes_read == 0 {
                 break;
             }
             received.extend(&buf[..bytes_read]);
+
+            while header_end.is_none() {
+                match find_header_end(&received, scanned) {
+                    // 1xxの途中経過のレスポンスは読み捨て、同じ接続で続く最終的なレスポンスを待つ
+                    Some(end) if is_interim_status(parse_status_code(&received[..end])) => {
+                        received.drain(..end);
+                        scanned = 0;
+                    }
+                    Some(end) => header_end = Some(end),
+                    None => {
+                        // 空行が受信した区切りをまたぐ場合に備えて、最後の数バイトは次も調べる
+                        scanned = received.len().saturating_sub(3);
+                        break;
+                    }
+                }
+            }
+            let header_end = match header_end {
+                Some(header_end) => header_end,
+                None => continue,
+            };
+
+            if let Some(observer) = observer.as_mut() {
+                if !headers_notified {
+                    headers_notified = true;
+                    if let Ok(headers) = parse_response(&received[..header_end], false) {
+                        content_length = headers.content_length();
+                        observer.on_headers(&headers);
+                    }
+                }
+            }
+
+            // ボディを持たないレスポンスはヘッダを受け取った時点で読み込みを終える
+            if !expects_body || is_body_less_status(parse_status_code(&received[..header_end])) {
+                received.truncate(header_end);
+                break;
+            }
+
+            if let Some(observer) = observer.as_mut() {
+                observer.on_body_chunk(received.len() - header_end, content_length);
+            }
+        }
+
+        let response = parse_response(&received, expects_body)?;
+        if let Some(observer) = observer.as_mut() {
+            observer.on_complete(&response);
+        }
+
+        Ok(response)
+    }
+}
+
+fn parse_response(received: &[u8], expects_body: bool) -> Result<HttpResponse, Error> {
+    match core::str::from_utf8(received) {
+        Ok(response) => {
+            if expects_body {
+                HttpResponse::new(response.to_string())
+            } else {
+                HttpResponse::new_without_body(response.to_string())
+            }
         }
+        Err(e) => Err(Error::Network(format!("Invalid received response: {}", e)))
+    }
+}
 
-        match core::str::from_utf8(&received) {
-            Ok(response) => HttpResponse::new(response.to_string()),
-            Err(e) => Err(Error::Network(format!("Invalid received response: {}", e)))
+/// `start` 以降で、ヘッダの終わりを示す空行の直後の位置を返す
+fn find_header_end(received: &[u8], start: usize) -> Option<usize> {
+    for i in start..received.len() {
+        if received[i..].starts_with(b"\r\n\r\n") {
+            return Some(i + 4);
+        }
+        if received[i..].starts_with(b"\n\n") {
+            return Some(i + 2);
         }
     }
+
+    None
+}
+
+fn parse_status_code(header: &[u8]) -> u32 {
+    let status_line = match core::str::from_utf8(header) {
+        Ok(h) => h.lines().next().unwrap_or(""),
+        Err(_) => return 0,
+    };
+
+    status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or(0)
 }
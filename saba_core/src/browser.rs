@@ -1,3 +1,4 @@
+use crate::navigator::Navigator;
 use crate::renderer::page::Page;
 use alloc::rc::Rc;
 use alloc::vec::Vec;
@@ -7,6 +8,7 @@ use core::cell::RefCell;
 pub struct Browser {
     active_page_index: usize,
     pages: Vec<Rc<RefCell<Page>>>,
+    navigator: Navigator,
 }
 
 impl Browser {
@@ -16,6 +18,7 @@ impl Browser {
         let browser = Rc::new(RefCell::new(Self {
             active_page_index: 0,
             pages: Vec::new(),
+            navigator: Navigator::default(),
         }));
 
         page.set_browser(Rc::downgrade(&browser));
@@ -27,4 +30,17 @@ impl Browser {
     pub fn current_page(&self) -> Rc<RefCell<Page>> {
         self.pages[self.active_page_index].clone()
     }
+
+    pub fn navigator(&self) -> Navigator {
+        self.navigator.clone()
+    }
+
+    pub fn set_navigator(&mut self, navigator: Navigator) {
+        self.navigator = navigator;
+    }
+
+    /// ネットワークへの接続状態が変わったときに呼ぶ
+    pub fn set_on_line(&mut self, on_line: bool) {
+        self.navigator.set_on_line(on_line);
+    }
 }
@@ -0,0 +1,190 @@
+use crate::error::Error;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// 名前解決で得たアドレスの種類
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+/// 名前解決で得た1つのアドレス。`ttl` はこのレコードをキャッシュしてよい時間
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRecord<A> {
+    pub addr: A,
+    pub family: AddressFamily,
+    pub ttl: u64,
+}
+
+/// ホスト名をIPアドレスのレコードの一覧に解決する。実際の名前解決はOS側が実装する
+pub trait DnsResolver {
+    type Addr: Clone;
+
+    fn lookup(&self, host: &str) -> Result<Vec<DnsRecord<Self::Addr>>, Error>;
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry<A> {
+    host: String,
+    addrs: Vec<A>,
+    expires_at: u64,
+}
+
+/// 名前解決の結果を、レコードのTTLのうち最も短い間だけキャッシュする
+#[derive(Debug, Clone)]
+pub struct CachedDnsResolver<R: DnsResolver> {
+    resolver: R,
+    /// TTLの上限。これより長いTTLのレコードもこの時間で期限切れにする
+    max_ttl: u64,
+    entries: Vec<CacheEntry<R::Addr>>,
+}
+
+impl<R: DnsResolver> CachedDnsResolver<R> {
+    pub fn new(resolver: R, max_ttl: u64) -> Self {
+        Self {
+            resolver,
+            max_ttl,
+            entries: Vec::new(),
+        }
+    }
+
+    /// 接続を試す順に並べたアドレスを返す。`now` はTTLと同じ単位で表した現在時刻
+    pub fn resolve(&mut self, host: &str, now: u64) -> Result<Vec<R::Addr>, Error> {
+        self.entries.retain(|e| e.expires_at > now);
+
+        if let Some(entry) = self.entries.iter().find(|e| e.host == host) {
+            return Ok(entry.addrs.clone());
+        }
+
+        let records = self.resolver.lookup(host)?;
+        let ttl = match records.iter().map(|r| r.ttl).min() {
+            Some(ttl) => ttl.min(self.max_ttl),
+            None => return Err(Error::Network("Failed to find IP addresses".to_string())),
+        };
+        let addrs = sort_addresses(records);
+
+        // TTLが0のレコードはキャッシュしない
+        if ttl > 0 {
+            self.entries.push(CacheEntry {
+                host: host.to_string(),
+                addrs: addrs.clone(),
+                expires_at: now.saturating_add(ttl),
+            });
+        }
+
+        Ok(addrs)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc8305#section-4
+/// Happy Eyeballsの順序で並べる。IPv6から始めてIPv4と交互にし、それぞれの中では元の順序を保つ
+pub fn sort_addresses<A>(records: Vec<DnsRecord<A>>) -> Vec<A> {
+    let (ipv6, ipv4): (Vec<DnsRecord<A>>, Vec<DnsRecord<A>>) =
+        records.into_iter().partition(|r| r.family == AddressFamily::Ipv6);
+    let mut ipv6 = ipv6.into_iter();
+    let mut ipv4 = ipv4.into_iter();
+
+    let mut addrs = Vec::new();
+    loop {
+        let (a, b) = (ipv6.next(), ipv4.next());
+        if a.is_none() && b.is_none() {
+            return addrs;
+        }
+        addrs.extend(a.map(|r| r.addr));
+        addrs.extend(b.map(|r| r.addr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use core::cell::Cell;
+
+    struct FakeResolver {
+        lookups: Cell<usize>,
+    }
+
+    fn record(addr: u8, family: AddressFamily, ttl: u64) -> DnsRecord<u8> {
+        DnsRecord { addr, family, ttl }
+    }
+
+    impl DnsResolver for FakeResolver {
+        type Addr = u8;
+
+        fn lookup(&self, host: &str) -> Result<Vec<DnsRecord<Self::Addr>>, Error> {
+            self.lookups.set(self.lookups.get() + 1);
+            match host {
+                "example.com" => Ok(vec![record(1, AddressFamily::Ipv4, 30)]),
+                "short.example" => Ok(vec![record(1, AddressFamily::Ipv4, 30), record(2, AddressFamily::Ipv4, 5)]),
+                "zero.example" => Ok(vec![record(1, AddressFamily::Ipv4, 0)]),
+                "dual.example" => Ok(vec![
+                    record(4, AddressFamily::Ipv4, 30),
+                    record(5, AddressFamily::Ipv4, 30),
+                    record(6, AddressFamily::Ipv6, 30),
+                    record(7, AddressFamily::Ipv6, 30),
+                    record(8, AddressFamily::Ipv6, 30),
+                ]),
+                "empty.example" => Ok(Vec::new()),
+                _ => Err(Error::Network("unknown host".to_string())),
+            }
+        }
+    }
+
+    fn resolver() -> CachedDnsResolver<FakeResolver> {
+        CachedDnsResolver::new(FakeResolver { lookups: Cell::new(0) }, 10)
+    }
+
+    #[test]
+    fn test_cache_hit() {
+        let mut r = resolver();
+        assert_eq!(Ok(vec![1]), r.resolve("example.com", 0));
+        assert_eq!(Ok(vec![1]), r.resolve("example.com", 9));
+        assert_eq!(1, r.resolver.lookups.get());
+    }
+
+    #[test]
+    fn test_ttl_expired() {
+        let mut r = resolver();
+        // レコードのTTLは30だが、上限の10で期限切れになる
+        assert!(r.resolve("example.com", 0).is_ok());
+        assert!(r.resolve("example.com", 10).is_ok());
+        assert_eq!(2, r.resolver.lookups.get());
+    }
+
+    #[test]
+    fn test_record_ttl() {
+        let mut r = resolver();
+        // 最も短いレコードのTTLで期限切れになる
+        assert!(r.resolve("short.example", 0).is_ok());
+        assert!(r.resolve("short.example", 4).is_ok());
+        assert_eq!(1, r.resolver.lookups.get());
+        assert!(r.resolve("short.example", 5).is_ok());
+        assert_eq!(2, r.resolver.lookups.get());
+
+        assert!(r.resolve("zero.example", 0).is_ok());
+        assert!(r.resolve("zero.example", 0).is_ok());
+        assert_eq!(4, r.resolver.lookups.get());
+    }
+
+    #[test]
+    fn test_happy_eyeballs_order() {
+        let mut r = resolver();
+        assert_eq!(Ok(vec![6, 4, 7, 5, 8]), r.resolve("dual.example", 0));
+        assert_eq!(Ok(vec![6, 4, 7, 5, 8]), r.resolve("dual.example", 1));
+    }
+
+    #[test]
+    fn test_failure_is_not_cached() {
+        let mut r = resolver();
+        assert!(r.resolve("unknown.example", 0).is_err());
+        assert!(r.resolve("empty.example", 0).is_err());
+        assert!(r.resolve("unknown.example", 1).is_err());
+        assert_eq!(3, r.resolver.lookups.get());
+    }
+}
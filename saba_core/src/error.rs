@@ -5,5 +5,7 @@ pub enum Error {
     Network(String),
     UnexpectedInput(String),
     InvalidUI(String),
+    Cancelled,
+    QuotaExceeded,
     Other(String),
 }
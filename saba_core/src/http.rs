@@ -1,7 +1,100 @@
 use crate::error::Error;
 use alloc::format;
+use alloc::rc::Rc;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
+use core::cell::Cell;
+
+/// https://www.rfc-editor.org/rfc/rfc9112#section-3.2
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RequestTarget {
+    /// `GET /index.html HTTP/1.1`
+    OriginForm,
+    /// `GET http://example.com/index.html HTTP/1.1`。プロキシに送る場合に使う
+    AbsoluteForm,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    method: String,
+    host: String,
+    port: u16,
+    path: String,
+    target: RequestTarget,
+    host_override: Option<String>,
+    headers: Vec<Header>,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, host: &str, port: u16, path: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            target: RequestTarget::OriginForm,
+            host_override: None,
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn method(&self) -> String {
+        self.method.clone()
+    }
+
+    pub fn host(&self) -> String {
+        self.host.clone()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn set_target(&mut self, target: RequestTarget) {
+        self.target = target;
+    }
+
+    /// 接続先とは異なる値を Host ヘッダに使う
+    pub fn set_host_override(&mut self, host: String) {
+        self.host_override = Some(host);
+    }
+
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.push(Header::new(name.to_string(), value.to_string()));
+    }
+
+    pub fn serialize(&self) -> String {
+        // パスが空の場合でもリクエストターゲットは必ず "/" から始める
+        let path = if self.path.starts_with('/') {
+            self.path.clone()
+        } else {
+            format!("/{}", self.path)
+        };
+
+        let authority = if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        };
+
+        let target = match self.target {
+            RequestTarget::OriginForm => path,
+            RequestTarget::AbsoluteForm => format!("http://{}{}", authority, path),
+        };
+
+        let mut request = format!("{} {} HTTP/1.1\n", self.method, target);
+        request.push_str(&format!(
+            "Host: {}\n",
+            self.host_override.as_ref().unwrap_or(&authority)
+        ));
+        for header in &self.headers {
+            request.push_str(&format!("{}: {}\n", header.name, header.value));
+        }
+        request.push('\n');
+
+        request
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct HttpResponse {
@@ -14,6 +107,15 @@ pub struct HttpResponse {
 
 impl HttpResponse {
     pub fn new(raw_response: String) -> Result<Self, Error> {
+        Self::parse(raw_response, true)
+    }
+
+    /// HEADリクエストに対するレスポンスはヘッダにかかわらずボディを持たない
+    pub fn new_without_body(raw_response: String) -> Result<Self, Error> {
+        Self::parse(raw_response, false)
+    }
+
+    fn parse(raw_response: String, expects_body: bool) -> Result<Self, Error> {
         let preprocessed_response = raw_response.trim_start().replace("\n\r", "\n");
 
         let (status_line, remaining) = match preprocessed_response.split_once("\n") {
@@ -21,28 +123,36 @@ impl HttpResponse {
             None => { return Err(Error::Network(format!("Invalid HTTP response: {}", preprocessed_response))) }
         };
 
-        let (headers, body) = match remaining.split_once("\n\n") {
-            Some((h, b)) => {
-                let mut headers = Vec::new();
-                for header in h.split("\n") {
-                    let splitted: Vec<&str> = header.splitn(2, ":").collect();
-                    headers.push(Header::new(
-                        splitted[0].trim().to_string(),
-                        splitted[1].trim().to_string(),
-                    ))
+        let statuses: Vec<&str> = status_line.trim_end().splitn(3, " ").collect();
+        if statuses.len() < 2 {
+            return Err(Error::Network(format!("Invalid HTTP status line: {}", status_line)));
+        }
+        let status_code = statuses[1].parse().unwrap_or(404);
+
+        // 途中経過のレスポンスの後に最終的なレスポンスが続いていれば、そちらを使う
+        if is_interim_status(status_code) {
+            if let Some((_, rest)) = remaining.split_once("\n\n") {
+                if !rest.trim().is_empty() {
+                    return Self::parse(rest.to_string(), expects_body);
                 }
-                (headers, b)
             }
+        }
+
+        let expects_body = expects_body && !is_body_less_status(status_code);
+
+        let (headers, body) = match remaining.split_once("\n\n") {
+            Some((h, b)) => (parse_headers(h), b),
+            // ボディを持たないレスポンスは空行で終わっていなくてもヘッダとして扱う
+            None if !expects_body => (parse_headers(remaining), ""),
             None => (Vec::new(), remaining),
         };
-        let statuses: Vec<&str> = status_line.split(" ").collect();
 
         Ok(Self {
             version: statuses[0].to_string(),
-            status_code: statuses[1].parse().unwrap_or(404),
-            reason: statuses[2].to_string(),
+            status_code,
+            reason: statuses.get(2).unwrap_or(&"").to_string(),
             headers,
-            body: body.to_string(),
+            body: if expects_body { body.to_string() } else { String::new() },
         })
     }
 
@@ -55,6 +165,72 @@ impl HttpResponse {
 
         Err(format!("Failed to find {} in headers", name))
     }
+
+    pub fn content_length(&self) -> Option<usize> {
+        self.header_value("Content-Length")
+            .ok()
+            .and_then(|v| v.parse().ok())
+    }
+}
+
+/// 通信の進捗を受け取る。遅いページや大きな画像の読み込み中にプログレスバーを表示するために使う
+pub trait FetchObserver {
+    /// ステータスラインとヘッダを受け取ったときに呼ばれる。`response.body` は常に空
+    fn on_headers(&mut self, _response: &HttpResponse) {}
+
+    /// ボディを受け取るたびに呼ばれる。`total` は Content-Length ヘッダがあればその値
+    fn on_body_chunk(&mut self, _received: usize, _total: Option<usize>) {}
+
+    fn on_complete(&mut self, _response: &HttpResponse) {}
+}
+
+/// 読み込みを中断するためのトークン。クローンしたトークン同士は状態を共有する
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self {
+            cancelled: Rc::new(Cell::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9110#section-6.4.1
+/// 1xx、204、304のレスポンスはボディを持たない
+pub fn is_body_less_status(status_code: u32) -> bool {
+    (100..200).contains(&status_code) || status_code == 204 || status_code == 304
+}
+
+/// https://www.rfc-editor.org/rfc/rfc9110#section-15.2
+/// 101以外の1xxは途中経過のレスポンスで、同じ接続で最終的なレスポンスが続く
+pub fn is_interim_status(status_code: u32) -> bool {
+    (100..200).contains(&status_code) && status_code != 101
+}
+
+fn parse_headers(raw_headers: &str) -> Vec<Header> {
+    let mut headers = Vec::new();
+    for header in raw_headers.split("\n") {
+        let splitted: Vec<&str> = header.splitn(2, ":").collect();
+        if splitted.len() < 2 {
+            continue;
+        }
+        headers.push(Header::new(
+            splitted[0].trim().to_string(),
+            splitted[1].trim().to_string(),
+        ))
+    }
+    headers
 }
 
 #[derive(Debug, Clone)]
@@ -72,6 +248,43 @@ impl Header {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn test_request_empty_path() {
+        let request = HttpRequest::new("GET", "example.com", 80, "");
+        assert_eq!("GET / HTTP/1.1\nHost: example.com\n\n", request.serialize());
+    }
+
+    #[test]
+    fn test_request_headers_and_port() {
+        let mut request = HttpRequest::new("HEAD", "example.com", 8000, "index.html");
+        request.add_header("Accept", "text/html");
+        assert_eq!(
+            "HEAD /index.html HTTP/1.1\nHost: example.com:8000\nAccept: text/html\n\n",
+            request.serialize()
+        );
+    }
+
+    #[test]
+    fn test_request_absolute_form_and_host_override() {
+        let mut request = HttpRequest::new("GET", "proxy.example", 80, "/index.html");
+        request.set_target(RequestTarget::AbsoluteForm);
+        request.set_host_override("example.com".to_string());
+        assert_eq!(
+            "GET http://proxy.example/index.html HTTP/1.1\nHost: example.com\n\n",
+            request.serialize()
+        );
+    }
+
+    #[test]
+    fn test_cancel_token() {
+        let token = CancelToken::new();
+        let cloned = token.clone();
+        assert!(!cloned.is_cancelled());
+
+        token.cancel();
+        assert!(cloned.is_cancelled());
+    }
+
     #[test]
     fn test_status_line_only() {
         let raw = "HTTP/1.1 200 OK\n\n".to_string();
@@ -120,6 +333,61 @@ mod tests {
         assert_eq!("body message".to_string(), res.body);
     }
 
+    #[test]
+    fn test_reason_with_white_space() {
+        let raw = "HTTP/1.1 404 Not Found\n\n".to_string();
+        let res = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+
+        assert_eq!(404, res.status_code);
+        assert_eq!("Not Found", res.reason);
+    }
+
+    #[test]
+    fn test_no_content() {
+        let raw = "HTTP/1.1 204 No Content\nDate: xx xx xx\n\nunexpected".to_string();
+        let res = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+
+        assert_eq!(204, res.status_code);
+        assert_eq!("No Content", res.reason);
+        assert_eq!(Ok("xx xx xx".to_string()), res.header_value("Date"));
+        assert_eq!("".to_string(), res.body);
+    }
+
+    #[test]
+    fn test_not_modified_without_blank_line() {
+        let raw = "HTTP/1.1 304 Not Modified\nETag: \"abc\"\n".to_string();
+        let res = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+
+        assert_eq!(304, res.status_code);
+        assert_eq!(Ok("\"abc\"".to_string()), res.header_value("ETag"));
+        assert_eq!("".to_string(), res.body);
+    }
+
+    #[test]
+    fn test_interim_response() {
+        let raw = "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\n\
+                   HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nbody"
+            .to_string();
+        let res = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+
+        assert_eq!(200, res.status_code);
+        assert_eq!(Some(4), res.content_length());
+        assert_eq!("body".to_string(), res.body);
+        assert!(is_interim_status(100));
+        assert!(!is_interim_status(101));
+    }
+
+    #[test]
+    fn test_head_response() {
+        let raw = "HTTP/1.1 200 OK\nContent-Length: 42\n\n".to_string();
+        let res = HttpResponse::new_without_body(raw).expect("Failed to parse HTTP response");
+
+        assert_eq!(200, res.status_code);
+        assert_eq!(Ok("42".to_string()), res.header_value("Content-Length"));
+        assert_eq!(Some(42), res.content_length());
+        assert_eq!("".to_string(), res.body);
+    }
+
     #[test]
     fn test_invalid() {
         let raw = "HTTP/1.1 200 OK".to_string();
@@ -4,7 +4,10 @@ extern crate alloc;
 
 pub mod url;
 pub mod http;
+pub mod dns;
 pub mod renderer;
 pub mod error;
 pub mod browser;
-mod utils;
+pub mod navigator;
+pub mod storage;
+pub mod utils;
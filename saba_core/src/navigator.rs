@@ -0,0 +1,59 @@
+use alloc::string::{String, ToString};
+
+const DEFAULT_USER_AGENT: &str = concat!("saba/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_LANGUAGE: &str = "en-US";
+
+/// https://html.spec.whatwg.org/multipage/system-state.html#the-navigator-object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Navigator {
+    user_agent: String,
+    language: String,
+    on_line: bool,
+}
+
+impl Navigator {
+    pub fn new(user_agent: String, language: String) -> Self {
+        Self {
+            user_agent,
+            language,
+            on_line: true,
+        }
+    }
+
+    pub fn user_agent(&self) -> String {
+        self.user_agent.clone()
+    }
+
+    pub fn language(&self) -> String {
+        self.language.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/system-state.html#dom-navigator-online
+    pub fn on_line(&self) -> bool {
+        self.on_line
+    }
+
+    /// ネットワークに接続できるかどうかは、ブラウザを組み込む側が設定する
+    pub fn set_on_line(&mut self, on_line: bool) {
+        self.on_line = on_line;
+    }
+}
+
+impl Default for Navigator {
+    fn default() -> Self {
+        Self::new(DEFAULT_USER_AGENT.to_string(), DEFAULT_LANGUAGE.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let navigator = Navigator::default();
+        assert_eq!("saba/0.1.0", navigator.user_agent());
+        assert_eq!("en-US", navigator.language());
+        assert!(navigator.on_line());
+    }
+}
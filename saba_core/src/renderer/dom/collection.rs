@@ -0,0 +1,228 @@
+use crate::error::Error;
+use crate::renderer::dom::node::Node;
+use crate::renderer::html::attribute::Attribute;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::vec::{IntoIter, Vec};
+use core::cell::RefCell;
+use core::ops::Index;
+use core::slice::Iter;
+
+/// https://dom.spec.whatwg.org/#interface-nodelist
+/// 作った時点のノードを保持する。DOMツリーを変更しても中身は変わらない
+#[derive(Debug, Clone, Default)]
+pub struct NodeList {
+    nodes: Vec<Rc<RefCell<Node>>>,
+}
+
+impl NodeList {
+    pub fn new(nodes: Vec<Rc<RefCell<Node>>>) -> Self {
+        Self { nodes }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-nodelist-length
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-nodelist-item
+    pub fn item(&self, index: usize) -> Option<Rc<RefCell<Node>>> {
+        self.nodes.get(index).cloned()
+    }
+
+    pub fn iter(&self) -> Iter<'_, Rc<RefCell<Node>>> {
+        self.nodes.iter()
+    }
+}
+
+impl Index<usize> for NodeList {
+    type Output = Rc<RefCell<Node>>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.nodes[index]
+    }
+}
+
+impl IntoIterator for NodeList {
+    type Item = Rc<RefCell<Node>>;
+    type IntoIter = IntoIter<Rc<RefCell<Node>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.nodes.into_iter()
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-htmlcollection
+/// 要素だけを保持する。`NodeList` と同じく、作った時点の要素を保持する
+#[derive(Debug, Clone, Default)]
+pub struct HtmlCollection {
+    elements: Vec<Rc<RefCell<Node>>>,
+}
+
+impl HtmlCollection {
+    /// 要素でないノードは取り除く
+    pub fn new(nodes: Vec<Rc<RefCell<Node>>>) -> Self {
+        Self {
+            elements: nodes.into_iter().filter(|n| n.borrow().get_element().is_some()).collect(),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-htmlcollection-length
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-htmlcollection-item
+    pub fn item(&self, index: usize) -> Option<Rc<RefCell<Node>>> {
+        self.elements.get(index).cloned()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-htmlcollection-nameditem
+    /// id属性、またはname属性が `name` と一致する最初の要素を返す
+    pub fn named_item(&self, name: &str) -> Option<Rc<RefCell<Node>>> {
+        if name.is_empty() {
+            return None;
+        }
+
+        self.elements
+            .iter()
+            .find(|node| {
+                let element = match node.borrow().get_element() {
+                    Some(element) => element,
+                    None => return false,
+                };
+                element.id().as_deref() == Some(name) || element.get_attribute("name").as_deref() == Some(name)
+            })
+            .cloned()
+    }
+
+    pub fn iter(&self) -> Iter<'_, Rc<RefCell<Node>>> {
+        self.elements.iter()
+    }
+}
+
+impl Index<usize> for HtmlCollection {
+    type Output = Rc<RefCell<Node>>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.elements[index]
+    }
+}
+
+impl IntoIterator for HtmlCollection {
+    type Item = Rc<RefCell<Node>>;
+    type IntoIter = IntoIter<Rc<RefCell<Node>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-namednodemap
+/// 要素の属性を、HTMLに書かれた順序のまま保持する。同じ名前の属性は1つしか持たない
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NamedNodeMap {
+    attributes: Vec<Attribute>,
+}
+
+impl NamedNodeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-namednodemap-length
+    pub fn len(&self) -> usize {
+        self.attributes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-namednodemap-item
+    pub fn item(&self, index: usize) -> Option<Attribute> {
+        self.attributes.get(index).cloned()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-namednodemap-getnameditem
+    /// 名前は正規化せずにそのまま比べる
+    pub fn get_named_item(&self, name: &str) -> Option<Attribute> {
+        self.attributes.iter().find(|a| a.name() == name).cloned()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-namednodemap-setnameditem
+    /// 同じ名前の属性があればその位置で置き換えて古い属性を返し、なければ末尾に追加する
+    pub fn set_named_item(&mut self, attribute: Attribute) -> Option<Attribute> {
+        match self.attributes.iter_mut().find(|a| a.name() == attribute.name()) {
+            Some(old) => Some(core::mem::replace(old, attribute)),
+            None => {
+                self.attributes.push(attribute);
+                None
+            }
+        }
+    }
+
+    /// 末尾に追加する。同じ名前の属性がすでにあればエラーを返し、何も変えない
+    pub fn append(&mut self, attribute: Attribute) -> Result<(), Error> {
+        if self.get_named_item(&attribute.name()).is_some() {
+            return Err(Error::UnexpectedInput(format!(
+                "InUseAttributeError: {} is already present",
+                attribute.name()
+            )));
+        }
+        self.attributes.push(attribute);
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-namednodemap-removenameditem
+    /// 取り除いた属性を返す。残りの属性の順序は変わらない
+    pub fn remove_named_item(&mut self, name: &str) -> Option<Attribute> {
+        let index = self.attributes.iter().position(|a| a.name() == name)?;
+        Some(self.attributes.remove(index))
+    }
+
+    pub fn iter(&self) -> Iter<'_, Attribute> {
+        self.attributes.iter()
+    }
+
+    pub fn to_vec(&self) -> Vec<Attribute> {
+        self.attributes.clone()
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#attribute-name-state
+/// 構文解析と同じく、同じ名前の属性は最初のものだけを残す
+impl From<Vec<Attribute>> for NamedNodeMap {
+    fn from(attributes: Vec<Attribute>) -> Self {
+        let mut map = Self::new();
+        for attribute in attributes {
+            let _ = map.append(attribute);
+        }
+        map
+    }
+}
+
+impl Index<usize> for NamedNodeMap {
+    type Output = Attribute;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.attributes[index]
+    }
+}
+
+impl IntoIterator for NamedNodeMap {
+    type Item = Attribute;
+    type IntoIter = IntoIter<Attribute>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.attributes.into_iter()
+    }
+}
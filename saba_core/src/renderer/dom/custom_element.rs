@@ -0,0 +1,295 @@
+use crate::error::Error;
+use crate::renderer::dom::node::{is_valid_custom_element_name, Node};
+use alloc::format;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+type CustomElementCallback = dyn Fn(&Rc<RefCell<Node>>);
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#concept-custom-element-definition-lifecycle-callbacks
+/// カスタム要素の各時点で呼び出す関数。設定しなかった時点では何もしない
+#[derive(Clone, Default)]
+pub struct CustomElementHooks {
+    upgrade: Option<Rc<CustomElementCallback>>,
+    connected: Option<Rc<CustomElementCallback>>,
+    disconnected: Option<Rc<CustomElementCallback>>,
+}
+
+impl fmt::Debug for CustomElementHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomElementHooks")
+            .field("upgrade", &self.upgrade.is_some())
+            .field("connected", &self.connected.is_some())
+            .field("disconnected", &self.disconnected.is_some())
+            .finish()
+    }
+}
+
+impl CustomElementHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#concept-upgrade-an-element
+    /// 要素が定義に結びついたときに、一度だけ呼び出される
+    pub fn set_upgrade(&mut self, callback: impl Fn(&Rc<RefCell<Node>>) + 'static) {
+        self.upgrade = Some(Rc::new(callback));
+    }
+
+    /// 要素が文書に挿入されるたびに呼び出される
+    pub fn set_connected(&mut self, callback: impl Fn(&Rc<RefCell<Node>>) + 'static) {
+        self.connected = Some(Rc::new(callback));
+    }
+
+    /// 要素が文書から取り除かれるたびに呼び出される
+    pub fn set_disconnected(&mut self, callback: impl Fn(&Rc<RefCell<Node>>) + 'static) {
+        self.disconnected = Some(Rc::new(callback));
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#custom-element-definition
+#[derive(Debug, Clone)]
+struct CustomElementDefinition {
+    name: String,
+    hooks: CustomElementHooks,
+}
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#customelementregistry
+/// `Window` と文書で共有する
+#[derive(Debug, Clone, Default)]
+pub struct CustomElementRegistry {
+    definitions: Rc<RefCell<Vec<CustomElementDefinition>>>,
+    document: Weak<RefCell<Node>>,
+}
+
+impl CustomElementRegistry {
+    pub fn new(document: Weak<RefCell<Node>>) -> Self {
+        Self {
+            definitions: Rc::new(RefCell::new(Vec::new())),
+            document,
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#dom-customelementregistry-define
+    /// 定義した時点で文書にある同じ名前の要素は、文書順にアップグレードする
+    pub fn define(&self, name: &str, hooks: CustomElementHooks) -> Result<(), Error> {
+        if !is_valid_custom_element_name(name) {
+            return Err(Error::UnexpectedInput(format!(
+                "SyntaxError: {} is not a valid custom element name",
+                name
+            )));
+        }
+        if self.get(name).is_some() {
+            return Err(Error::UnexpectedInput(format!("NotSupportedError: {} is already defined", name)));
+        }
+
+        self.definitions.borrow_mut().push(CustomElementDefinition {
+            name: name.to_string(),
+            hooks,
+        });
+        if let Some(document) = self.document.upgrade() {
+            self.upgrade(&document);
+        }
+        Ok(())
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#dom-customelementregistry-get
+    pub fn get(&self, name: &str) -> Option<CustomElementHooks> {
+        self.definitions
+            .borrow()
+            .iter()
+            .find(|definition| definition.name == name)
+            .map(|definition| definition.hooks.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.borrow().is_empty()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#dom-customelementregistry-upgrade
+    /// `root` とその子孫のうち、定義のあるカスタム要素をアップグレードする
+    pub fn upgrade(&self, root: &Rc<RefCell<Node>>) {
+        for node in inclusive_descendants(root) {
+            self.try_upgrade(&node);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#concept-try-upgrade
+    fn try_upgrade(&self, node: &Rc<RefCell<Node>>) {
+        if node.borrow().is_defined() {
+            return;
+        }
+        let name = match node.borrow().get_element() {
+            Some(element) => element.local_name(),
+            None => return,
+        };
+        let hooks = match self.get(&name) {
+            Some(hooks) => hooks,
+            None => return,
+        };
+
+        node.borrow_mut().set_custom_element_upgraded(true);
+        if let Some(upgrade) = hooks.upgrade {
+            upgrade(node);
+        }
+        if node.borrow().is_connected() {
+            if let Some(connected) = hooks.connected {
+                connected(node);
+            }
+        }
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#concept-custom-element-reaction
+/// 文書に挿入された `node` とその子孫のカスタム要素をアップグレードするか、connectedの関数を呼び出す
+pub fn notify_connected(node: &Rc<RefCell<Node>>) {
+    let registry = match registry_of(node) {
+        Some(registry) => registry,
+        None => return,
+    };
+    if !node.borrow().is_connected() {
+        return;
+    }
+
+    for n in inclusive_descendants(node) {
+        if !n.borrow().is_defined() {
+            registry.try_upgrade(&n);
+            continue;
+        }
+        if let Some(connected) = hooks_of(&registry, &n).and_then(|hooks| hooks.connected) {
+            connected(&n);
+        }
+    }
+}
+
+/// 文書から取り除かれた `node` とその子孫のカスタム要素について、disconnectedの関数を呼び出す
+pub fn notify_disconnected(node: &Rc<RefCell<Node>>, registry: &CustomElementRegistry) {
+    for n in inclusive_descendants(node) {
+        if let Some(disconnected) = hooks_of(registry, &n).and_then(|hooks| hooks.disconnected) {
+            disconnected(&n);
+        }
+    }
+}
+
+/// `node` の文書のレジストリ。定義が1つもなければ、調べる必要がないのでNoneを返す
+pub fn registry_of(node: &Rc<RefCell<Node>>) -> Option<CustomElementRegistry> {
+    let document = match node.borrow().owner_document() {
+        Some(document) => document,
+        None => node.clone(),
+    };
+    let registry = document.borrow().custom_element_registry()?;
+    if registry.is_empty() {
+        return None;
+    }
+    Some(registry)
+}
+
+/// アップグレード済みのカスタム要素であれば、その定義の関数を返す
+fn hooks_of(registry: &CustomElementRegistry, node: &Rc<RefCell<Node>>) -> Option<CustomElementHooks> {
+    let element = node.borrow().get_element()?;
+    if !element.is_custom() || !node.borrow().is_defined() {
+        return None;
+    }
+    registry.get(&element.local_name())
+}
+
+/// 関数の中で木が変わってもよいように、先に集めておく
+fn inclusive_descendants(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    let mut nodes = Vec::from([node.clone()]);
+    nodes.extend(node.borrow().descendants());
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{append_child, remove_child, set_attribute, Element, NodeKind};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::vec;
+
+    fn logging_hooks(log: &Rc<RefCell<Vec<String>>>) -> CustomElementHooks {
+        let mut hooks = CustomElementHooks::new();
+        let upgrade_log = log.clone();
+        hooks.set_upgrade(move |node| {
+            let id = node.borrow().get_element().and_then(|e| e.id()).unwrap_or_default();
+            upgrade_log.borrow_mut().push(format!("upgrade {}", id));
+            set_attribute(node, "upgraded", "");
+        });
+        let connected_log = log.clone();
+        hooks.set_connected(move |node| {
+            let id = node.borrow().get_element().and_then(|e| e.id()).unwrap_or_default();
+            connected_log.borrow_mut().push(format!("connected {}", id));
+        });
+        let disconnected_log = log.clone();
+        hooks.set_disconnected(move |node| {
+            let id = node.borrow().get_element().and_then(|e| e.id()).unwrap_or_default();
+            disconnected_log.borrow_mut().push(format!("disconnected {}", id));
+        });
+        hooks
+    }
+
+    #[test]
+    fn test_define_upgrades_parsed_elements() {
+        let html = "<html><head></head><body><my-widget id=a><my-widget id=b></my-widget></my-widget><other-widget id=c></other-widget></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let registry = window.borrow().custom_elements();
+        let widgets: Vec<Rc<RefCell<Node>>> = window
+            .borrow()
+            .document()
+            .borrow()
+            .descendants()
+            .filter(|n| n.borrow().get_element().is_some_and(|e| e.is_custom()))
+            .collect();
+        assert!(widgets.iter().all(|w| !w.borrow().is_defined()));
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        registry.define("my-widget", logging_hooks(&log)).expect("Failed to define a custom element");
+        assert_eq!(vec!["upgrade a", "connected a", "upgrade b", "connected b"], *log.borrow());
+        assert!(widgets[0].borrow().get_element().expect("Failed to get an element").has_attribute("upgraded"));
+        assert!(widgets[1].borrow().is_defined());
+        assert!(!widgets[2].borrow().is_defined());
+
+        assert!(registry.get("my-widget").is_some());
+        assert!(registry.define("my-widget", CustomElementHooks::new()).is_err());
+        assert!(registry.define("widget", CustomElementHooks::new()).is_err());
+    }
+
+    #[test]
+    fn test_connected_and_disconnected() {
+        let html = "<html><head></head><body><div></div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        window
+            .borrow()
+            .custom_elements()
+            .define("my-widget", logging_hooks(&log))
+            .expect("Failed to define a custom element");
+
+        let body = window.borrow().body().expect("Failed to get body");
+        let mut element = Element::new("my-widget", Vec::new());
+        element.set_attribute("id", "x");
+        let widget = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+
+        // 文書の外では何も呼び出さない
+        let div = Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("div", Vec::new())))));
+        append_child(&div, &widget).expect("Failed to append a child");
+        assert!(log.borrow().is_empty());
+
+        // 祖先と一緒に挿入されたときにアップグレードし、移動するたびにdisconnectedとconnectedを呼び出す
+        append_child(&body, &div).expect("Failed to append a child");
+        assert!(widget.borrow().is_connected());
+        append_child(&body, &widget).expect("Failed to append a child");
+        remove_child(&body, &div).expect("Failed to remove a child");
+        remove_child(&body, &widget).expect("Failed to remove a child");
+        assert_eq!(
+            vec!["upgrade x", "connected x", "disconnected x", "connected x", "disconnected x"],
+            *log.borrow()
+        );
+        assert!(!widget.borrow().is_connected());
+        assert!(widget.borrow().is_defined());
+    }
+}
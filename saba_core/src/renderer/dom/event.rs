@@ -0,0 +1,295 @@
+use crate::renderer::dom::node::Node;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+
+/// https://dom.spec.whatwg.org/#dom-event-eventphase
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EventPhase {
+    None,
+    Capturing,
+    AtTarget,
+    Bubbling,
+}
+
+/// https://dom.spec.whatwg.org/#interface-event
+#[derive(Debug, Clone)]
+pub struct Event {
+    event_type: String,
+    bubbles: bool,
+    cancelable: bool,
+    target: Option<Rc<RefCell<Node>>>,
+    current_target: Option<Rc<RefCell<Node>>>,
+    phase: EventPhase,
+    stop_propagation: bool,
+    stop_immediate_propagation: bool,
+    canceled: bool,
+}
+
+impl Event {
+    pub fn new(event_type: &str, bubbles: bool, cancelable: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            bubbles,
+            cancelable,
+            target: None,
+            current_target: None,
+            phase: EventPhase::None,
+            stop_propagation: false,
+            stop_immediate_propagation: false,
+            canceled: false,
+        }
+    }
+
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    pub fn bubbles(&self) -> bool {
+        self.bubbles
+    }
+
+    pub fn cancelable(&self) -> bool {
+        self.cancelable
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-target
+    pub fn target(&self) -> Option<Rc<RefCell<Node>>> {
+        self.target.clone()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-currenttarget
+    /// 今呼び出しているリスナーが登録されたノード
+    pub fn current_target(&self) -> Option<Rc<RefCell<Node>>> {
+        self.current_target.clone()
+    }
+
+    pub fn phase(&self) -> EventPhase {
+        self.phase
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-stoppropagation
+    /// 今のノードのリスナーは最後まで呼び出すが、次のノードには伝えない
+    pub fn stop_propagation(&mut self) {
+        self.stop_propagation = true;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-stopimmediatepropagation
+    /// 同じノードに残っているリスナーも呼び出さない
+    pub fn stop_immediate_propagation(&mut self) {
+        self.stop_propagation = true;
+        self.stop_immediate_propagation = true;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-preventdefault
+    /// キャンセルできないイベントでは何もしない
+    pub fn prevent_default(&mut self) {
+        if self.cancelable {
+            self.canceled = true;
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-event-defaultprevented
+    pub fn default_prevented(&self) -> bool {
+        self.canceled
+    }
+}
+
+type EventCallback = dyn Fn(&mut Event);
+
+/// https://dom.spec.whatwg.org/#concept-event-listener
+#[derive(Clone)]
+pub struct EventListener {
+    event_type: String,
+    callback: Rc<EventCallback>,
+    capture: bool,
+}
+
+impl fmt::Debug for EventListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventListener")
+            .field("event_type", &self.event_type)
+            .field("capture", &self.capture)
+            .finish()
+    }
+}
+
+impl EventListener {
+    pub fn new(event_type: &str, callback: impl Fn(&mut Event) + 'static, capture: bool) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            callback: Rc::new(callback),
+            capture,
+        }
+    }
+
+    pub fn event_type(&self) -> String {
+        self.event_type.clone()
+    }
+
+    pub fn capture(&self) -> bool {
+        self.capture
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-event-dispatch
+/// 根から `target` の親まで捕捉フェーズ、`target` 自身、`target` の親から根まで浮上フェーズの順に
+/// リスナーを呼び出す。`prevent_default()` が呼ばれなければtrueを返す
+pub fn dispatch_event(target: &Rc<RefCell<Node>>, event: &mut Event) -> bool {
+    event.target = Some(target.clone());
+    event.stop_propagation = false;
+    event.stop_immediate_propagation = false;
+    event.canceled = false;
+
+    let ancestors: Vec<Rc<RefCell<Node>>> = target.borrow().ancestors().collect();
+
+    for node in ancestors.iter().rev() {
+        if event.stop_propagation {
+            break;
+        }
+        invoke(node, event, EventPhase::Capturing);
+    }
+
+    if !event.stop_propagation {
+        invoke(target, event, EventPhase::AtTarget);
+    }
+
+    if event.bubbles {
+        for node in ancestors.iter() {
+            if event.stop_propagation {
+                break;
+            }
+            invoke(node, event, EventPhase::Bubbling);
+        }
+    }
+
+    event.phase = EventPhase::None;
+    event.current_target = None;
+    !event.canceled
+}
+
+/// https://dom.spec.whatwg.org/#concept-event-listener-inner-invoke
+fn invoke(node: &Rc<RefCell<Node>>, event: &mut Event, phase: EventPhase) {
+    // リスナーの中でDOMを書き換えられるように、借用を手放してから呼び出す
+    let listeners: Vec<EventListener> = node
+        .borrow()
+        .event_listeners()
+        .into_iter()
+        .filter(|l| {
+            l.event_type == event.event_type
+                && match phase {
+                    EventPhase::Capturing => l.capture,
+                    EventPhase::Bubbling => !l.capture,
+                    _ => true,
+                }
+        })
+        .collect();
+
+    event.phase = phase;
+    event.current_target = Some(node.clone());
+    // 対象のノードでは、捕捉のリスナーを先に呼び出す
+    let (capturing, bubbling): (Vec<EventListener>, Vec<EventListener>) =
+        listeners.into_iter().partition(|l| l.capture);
+    for listener in capturing.iter().chain(bubbling.iter()) {
+        if event.stop_immediate_propagation {
+            break;
+        }
+        (listener.callback)(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{append_child, Element, NodeKind};
+    use alloc::format;
+    use alloc::vec;
+
+    fn element(name: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))))
+    }
+
+    fn listen(node: &Rc<RefCell<Node>>, name: &'static str, capture: bool, log: &Rc<RefCell<Vec<String>>>) {
+        let log = log.clone();
+        node.borrow_mut().add_event_listener(
+            "click",
+            move |event: &mut Event| log.borrow_mut().push(format!("{}:{:?}", name, event.phase())),
+            capture,
+        );
+    }
+
+    #[test]
+    fn test_propagation_order() {
+        let outer = element("div");
+        let inner = element("p");
+        append_child(&outer, &inner).expect("Failed to append a child");
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        listen(&outer, "outer", false, &log);
+        listen(&outer, "outer", true, &log);
+        listen(&inner, "inner", false, &log);
+        listen(&inner, "inner", true, &log);
+
+        let mut event = Event::new("click", true, true);
+        assert!(dispatch_event(&inner, &mut event));
+        assert_eq!(
+            vec![
+                "outer:Capturing",
+                "inner:AtTarget",
+                "inner:AtTarget",
+                "outer:Bubbling"
+            ],
+            *log.borrow()
+        );
+        assert!(Rc::ptr_eq(&inner, &event.target().expect("Failed to get a target")));
+
+        // 浮上しないイベントは親の浮上フェーズのリスナーを呼び出さない
+        log.borrow_mut().clear();
+        let mut event = Event::new("click", false, true);
+        dispatch_event(&inner, &mut event);
+        assert_eq!(vec!["outer:Capturing", "inner:AtTarget", "inner:AtTarget"], *log.borrow());
+
+        // 型が違うリスナーは呼び出さない
+        log.borrow_mut().clear();
+        dispatch_event(&inner, &mut Event::new("keydown", true, true));
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_stop_propagation_and_prevent_default() {
+        let outer = element("div");
+        let inner = element("a");
+        append_child(&outer, &inner).expect("Failed to append a child");
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        inner.borrow_mut().add_event_listener(
+            "click",
+            |event: &mut Event| {
+                event.stop_propagation();
+                event.prevent_default();
+            },
+            false,
+        );
+        listen(&inner, "inner", false, &log);
+        listen(&outer, "outer", false, &log);
+
+        let mut event = Event::new("click", true, true);
+        assert!(!dispatch_event(&inner, &mut event));
+        assert!(event.default_prevented());
+        // 同じノードのリスナーは呼び出すが、親には伝わらない
+        assert_eq!(vec!["inner:AtTarget"], *log.borrow());
+
+        // キャンセルできないイベントは取り消せない
+        let mut event = Event::new("click", true, false);
+        assert!(dispatch_event(&inner, &mut event));
+
+        let target = element("span");
+        let log = Rc::new(RefCell::new(Vec::new()));
+        target.borrow_mut().add_event_listener("click", |event: &mut Event| event.stop_immediate_propagation(), false);
+        listen(&target, "second", false, &log);
+        dispatch_event(&target, &mut Event::new("click", true, true));
+        assert!(log.borrow().is_empty());
+    }
+}
@@ -1 +1,8 @@
+pub mod collection;
+pub mod custom_element;
+pub mod event;
+pub mod mutation_observer;
 pub mod node;
+pub mod range;
+pub mod selector;
+pub mod tree_walker;
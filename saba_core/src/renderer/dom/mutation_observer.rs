@@ -0,0 +1,295 @@
+use crate::error::Error;
+use crate::renderer::dom::node::Node;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// https://dom.spec.whatwg.org/#dictdef-mutationobserverinit
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+    pub child_list: bool,
+    pub attributes: bool,
+    pub character_data: bool,
+    /// 対象のノードだけでなく、その子孫の変更も記録する
+    pub subtree: bool,
+    pub attribute_old_value: bool,
+    pub character_data_old_value: bool,
+}
+
+/// https://dom.spec.whatwg.org/#dom-mutationrecord-type
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MutationType {
+    ChildList,
+    Attributes,
+    CharacterData,
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationrecord
+#[derive(Debug, Clone)]
+pub struct MutationRecord {
+    pub mutation_type: MutationType,
+    /// 子が変わったノード、属性が変わった要素、または文字列が変わったノード
+    pub target: Rc<RefCell<Node>>,
+    pub added_nodes: Vec<Rc<RefCell<Node>>>,
+    pub removed_nodes: Vec<Rc<RefCell<Node>>>,
+    pub previous_sibling: Option<Rc<RefCell<Node>>>,
+    pub next_sibling: Option<Rc<RefCell<Node>>>,
+    pub attribute_name: Option<String>,
+    /// `attribute_old_value` または `character_data_old_value` を指定した場合だけ設定される
+    pub old_value: Option<String>,
+}
+
+impl MutationRecord {
+    fn new(mutation_type: MutationType, target: &Rc<RefCell<Node>>) -> Self {
+        Self {
+            mutation_type,
+            target: target.clone(),
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: None,
+            old_value: None,
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-mutationobserver
+/// 変更は記録として溜めておき、`take_records()` でまとめて受け取る
+#[derive(Debug, Clone, Default)]
+pub struct MutationObserver {
+    records: Rc<RefCell<Vec<MutationRecord>>>,
+    /// 監視しているノード。`disconnect()` で登録を取り除くのに使う
+    nodes: Rc<RefCell<Vec<Weak<RefCell<Node>>>>>,
+}
+
+impl MutationObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-observe
+    /// 同じノードをもう一度監視する場合は、設定を置き換える
+    pub fn observe(&self, target: &Rc<RefCell<Node>>, options: MutationObserverInit) -> Result<(), Error> {
+        let mut options = options;
+        if options.attribute_old_value {
+            options.attributes = true;
+        }
+        if options.character_data_old_value {
+            options.character_data = true;
+        }
+        if !options.child_list && !options.attributes && !options.character_data {
+            return Err(Error::UnexpectedInput(
+                "TypeError: one of child_list, attributes or character_data must be true".to_string(),
+            ));
+        }
+
+        let mut registered = target.borrow().registered_observers();
+        match registered.iter_mut().find(|r| r.observer.is(self)) {
+            Some(r) => r.options = options,
+            None => {
+                registered.push(RegisteredObserver {
+                    observer: self.clone(),
+                    options,
+                });
+                self.nodes.borrow_mut().push(Rc::downgrade(target));
+            }
+        }
+        target.borrow_mut().set_registered_observers(registered);
+        Ok(())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-disconnect
+    /// すべてのノードの監視をやめ、溜まっている記録を捨てる
+    pub fn disconnect(&self) {
+        for node in self.nodes.borrow_mut().drain(..) {
+            if let Some(node) = node.upgrade() {
+                let mut registered = node.borrow().registered_observers();
+                registered.retain(|r| !r.observer.is(self));
+                node.borrow_mut().set_registered_observers(registered);
+            }
+        }
+        self.records.borrow_mut().clear();
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-mutationobserver-takerecords
+    pub fn take_records(&self) -> Vec<MutationRecord> {
+        self.records.borrow_mut().drain(..).collect()
+    }
+
+    fn is(&self, other: &MutationObserver) -> bool {
+        Rc::ptr_eq(&self.records, &other.records)
+    }
+}
+
+/// https://dom.spec.whatwg.org/#registered-observer
+#[derive(Debug, Clone)]
+pub struct RegisteredObserver {
+    observer: MutationObserver,
+    options: MutationObserverInit,
+}
+
+/// https://dom.spec.whatwg.org/#queue-a-tree-mutation-record
+pub fn queue_child_list_record(
+    target: &Rc<RefCell<Node>>,
+    added_nodes: Vec<Rc<RefCell<Node>>>,
+    removed_nodes: Vec<Rc<RefCell<Node>>>,
+    previous_sibling: Option<Rc<RefCell<Node>>>,
+    next_sibling: Option<Rc<RefCell<Node>>>,
+) {
+    let mut record = MutationRecord::new(MutationType::ChildList, target);
+    record.added_nodes = added_nodes;
+    record.removed_nodes = removed_nodes;
+    record.previous_sibling = previous_sibling;
+    record.next_sibling = next_sibling;
+    queue_record(record, |options| options.child_list.then_some(None));
+}
+
+/// https://dom.spec.whatwg.org/#queue-an-attribute-mutation-record
+pub fn queue_attribute_record(target: &Rc<RefCell<Node>>, name: &str, old_value: Option<String>) {
+    let mut record = MutationRecord::new(MutationType::Attributes, target);
+    record.attribute_name = Some(name.to_string());
+    queue_record(record, |options| {
+        options
+            .attributes
+            .then(|| if options.attribute_old_value { old_value.clone() } else { None })
+    });
+}
+
+/// https://dom.spec.whatwg.org/#queue-a-mutation-record
+/// 文字列が変わったテキストノードやコメントの記録を作る
+pub fn queue_character_data_record(target: &Rc<RefCell<Node>>, old_value: String) {
+    let record = MutationRecord::new(MutationType::CharacterData, target);
+    queue_record(record, |options| {
+        options
+            .character_data
+            .then(|| if options.character_data_old_value { Some(old_value.clone()) } else { None })
+    });
+}
+
+/// https://dom.spec.whatwg.org/#queue-a-mutation-record
+/// `target` とその祖先に登録されたオブザーバーのうち、`interested` がSomeを返すものに記録を渡す。
+/// Someの中身は記録の `old_value` になる
+fn queue_record(record: MutationRecord, interested: impl Fn(&MutationObserverInit) -> Option<Option<String>>) {
+    let target = record.target.clone();
+    let mut observers: Vec<(MutationObserver, Option<String>)> = Vec::new();
+
+    let mut node = Some(target.clone());
+    while let Some(n) = node {
+        for registered in n.borrow().registered_observers().iter() {
+            if !Rc::ptr_eq(&n, &target) && !registered.options.subtree {
+                continue;
+            }
+            let old_value = match interested(&registered.options) {
+                Some(old_value) => old_value,
+                None => continue,
+            };
+            match observers.iter_mut().find(|(o, _)| o.is(&registered.observer)) {
+                // 同じオブザーバーには記録を1つだけ渡す
+                Some((_, value)) => {
+                    if value.is_none() {
+                        *value = old_value;
+                    }
+                }
+                None => observers.push((registered.observer.clone(), old_value)),
+            }
+        }
+        node = n.borrow().parent();
+    }
+
+    for (observer, old_value) in observers {
+        let mut record = record.clone();
+        record.old_value = old_value;
+        observer.records.borrow_mut().push(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{
+        append_child, remove_attribute, remove_child, set_attribute, set_text_content, Element, NodeKind,
+    };
+    use alloc::vec;
+
+    fn element(name: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))))
+    }
+
+    #[test]
+    fn test_child_list_records() {
+        let parent = element("div");
+        let first = element("p");
+        let second = element("span");
+        append_child(&parent, &first).expect("Failed to append a child");
+
+        let observer = MutationObserver::new();
+        let options = MutationObserverInit {
+            child_list: true,
+            ..Default::default()
+        };
+        observer.observe(&parent, options).expect("Failed to observe");
+
+        append_child(&parent, &second).expect("Failed to append a child");
+        remove_child(&parent, &first).expect("Failed to remove a child");
+        // 子孫の変更は `subtree` を指定しなければ記録しない
+        append_child(&second, &element("b")).expect("Failed to append a child");
+
+        let records = observer.take_records();
+        assert_eq!(2, records.len());
+        assert_eq!(MutationType::ChildList, records[0].mutation_type);
+        assert!(Rc::ptr_eq(&parent, &records[0].target));
+        assert!(Rc::ptr_eq(&second, &records[0].added_nodes[0]));
+        assert!(Rc::ptr_eq(&first, records[0].previous_sibling.as_ref().expect("Failed to get a previous sibling")));
+        assert!(Rc::ptr_eq(&first, &records[1].removed_nodes[0]));
+        assert!(Rc::ptr_eq(&second, records[1].next_sibling.as_ref().expect("Failed to get a next sibling")));
+        assert!(observer.take_records().is_empty());
+
+        observer.disconnect();
+        append_child(&parent, &element("i")).expect("Failed to append a child");
+        assert!(observer.take_records().is_empty());
+    }
+
+    #[test]
+    fn test_subtree_attribute_and_character_data_records() {
+        let parent = element("div");
+        let child = element("p");
+        let text = Rc::new(RefCell::new(Node::new(NodeKind::Text("old".to_string()))));
+        append_child(&parent, &child).expect("Failed to append a child");
+        append_child(&child, &text).expect("Failed to append a child");
+
+        let observer = MutationObserver::new();
+        assert!(observer.observe(&parent, MutationObserverInit::default()).is_err());
+        let options = MutationObserverInit {
+            subtree: true,
+            attribute_old_value: true,
+            character_data: true,
+            ..Default::default()
+        };
+        observer.observe(&parent, options).expect("Failed to observe");
+
+        set_attribute(&child, "class", "a");
+        set_attribute(&child, "class", "b");
+        remove_attribute(&child, "class");
+        // 存在しない属性を取り除いても記録しない
+        remove_attribute(&child, "id");
+        set_text_content(&text, "new");
+
+        let records = observer.take_records();
+        let summary: Vec<(MutationType, Option<String>)> =
+            records.iter().map(|r| (r.mutation_type, r.old_value.clone())).collect();
+        assert_eq!(
+            vec![
+                (MutationType::Attributes, None),
+                (MutationType::Attributes, Some("a".to_string())),
+                (MutationType::Attributes, Some("b".to_string())),
+                // `character_data_old_value` を指定していないので古い値は記録しない
+                (MutationType::CharacterData, None),
+            ],
+            summary
+        );
+        assert_eq!(Some("class".to_string()), records[0].attribute_name);
+        assert!(Rc::ptr_eq(&text, &records[3].target));
+    }
+}
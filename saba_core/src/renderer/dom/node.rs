@@ -1,20 +1,61 @@
+use crate::error::Error;
+use crate::renderer::dom::collection::{HtmlCollection, NamedNodeMap};
+use crate::renderer::dom::custom_element::{notify_connected, notify_disconnected, registry_of, CustomElementRegistry};
+use crate::renderer::dom::event::{dispatch_event, Event, EventListener};
+use crate::renderer::dom::mutation_observer::{
+    queue_attribute_record, queue_character_data_record, queue_child_list_record, RegisteredObserver,
+};
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::parse_error::SourcePosition;
+use crate::renderer::html::parser::{HtmlParser, QuirksMode};
+use crate::renderer::html::serializer::{serialize_children, serialize_node};
+use crate::url::Url;
 use alloc::format;
 use alloc::rc::{Rc, Weak};
-use alloc::string::String;
+use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::fmt;
 use core::str::FromStr;
 
 #[derive(Debug, Clone)]
 pub struct Node {
     pub kind: NodeKind,
     window: Weak<RefCell<Window>>,
+    /// https://dom.spec.whatwg.org/#concept-node-document
+    /// 文書に挿入したときに設定される。文書自身では空
+    owner_document: Weak<RefCell<Node>>,
     parent: Weak<RefCell<Node>>,
     first_child: Option<Rc<RefCell<Node>>>,
     last_child: Weak<RefCell<Node>>,
     previous_sibling: Weak<RefCell<Node>>,
     next_sibling: Option<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/scripting.html#template-contents
+    template_content: Option<Rc<RefCell<Node>>>,
+    /// https://dom.spec.whatwg.org/#concept-element-shadow-root
+    shadow_root: Option<Rc<RefCell<Node>>>,
+    /// https://dom.spec.whatwg.org/#concept-documentfragment-host
+    /// シャドウルートでは、それを持つ要素
+    host: Weak<RefCell<Node>>,
+    /// HTMLの中でこのノードが始まる位置。構文解析器が作ったノードにだけ設定される
+    source_location: Option<SourcePosition>,
+    /// https://dom.spec.whatwg.org/#eventtarget-event-listener-list
+    event_listeners: Vec<EventListener>,
+    /// https://dom.spec.whatwg.org/#registered-observer-list
+    registered_observers: Vec<RegisteredObserver>,
+    /// 文書だけが持つ。`Window` と同じ定義を共有する
+    custom_element_registry: Option<CustomElementRegistry>,
+    /// https://dom.spec.whatwg.org/#concept-element-custom-element-state
+    /// カスタム要素が定義に結びついたらtrueになる
+    custom_element_upgraded: bool,
+}
+
+/// デバッグ用に、ノードとその子孫をHTMLとして書く
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.outer_html())
+    }
 }
 
 impl PartialEq for Node {
@@ -28,20 +69,40 @@ impl Node {
         Self {
             kind,
             window: Weak::new(),
+            owner_document: Weak::new(),
             parent: Weak::new(),
             first_child: None,
             last_child: Weak::new(),
             previous_sibling: Weak::new(),
             next_sibling: None,
+            template_content: None,
+            shadow_root: None,
+            host: Weak::new(),
+            source_location: None,
+            event_listeners: Vec::new(),
+            registered_observers: Vec::new(),
+            custom_element_registry: None,
+            custom_element_upgraded: false,
         }
     }
 
+    pub fn set_owner_document(&mut self, owner_document: Weak<RefCell<Node>>) {
+        self.owner_document = owner_document;
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-ownerdocument
+    /// 文書自身と、まだ文書に挿入されていないノードではNoneを返す
+    pub fn owner_document(&self) -> Option<Rc<RefCell<Node>>> {
+        self.owner_document.upgrade()
+    }
+
     pub fn set_parent(&mut self, parent: Weak<RefCell<Node>>) {
         self.parent = parent;
     }
 
-    pub fn parent(&self) -> Weak<RefCell<Node>> {
-        self.parent.clone()
+    /// 親がないか、親がすでに破棄されていればNoneを返す
+    pub fn parent(&self) -> Option<Rc<RefCell<Node>>> {
+        self.parent.upgrade()
     }
 
     pub fn set_first_child(&mut self, first_child: Option<Rc<RefCell<Node>>>) {
@@ -76,6 +137,105 @@ impl Node {
         self.next_sibling.as_ref().cloned()
     }
 
+    pub fn set_template_content(&mut self, template_content: Option<Rc<RefCell<Node>>>) {
+        self.template_content = template_content;
+    }
+
+    /// <template>の中身は文書の木に含めず、この断片に保持する
+    pub fn template_content(&self) -> Option<Rc<RefCell<Node>>> {
+        self.template_content.as_ref().cloned()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-shadowroot
+    /// 開いたシャドウルートだけを返す
+    pub fn shadow_root(&self) -> Option<Rc<RefCell<Node>>> {
+        let shadow_root = self.shadow_root.as_ref()?;
+        match shadow_root.borrow().kind {
+            NodeKind::ShadowRoot(ShadowRootMode::Open) => Some(shadow_root.clone()),
+            _ => None,
+        }
+    }
+
+    /// 閉じたシャドウルートも返す。レイアウトなど、ブラウザ自身が使う
+    pub fn attached_shadow_root(&self) -> Option<Rc<RefCell<Node>>> {
+        self.shadow_root.as_ref().cloned()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-shadowroot-host
+    pub fn host(&self) -> Option<Rc<RefCell<Node>>> {
+        self.host.upgrade()
+    }
+
+    /// https://dom.spec.whatwg.org/#connected
+    /// シャドウルートを含めてたどった根が文書であればtrueを返す
+    pub fn is_connected(&self) -> bool {
+        let mut node = match self.kind {
+            NodeKind::Document => return true,
+            NodeKind::ShadowRoot(_) => self.host(),
+            _ => self.parent(),
+        };
+        while let Some(n) = node {
+            node = match n.borrow().kind {
+                NodeKind::Document => return true,
+                NodeKind::ShadowRoot(_) => n.borrow().host(),
+                _ => n.borrow().parent(),
+            };
+        }
+        false
+    }
+
+    pub fn set_custom_element_registry(&mut self, custom_element_registry: Option<CustomElementRegistry>) {
+        self.custom_element_registry = custom_element_registry;
+    }
+
+    pub fn custom_element_registry(&self) -> Option<CustomElementRegistry> {
+        self.custom_element_registry.clone()
+    }
+
+    pub fn set_custom_element_upgraded(&mut self, custom_element_upgraded: bool) {
+        self.custom_element_upgraded = custom_element_upgraded;
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-element-defined
+    /// カスタム要素はアップグレードされるまでfalseを返す。それ以外のノードは常にtrue
+    pub fn is_defined(&self) -> bool {
+        match self.get_element() {
+            Some(element) if element.is_custom() => self.custom_element_upgraded,
+            _ => true,
+        }
+    }
+
+    pub fn set_source_location(&mut self, source_location: Option<SourcePosition>) {
+        self.source_location = source_location;
+    }
+
+    pub fn source_location(&self) -> Option<SourcePosition> {
+        self.source_location
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-eventtarget-addeventlistener
+    /// `capture` がtrueなら捕捉フェーズで、falseなら浮上フェーズで呼び出される
+    pub fn add_event_listener(&mut self, event_type: &str, callback: impl Fn(&mut Event) + 'static, capture: bool) {
+        self.event_listeners.push(EventListener::new(event_type, callback, capture));
+    }
+
+    /// `event_type` のリスナーをすべて取り除く
+    pub fn remove_event_listeners(&mut self, event_type: &str) {
+        self.event_listeners.retain(|l| l.event_type() != event_type);
+    }
+
+    pub fn event_listeners(&self) -> Vec<EventListener> {
+        self.event_listeners.clone()
+    }
+
+    pub fn set_registered_observers(&mut self, registered_observers: Vec<RegisteredObserver>) {
+        self.registered_observers = registered_observers;
+    }
+
+    pub fn registered_observers(&self) -> Vec<RegisteredObserver> {
+        self.registered_observers.clone()
+    }
+
     fn set_window(&mut self, window: Weak<RefCell<Window>>) {
         self.window = window;
     }
@@ -87,51 +247,370 @@ impl Node {
     pub fn get_element(&self) -> Option<Element> {
         match self.kind {
             NodeKind::Document => { None }
+            NodeKind::DocumentFragment => { None }
             NodeKind::Element(ref e) => { Some(e.clone()) }
             NodeKind::Text(_) => { None }
+            NodeKind::Comment(_) => { None }
+            NodeKind::DocumentType(_) => { None }
+            NodeKind::ShadowRoot(_) => { None }
+        }
+    }
+
+    /// 木の中の要素を直接書き換えるときに使う
+    pub fn get_element_mut(&mut self) -> Option<&mut Element> {
+        match self.kind {
+            NodeKind::Element(ref mut e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-childnodes
+    pub fn child_nodes(&self) -> ChildNodes {
+        ChildNodes {
+            next: self.first_child(),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-parentnode-children
+    /// 子のうち要素だけを返す
+    pub fn children(&self) -> impl Iterator<Item = Rc<RefCell<Node>>> {
+        self.child_nodes().filter(|c| c.borrow().get_element().is_some())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-parentnode-firstelementchild
+    pub fn first_element_child(&self) -> Option<Rc<RefCell<Node>>> {
+        self.children().next()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-parentnode-lastelementchild
+    pub fn last_element_child(&self) -> Option<Rc<RefCell<Node>>> {
+        let mut child = self.last_child().upgrade();
+        while let Some(c) = child {
+            if c.borrow().get_element().is_some() {
+                return Some(c);
+            }
+            child = c.borrow().previous_sibling().upgrade();
+        }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-nondocumenttypechildnode-nextelementsibling
+    /// テキストやコメントを飛ばして、次の兄弟の要素を返す
+    pub fn next_element_sibling(&self) -> Option<Rc<RefCell<Node>>> {
+        let mut sibling = self.next_sibling();
+        while let Some(s) = sibling {
+            if s.borrow().get_element().is_some() {
+                return Some(s);
+            }
+            sibling = s.borrow().next_sibling();
         }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-nondocumenttypechildnode-previouselementsibling
+    /// テキストやコメントを飛ばして、前の兄弟の要素を返す
+    pub fn previous_element_sibling(&self) -> Option<Rc<RefCell<Node>>> {
+        let mut sibling = self.previous_sibling().upgrade();
+        while let Some(s) = sibling {
+            if s.borrow().get_element().is_some() {
+                return Some(s);
+            }
+            sibling = s.borrow().previous_sibling().upgrade();
+        }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-tree-descendant
+    /// 自分自身は含めずに、子孫を文書順（行きがけ順）に返す
+    pub fn descendants(&self) -> Descendants {
+        Descendants {
+            next: self.first_child(),
+            pending_siblings: Vec::new(),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-tree-ancestor
+    /// 親から順に、根までの祖先を返す
+    pub fn ancestors(&self) -> Ancestors {
+        Ancestors {
+            next: self.parent(),
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-node-textcontent
+    /// 子孫のテキストノードの文字列を文書順に連結する。文書ではNoneを返す
+    pub fn text_content(&self) -> Option<String> {
+        match self.kind {
+            NodeKind::Document | NodeKind::DocumentType(_) => None,
+            NodeKind::Text(ref s) | NodeKind::Comment(ref s) => Some(s.clone()),
+            NodeKind::Element(_) | NodeKind::DocumentFragment | NodeKind::ShadowRoot(_) => {
+                let mut text = String::new();
+                for node in self.descendants() {
+                    if let NodeKind::Text(ref s) = node.borrow().kind {
+                        text.push_str(s);
+                    }
+                }
+                Some(text)
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+    pub fn inner_html(&self) -> String {
+        serialize_children(self)
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-node-clone
+    /// 親や兄弟を持たない新しいノードを作る。`deep` がtrueなら子孫も複製する。
+    /// <template>の中身は、`deep` がfalseでも空の文書片として作り直す
+    pub fn clone_node(&self, deep: bool) -> Rc<RefCell<Node>> {
+        let mut clone = Node::new(self.kind.clone());
+        clone.window = self.window.clone();
+        clone.owner_document = self.owner_document.clone();
+        clone.source_location = self.source_location;
+        if let Some(ref content) = self.template_content {
+            clone.template_content = Some(content.borrow().clone_node(deep));
+        }
+
+        let clone = Rc::new(RefCell::new(clone));
+        if deep {
+            let mut child = self.first_child();
+            while let Some(c) = child {
+                let copy = c.borrow().clone_node(true);
+                insert_child(&clone, &copy, None);
+                child = c.borrow().next_sibling();
+            }
+        }
+        clone
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-node-equals
+    /// `PartialEq` と違い、要素名や属性、文字列、子孫まで比べる。属性の順序は問わない
+    pub fn deep_equals(&self, other: &Node) -> bool {
+        let equals = match (&self.kind, &other.kind) {
+            (NodeKind::Document, NodeKind::Document) => true,
+            (NodeKind::DocumentFragment, NodeKind::DocumentFragment) => true,
+            (NodeKind::Element(e1), NodeKind::Element(e2)) => {
+                let attributes = e2.attributes();
+                e1.namespace() == e2.namespace()
+                    && e1.local_name() == e2.local_name()
+                    && e1.attributes().len() == attributes.len()
+                    && e1
+                        .attributes()
+                        .iter()
+                        .all(|a1| attributes.iter().any(|a2| a1.name() == a2.name() && a1.value() == a2.value()))
+            }
+            (NodeKind::Text(s1), NodeKind::Text(s2)) => s1 == s2,
+            (NodeKind::Comment(s1), NodeKind::Comment(s2)) => s1 == s2,
+            (NodeKind::DocumentType(d1), NodeKind::DocumentType(d2)) => d1 == d2,
+            (NodeKind::ShadowRoot(m1), NodeKind::ShadowRoot(m2)) => m1 == m2,
+            _ => false,
+        };
+        if !equals {
+            return false;
+        }
+
+        match (self.template_content(), other.template_content()) {
+            (Some(c1), Some(c2)) => {
+                if !c1.borrow().deep_equals(&c2.borrow()) {
+                    return false;
+                }
+            }
+            (None, None) => {}
+            _ => return false,
+        }
+
+        let mut children = other.child_nodes();
+        for child in self.child_nodes() {
+            match children.next() {
+                Some(other_child) => {
+                    if !child.borrow().deep_equals(&other_child.borrow()) {
+                        return false;
+                    }
+                }
+                None => return false,
+            }
+        }
+        children.next().is_none()
+    }
+
+    /// https://w3c.github.io/DOM-Parsing/#dom-element-outerhtml
+    pub fn outer_html(&self) -> String {
+        serialize_node(self)
     }
 
     pub fn element_kind(&self) -> Option<ElementKind> {
         match self.kind {
             NodeKind::Document => { None }
+            NodeKind::DocumentFragment => { None }
             NodeKind::Element(ref e) => { Some(e.kind()) }
             NodeKind::Text(_) => { None }
+            NodeKind::Comment(_) => { None }
+            NodeKind::DocumentType(_) => { None }
+            NodeKind::ShadowRoot(_) => { None }
         }
     }
 }
 
+/// `Node::child_nodes()` が返すイテレータ
+#[derive(Debug, Clone)]
+pub struct ChildNodes {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for ChildNodes {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().next_sibling();
+        Some(node)
+    }
+}
+
+/// `Node::descendants()` が返すイテレータ
+#[derive(Debug, Clone)]
+pub struct Descendants {
+    next: Option<Rc<RefCell<Node>>>,
+    /// 子孫をたどり終えた後に返す、祖先の次の兄弟
+    pending_siblings: Vec<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Descendants {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        let first_child = node.borrow().first_child();
+        let next_sibling = node.borrow().next_sibling();
+        self.next = match first_child {
+            Some(child) => {
+                self.pending_siblings.extend(next_sibling);
+                Some(child)
+            }
+            None => next_sibling.or_else(|| self.pending_siblings.pop()),
+        };
+        Some(node)
+    }
+}
+
+/// `Node::ancestors()` が返すイテレータ
+#[derive(Debug, Clone)]
+pub struct Ancestors {
+    next: Option<Rc<RefCell<Node>>>,
+}
+
+impl Iterator for Ancestors {
+    type Item = Rc<RefCell<Node>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.take()?;
+        self.next = node.borrow().parent();
+        Some(node)
+    }
+}
+
 #[derive(Debug, Clone, Eq)]
 pub enum NodeKind {
     Document,
+    /// https://dom.spec.whatwg.org/#interface-documentfragment
+    DocumentFragment,
     Element(Element),
     Text(String),
+    /// https://dom.spec.whatwg.org/#interface-comment
+    Comment(String),
+    /// https://dom.spec.whatwg.org/#interface-documenttype
+    DocumentType(DocumentType),
+    /// https://dom.spec.whatwg.org/#interface-shadowroot
+    /// ホストの子ではないので、ホストの木をたどっても現れない
+    ShadowRoot(ShadowRootMode),
+}
+
+/// https://dom.spec.whatwg.org/#enumdef-shadowrootmode
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ShadowRootMode {
+    Open,
+    /// ホストの `shadow_root()` から見えない
+    Closed,
 }
 
 impl PartialEq for NodeKind {
     fn eq(&self, other: &Self) -> bool {
         match &self {
             NodeKind::Document => matches!(other, NodeKind::Document),
+            NodeKind::DocumentFragment => matches!(other, NodeKind::DocumentFragment),
             NodeKind::Element(e1) => {
                 match &other {
                     NodeKind::Element(e2) => e1.kind == e2.kind,
                     _ => false
                 }
             }
-            NodeKind::Text(_) => matches!(other,NodeKind::Text(_))
+            NodeKind::Text(_) => matches!(other,NodeKind::Text(_)),
+            NodeKind::Comment(_) => matches!(other, NodeKind::Comment(_)),
+            NodeKind::DocumentType(_) => matches!(other, NodeKind::DocumentType(_)),
+            NodeKind::ShadowRoot(_) => matches!(other, NodeKind::ShadowRoot(_)),
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#interface-documenttype
+/// 識別子が書かれていない場合は空文字列になる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentType {
+    name: String,
+    public_id: String,
+    system_id: String,
+}
+
+impl DocumentType {
+    pub fn new(name: &str, public_id: &str, system_id: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            public_id: public_id.to_string(),
+            system_id: system_id.to_string(),
         }
     }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn public_id(&self) -> String {
+        self.public_id.clone()
+    }
+
+    pub fn system_id(&self) -> String {
+        self.system_id.clone()
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Window {
     document: Rc<RefCell<Node>>,
+    focused_element: Option<Rc<RefCell<Node>>>,
+    /// 解析中のスクリプトが `document.write()` で書き込んだ、まだ解析していない文字列
+    pending_writes: String,
+    /// 最初の<base>要素のhref属性
+    base_href: Option<String>,
+    /// https://dom.spec.whatwg.org/#concept-document-mode
+    /// DOCTYPEから決まる。CSSとレイアウトで互換性のための挙動を切り替えるのに使う
+    quirks_mode: QuirksMode,
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#dom-window-customelements
+    custom_elements: CustomElementRegistry,
 }
 
 impl Window {
     pub fn new() -> Self {
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        let custom_elements = CustomElementRegistry::new(Rc::downgrade(&document));
+        document.borrow_mut().set_custom_element_registry(Some(custom_elements.clone()));
         let window = Self {
-            document: Rc::new(RefCell::new(Node::new(NodeKind::Document)))
+            document,
+            focused_element: None,
+            pending_writes: String::new(),
+            base_href: None,
+            quirks_mode: QuirksMode::NoQuirks,
+            custom_elements,
         };
         window.document.borrow_mut().set_window(Rc::downgrade(&Rc::new(RefCell::new(window.clone()))));
 
@@ -141,55 +620,1682 @@ impl Window {
     pub fn document(&self) -> Rc<RefCell<Node>> {
         Rc::clone(&self.document)
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Element {
-    kind: ElementKind,
-    attributes: Vec<Attribute>,
-}
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#dom-window-customelements
+    pub fn custom_elements(&self) -> CustomElementRegistry {
+        self.custom_elements.clone()
+    }
 
-impl Element {
-    pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
-        Self {
-            kind: ElementKind::from_str(element_name).expect("Failed to convert string to ElementKind"),
-            attributes,
+    /// https://dom.spec.whatwg.org/#dom-document-documentelement
+    /// 文書の子の要素。ふつうは<html>
+    pub fn document_element(&self) -> Option<Rc<RefCell<Node>>> {
+        self.document.borrow().first_element_child()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#dom-document-head
+    pub fn head(&self) -> Option<Rc<RefCell<Node>>> {
+        self.find_html_child(ElementKind::Head)
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#dom-document-body
+    pub fn body(&self) -> Option<Rc<RefCell<Node>>> {
+        self.find_html_child(ElementKind::Body)
+    }
+
+    /// <html>の子のうち、最初の `kind` の要素
+    fn find_html_child(&self, kind: ElementKind) -> Option<Rc<RefCell<Node>>> {
+        let html = self.document_element()?;
+        if html.borrow().element_kind() != Some(ElementKind::Html) {
+            return None;
         }
+        let child = html.borrow().children().find(|c| c.borrow().element_kind() == Some(kind));
+        child
     }
 
-    pub fn kind(&self) -> ElementKind {
-        self.kind
+    /// https://html.spec.whatwg.org/multipage/interaction.html#dom-document-activeelement
+    pub fn focused_element(&self) -> Option<Rc<RefCell<Node>>> {
+        self.focused_element.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#dom-focus
+    /// フォーカスできない要素の場合は何もせずにfalseを返す
+    pub fn focus(&mut self, node: &Rc<RefCell<Node>>) -> bool {
+        let focusable = match node.borrow().get_element() {
+            Some(element) => element.tab_index().is_some(),
+            None => false,
+        };
+
+        if focusable {
+            self.set_focused_element(Some(node.clone()));
+        }
+        focusable
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-document-write
+    /// 書き込んだ文字列は、スクリプトを閉じた直後の位置に挿入されて解析される
+    pub fn document_write(&mut self, text: &str) {
+        self.pending_writes.push_str(text);
+    }
+
+    /// `document_write()` で書き込まれた文字列を取り出す
+    pub fn take_pending_writes(&mut self) -> String {
+        core::mem::take(&mut self.pending_writes)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-document-createdocumentfragment
+    /// 子を追加してから挿入すると、子がまとめて移される
+    pub fn create_document_fragment(&self) -> Rc<RefCell<Node>> {
+        let mut fragment = Node::new(NodeKind::DocumentFragment);
+        fragment.set_window(self.document.borrow().window.clone());
+        fragment.set_owner_document(Rc::downgrade(&self.document));
+        Rc::new(RefCell::new(fragment))
+    }
+
+    pub fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-document-doctype
+    pub fn doctype(&self) -> Option<Rc<RefCell<Node>>> {
+        self.document
+            .borrow()
+            .child_nodes()
+            .find(|node| matches!(node.borrow().kind, NodeKind::DocumentType(_)))
+    }
+
+    pub fn set_base_href(&mut self, base_href: Option<String>) {
+        self.base_href = base_href;
+    }
+
+    pub fn base_href(&self) -> Option<String> {
+        self.base_href.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#document-base-url
+    /// 文書の中の相対URLを解決するときの基準。<base>がない場合や解決できない場合は、文書のURLをそのまま使う
+    pub fn base_url(&self, document_url: &Url) -> Url {
+        match self.base_href {
+            Some(ref href) => document_url.join(href).unwrap_or_else(|_| document_url.clone()),
+            None => document_url.clone(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#dom-blur
+    pub fn blur(&mut self) {
+        self.set_focused_element(None);
+    }
+
+    /// Tabキーが押されたときに、順番が次の要素にフォーカスを移す。最後の要素の次は最初の要素に戻る
+    pub fn focus_next(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.move_focus(true)
+    }
+
+    /// Shift+Tabキーが押されたときに、順番が前の要素にフォーカスを移す
+    pub fn focus_previous(&mut self) -> Option<Rc<RefCell<Node>>> {
+        self.move_focus(false)
+    }
+
+    fn move_focus(&mut self, forward: bool) -> Option<Rc<RefCell<Node>>> {
+        let order = self.sequential_focus_order();
+        if order.is_empty() {
+            return None;
+        }
+
+        let current = self
+            .focused_element
+            .as_ref()
+            .and_then(|focused| order.iter().position(|n| Rc::ptr_eq(n, focused)));
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % order.len(),
+            (Some(i), false) => (i + order.len() - 1) % order.len(),
+            (None, true) => 0,
+            (None, false) => order.len() - 1,
+        };
+
+        self.set_focused_element(Some(order[next].clone()));
+        self.focused_element()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#focus-update-steps
+    /// フォーカスが移るときに、前の要素にblur、新しい要素にfocusを送る。どちらも浮上しない。
+    /// リスナーを呼び出す間も `Window` は借用されたままなので、リスナーの中から `Window` は使えない
+    fn set_focused_element(&mut self, node: Option<Rc<RefCell<Node>>>) {
+        let unchanged = match (&self.focused_element, &node) {
+            (Some(old), Some(new)) => Rc::ptr_eq(old, new),
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return;
+        }
+
+        if let Some(old) = self.focused_element.take() {
+            dispatch_event(&old, &mut Event::new("blur", false, false));
+        }
+        self.focused_element = node.clone();
+        if let Some(new) = node {
+            dispatch_event(&new, &mut Event::new("focus", false, false));
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#sequential-focus-navigation-order
+    /// tabindexが正の要素を値の小さい順に並べ、その後にtabindexが0の要素を文書順に並べる
+    fn sequential_focus_order(&self) -> Vec<Rc<RefCell<Node>>> {
+        let mut order = self.collect_elements(|element| element.tab_index().is_some_and(|i| i >= 0));
+        order.sort_by_key(|node| {
+            let index = node
+                .borrow()
+                .get_element()
+                .and_then(|element| element.tab_index())
+                .unwrap_or(0);
+            (index == 0, index)
+        });
+        order
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#dom-document-forms
+    /// 呼び出すたびにDOMツリーをたどって集め直す
+    pub fn forms(&self) -> HtmlCollection {
+        HtmlCollection::new(self.collect_elements(|element| element.kind() == ElementKind::Form))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#dom-document-links
+    pub fn links(&self) -> HtmlCollection {
+        HtmlCollection::new(self.collect_elements(|element| {
+            element.kind() == ElementKind::A
+                && element.href().is_some()
+        }))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#dom-document-images
+    pub fn images(&self) -> HtmlCollection {
+        HtmlCollection::new(self.collect_elements(|element| element.kind() == ElementKind::Img))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#document.title
+    /// 最初の<title>要素の文字列。前後の空白を取り除き、連続する空白は1つにまとめる
+    pub fn title(&self) -> String {
+        let title = match self.collect_elements(|element| element.kind() == ElementKind::Title).first() {
+            Some(title) => title.clone(),
+            None => return String::new(),
+        };
+
+        let mut text = String::new();
+        let mut child = title.borrow().first_child();
+        while let Some(c) = child {
+            if let NodeKind::Text(ref s) = c.borrow().kind {
+                text.push_str(s);
+            }
+            child = c.borrow().next_sibling();
+        }
+
+        text.split_ascii_whitespace().collect::<Vec<&str>>().join(" ")
+    }
+
+    /// 画像の読み込み先として、<img>要素のsrc属性を文書順に返す。src属性がないか空の要素は含めない
+    pub fn image_sources(&self) -> Vec<String> {
+        self.images()
+            .iter()
+            .filter_map(|image| image.borrow().get_element()?.src())
+            .filter(|src| !src.is_empty())
+            .collect()
+    }
+
+    /// 文書内の要素のうち `predicate` を満たすものを文書順に返す
+    fn collect_elements(&self, predicate: fn(&Element) -> bool) -> Vec<Rc<RefCell<Node>>> {
+        self.document
+            .borrow()
+            .descendants()
+            .filter(|node| node.borrow().get_element().is_some_and(|element| predicate(&element)))
+            .collect()
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum ElementKind {
-    Html,
-    Head,
-    Style,
-    Script,
-    Body,
-    P,
-    H1,
-    H2,
-    A,
+/// https://dom.spec.whatwg.org/#dom-node-textcontent
+/// 要素と文書片では子をすべて取り除き、空でなければ1つのテキストノードに置き換える
+pub fn set_text_content(node: &Rc<RefCell<Node>>, text: &str) {
+    let old_value = match node.borrow_mut().kind {
+        NodeKind::Document | NodeKind::DocumentType(_) => return,
+        NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) => Some(core::mem::replace(s, text.to_string())),
+        NodeKind::Element(_) | NodeKind::DocumentFragment | NodeKind::ShadowRoot(_) => None,
+    };
+    if let Some(old_value) = old_value {
+        queue_character_data_record(node, old_value);
+        return;
+    }
+
+    loop {
+        let child = match node.borrow().first_child() {
+            Some(child) => child,
+            None => break,
+        };
+        detach(&child);
+    }
+    if !text.is_empty() {
+        let text = Rc::new(RefCell::new(Node::new(NodeKind::Text(text.to_string()))));
+        insert_child(node, &text, None);
+    }
 }
 
-impl FromStr for ElementKind {
-    type Err = String;
+/// https://html.spec.whatwg.org/multipage/dynamic-markup-insertion.html#dom-element-innerhtml
+/// `html` を `node` を文脈として解析し、`node` の子をすべて置き換える。<template>では中身を置き換える
+pub fn set_inner_html(node: &Rc<RefCell<Node>>, html: &str) {
+    let fragment = HtmlParser::parse_fragment(node, html.to_string());
+    let content = node.borrow().template_content();
+    let target = content.unwrap_or_else(|| node.clone());
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "html" => Ok(ElementKind::Html),
-            "head" => Ok(ElementKind::Head),
-            "style" => Ok(ElementKind::Style),
-            "script" => Ok(ElementKind::Script),
-            "body" => Ok(ElementKind::Body),
-            "p" => Ok(ElementKind::P),
-            "h1" => Ok(ElementKind::H1),
-            "h2" => Ok(ElementKind::H2),
-            "a" => Ok(ElementKind::A),
-            _ => Err(format!("Unimplemented element name {}", s))
+    loop {
+        let child = match target.borrow().first_child() {
+            Some(child) => child,
+            None => break,
+        };
+        detach(&child);
+    }
+    insert_child(&target, &fragment, None);
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-normalize
+/// 子孫の隣り合うテキストノードを1つにまとめ、空のテキストノードを取り除く
+pub fn normalize(node: &Rc<RefCell<Node>>) {
+    let mut child = node.borrow().first_child();
+    while let Some(c) = child {
+        if !matches!(c.borrow().kind, NodeKind::Text(_)) {
+            normalize(&c);
+            child = c.borrow().next_sibling();
+            continue;
         }
+
+        loop {
+            let next = match c.borrow().next_sibling() {
+                Some(next) => next,
+                None => break,
+            };
+            let data = match next.borrow().kind {
+                NodeKind::Text(ref s) => s.clone(),
+                _ => break,
+            };
+            let old_value = match c.borrow_mut().kind {
+                NodeKind::Text(ref mut s) => {
+                    let old_value = s.clone();
+                    s.push_str(&data);
+                    old_value
+                }
+                _ => break,
+            };
+            queue_character_data_record(&c, old_value);
+            detach(&next);
+        }
+
+        child = c.borrow().next_sibling();
+        if c.borrow().text_content().is_some_and(|s| s.is_empty()) {
+            detach(&c);
+        }
+    }
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-appendchild
+pub fn append_child(parent: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) -> Result<Rc<RefCell<Node>>, Error> {
+    insert_before(parent, node, None)
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-insertbefore
+/// `child` がNoneの場合は末尾に追加する
+pub fn insert_before(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+) -> Result<Rc<RefCell<Node>>, Error> {
+    ensure_pre_insertion_validity(parent, node, child)?;
+    // 自分自身の前に挿入する場合は、自分の次のノードの前に挿入するのと同じ
+    let reference = match child {
+        Some(c) if Rc::ptr_eq(c, node) => node.borrow().next_sibling(),
+        _ => child.cloned(),
+    };
+    insert_child(parent, node, reference.as_ref());
+    Ok(node.clone())
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-removechild
+pub fn remove_child(parent: &Rc<RefCell<Node>>, child: &Rc<RefCell<Node>>) -> Result<Rc<RefCell<Node>>, Error> {
+    if !is_child_of(child, parent) {
+        return Err(Error::UnexpectedInput("NotFoundError: the node is not a child of the parent".to_string()));
+    }
+    detach(child);
+    Ok(child.clone())
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-replacechild
+/// `child` を `node` に置き換えて、取り除いた `child` を返す
+pub fn replace_child(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: &Rc<RefCell<Node>>,
+) -> Result<Rc<RefCell<Node>>, Error> {
+    ensure_pre_insertion_validity(parent, node, Some(child))?;
+
+    let mut reference = child.borrow().next_sibling();
+    if reference.as_ref().is_some_and(|r| Rc::ptr_eq(r, node)) {
+        reference = node.borrow().next_sibling();
+    }
+    if !Rc::ptr_eq(child, node) {
+        detach(child);
+    }
+    insert_child(parent, node, reference.as_ref());
+    Ok(child.clone())
+}
+
+/// https://dom.spec.whatwg.org/#concept-node-ensure-pre-insertion-validity
+fn ensure_pre_insertion_validity(
+    parent: &Rc<RefCell<Node>>,
+    node: &Rc<RefCell<Node>>,
+    child: Option<&Rc<RefCell<Node>>>,
+) -> Result<(), Error> {
+    let hierarchy_error = |message: &str| Err(Error::UnexpectedInput(format!("HierarchyRequestError: {}", message)));
+
+    if matches!(parent.borrow().kind, NodeKind::Text(_) | NodeKind::Comment(_) | NodeKind::DocumentType(_)) {
+        return hierarchy_error("a text, comment or doctype node cannot have children");
+    }
+    let mut ancestor = Some(parent.clone());
+    while let Some(a) = ancestor {
+        if Rc::ptr_eq(&a, node) {
+            return hierarchy_error("the node is an inclusive ancestor of the parent");
+        }
+        ancestor = a.borrow().parent();
+    }
+    if let Some(child) = child {
+        if !is_child_of(child, parent) {
+            return Err(Error::UnexpectedInput("NotFoundError: the node is not a child of the parent".to_string()));
+        }
+    }
+
+    match (&parent.borrow().kind, &node.borrow().kind) {
+        (_, NodeKind::Document) => hierarchy_error("a document cannot be inserted"),
+        (_, NodeKind::ShadowRoot(_)) => hierarchy_error("a shadow root cannot be inserted"),
+        (NodeKind::Document, NodeKind::Text(_)) => hierarchy_error("a document cannot have text children"),
+        (NodeKind::Document, NodeKind::DocumentType(_)) => Ok(()),
+        (_, NodeKind::DocumentType(_)) => hierarchy_error("a doctype can only be a child of a document"),
+        _ => Ok(()),
+    }
+}
+
+fn is_child_of(child: &Rc<RefCell<Node>>, parent: &Rc<RefCell<Node>>) -> bool {
+    child.borrow().parent().is_some_and(|p| Rc::ptr_eq(&p, parent))
+}
+
+/// https://dom.spec.whatwg.org/#concept-node-insert
+/// 検査をせずに `reference` の前（Noneなら末尾）に挿入する。別の場所にあるノードは先に取り除き、
+/// 文書片の場合はその子を順に移す
+pub fn insert_child(parent: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>, reference: Option<&Rc<RefCell<Node>>>) {
+    if node.borrow().kind == NodeKind::DocumentFragment {
+        loop {
+            let child = match node.borrow().first_child() {
+                Some(child) => child,
+                None => return,
+            };
+            insert_child(parent, &child, reference);
+        }
+    }
+
+    detach(node);
+    let previous = match reference {
+        Some(reference) => reference.borrow().previous_sibling().upgrade(),
+        None => parent.borrow().last_child().upgrade(),
+    };
+
+    match previous {
+        Some(ref previous) => previous.borrow_mut().set_next_sibling(Some(node.clone())),
+        None => parent.borrow_mut().set_first_child(Some(node.clone())),
+    }
+    match reference {
+        Some(reference) => reference.borrow_mut().set_previous_sibling(Rc::downgrade(node)),
+        None => parent.borrow_mut().set_last_child(Rc::downgrade(node)),
+    }
+
+    {
+        let mut node = node.borrow_mut();
+        node.set_previous_sibling(previous.as_ref().map_or_else(Weak::new, Rc::downgrade));
+        node.set_next_sibling(reference.cloned());
+        node.set_parent(Rc::downgrade(parent));
+    }
+    let owner_document = match parent.borrow().kind {
+        NodeKind::Document => Rc::downgrade(parent),
+        _ => parent.borrow().owner_document.clone(),
+    };
+    adopt(node, &owner_document);
+    queue_child_list_record(parent, vec![node.clone()], Vec::new(), previous, reference.cloned());
+    notify_connected(node);
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-attachshadow
+/// `host` にシャドウルートを付けて返す。シャドウルートの子はホストの子孫として扱われない
+pub fn attach_shadow(host: &Rc<RefCell<Node>>, mode: ShadowRootMode) -> Result<Rc<RefCell<Node>>, Error> {
+    let element = match host.borrow().get_element() {
+        Some(element) => element,
+        None => return Err(Error::UnexpectedInput("NotSupportedError: only elements can be shadow hosts".to_string())),
+    };
+    let valid_host = element.namespace() == Namespace::Html
+        && (element.is_custom() || VALID_SHADOW_HOST_NAMES.contains(&element.local_name().as_str()));
+    if !valid_host {
+        return Err(Error::UnexpectedInput(format!(
+            "NotSupportedError: <{}> cannot be a shadow host",
+            element.local_name()
+        )));
+    }
+    if host.borrow().shadow_root.is_some() {
+        return Err(Error::UnexpectedInput("NotSupportedError: the element already has a shadow root".to_string()));
+    }
+
+    let mut shadow_root = Node::new(NodeKind::ShadowRoot(mode));
+    shadow_root.host = Rc::downgrade(host);
+    shadow_root.owner_document = host.borrow().owner_document.clone();
+    let shadow_root = Rc::new(RefCell::new(shadow_root));
+    host.borrow_mut().shadow_root = Some(shadow_root.clone());
+    Ok(shadow_root)
+}
+
+/// https://dom.spec.whatwg.org/#valid-shadow-host-name
+/// 自律カスタム要素のほかにシャドウルートを付けられる要素
+const VALID_SHADOW_HOST_NAMES: [&str; 18] = [
+    "article", "aside", "blockquote", "body", "div", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "main",
+    "nav", "p", "section", "span",
+];
+
+/// https://dom.spec.whatwg.org/#concept-node-adopt
+/// `node` とその子孫の文書を `owner_document` にする。すでに同じ文書であれば子孫は調べない
+fn adopt(node: &Rc<RefCell<Node>>, owner_document: &Weak<RefCell<Node>>) {
+    if node.borrow().owner_document.ptr_eq(owner_document) {
+        return;
+    }
+    node.borrow_mut().set_owner_document(owner_document.clone());
+    let descendants: Vec<Rc<RefCell<Node>>> = node.borrow().descendants().collect();
+    for descendant in descendants {
+        descendant.borrow_mut().set_owner_document(owner_document.clone());
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-node-remove
+/// ノードを親から取り除く。親がなければ何もしない
+pub fn detach(node: &Rc<RefCell<Node>>) {
+    let parent = match node.borrow().parent() {
+        Some(parent) => parent,
+        None => return,
+    };
+    // 取り除いた後では文書にあったかどうか分からないので、先に調べておく
+    let registry = registry_of(node).filter(|_| node.borrow().is_connected());
+    let previous = node.borrow().previous_sibling().upgrade();
+    let next = node.borrow().next_sibling();
+
+    match previous {
+        Some(ref previous) => previous.borrow_mut().set_next_sibling(next.clone()),
+        None => parent.borrow_mut().set_first_child(next.clone()),
+    }
+    let weak_previous = previous.as_ref().map_or_else(Weak::new, Rc::downgrade);
+    match next {
+        Some(ref next) => next.borrow_mut().set_previous_sibling(weak_previous),
+        None => parent.borrow_mut().set_last_child(weak_previous),
+    }
+
+    {
+        let mut node = node.borrow_mut();
+        node.set_parent(Weak::new());
+        node.set_previous_sibling(Weak::new());
+        node.set_next_sibling(None);
+    }
+    queue_child_list_record(&parent, Vec::new(), vec![node.clone()], previous, next);
+    if let Some(registry) = registry {
+        notify_disconnected(node, &registry);
+    }
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-setattribute
+/// `Element::set_attribute()` と同じだが、木の中の要素ではオブザーバーに変更を知らせる
+pub fn set_attribute(node: &Rc<RefCell<Node>>, name: &str, value: &str) {
+    let old_value = match node.borrow_mut().get_element_mut() {
+        Some(element) => {
+            let old_value = element.get_attribute(name);
+            element.set_attribute(name, value);
+            old_value
+        }
+        None => return,
+    };
+    queue_attribute_record(node, name, old_value);
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-removeattribute
+/// 属性がなければ何もしない
+pub fn remove_attribute(node: &Rc<RefCell<Node>>, name: &str) {
+    let old_value = match node.borrow_mut().get_element_mut() {
+        Some(element) => match element.get_attribute(name) {
+            Some(old_value) => {
+                element.remove_attribute(name);
+                old_value
+            }
+            None => return,
+        },
+        None => return,
+    };
+    queue_attribute_record(node, name, Some(old_value));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    kind: ElementKind,
+    local_name: String,
+    namespace: Namespace,
+    attributes: NamedNodeMap,
+}
+
+impl Element {
+    /// 種類が定義されていない要素名の場合は `ElementKind::Unknown` になり、要素名だけを保持する。
+    /// 同じ名前の属性が複数あれば、最初のものだけを残す
+    pub fn new(element_name: &str, attributes: Vec<Attribute>) -> Self {
+        Self {
+            kind: ElementKind::from_str(element_name).unwrap_or(ElementKind::Unknown),
+            local_name: element_name.to_string(),
+            namespace: Namespace::Html,
+            attributes: NamedNodeMap::from(attributes),
+        }
+    }
+
+    /// SVGやMathMLの要素を作る。要素名は大文字と小文字を区別して保持する
+    pub fn new_foreign(element_name: &str, attributes: Vec<Attribute>, namespace: Namespace) -> Self {
+        let kind = match (namespace, element_name) {
+            (Namespace::Svg, "svg") => ElementKind::Svg,
+            (Namespace::MathMl, "math") => ElementKind::Math,
+            _ => ElementKind::Unknown,
+        };
+
+        Self {
+            kind,
+            local_name: element_name.to_string(),
+            namespace,
+            attributes: NamedNodeMap::from(attributes),
+        }
+    }
+
+    pub fn kind(&self) -> ElementKind {
+        self.kind
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-element-local-name
+    pub fn local_name(&self) -> String {
+        self.local_name.clone()
+    }
+
+    pub fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    /// https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name
+    /// `<my-widget>` のように要素名にハイフンを含む、カスタム要素として定義できる要素か
+    pub fn is_custom(&self) -> bool {
+        self.namespace == Namespace::Html && is_valid_custom_element_name(&self.local_name)
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-attributes
+    pub fn attributes(&self) -> NamedNodeMap {
+        self.attributes.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/interaction.html#attr-tabindex
+    /// フォーカスできない要素ではNoneを返す。tabindex属性がなければ、リンクやフォームの部品は0になる
+    pub fn tab_index(&self) -> Option<i64> {
+        if let Some(index) = self.get_attribute("tabindex").and_then(|v| v.trim().parse().ok()) {
+            return Some(index);
+        }
+
+        match self.kind {
+            ElementKind::A => self.href().map(|_| 0),
+            ElementKind::Input if self.get_attribute("type").is_some_and(|t| t.eq_ignore_ascii_case("hidden")) => None,
+            ElementKind::Input | ElementKind::Button | ElementKind::Textarea => {
+                match self.get_attribute("disabled") {
+                    Some(_) => None,
+                    None => Some(0),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-getattribute
+    pub fn get_attribute(&self, name: &str) -> Option<String> {
+        let name = self.normalize_attribute_name(name);
+        self.attributes.get_named_item(&name).map(|attribute| attribute.value())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-setattribute
+    /// 同じ名前の属性がすでにあれば、その位置のまま値を上書きする。
+    /// オブザーバーに知らせないので、クレートの外からは `node::set_attribute()` を使う
+    pub(crate) fn set_attribute(&mut self, name: &str, value: &str) {
+        let name = self.normalize_attribute_name(name);
+        let mut attribute = Attribute::new();
+        attribute.add_str(&name, true);
+        attribute.add_str(value, false);
+        self.attributes.set_named_item(attribute);
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-removeattribute
+    pub(crate) fn remove_attribute(&mut self, name: &str) {
+        let name = self.normalize_attribute_name(name);
+        self.attributes.remove_named_item(&name);
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-hasattribute
+    pub fn has_attribute(&self, name: &str) -> bool {
+        let name = self.normalize_attribute_name(name);
+        self.attributes.get_named_item(&name).is_some()
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-id
+    pub fn id(&self) -> Option<String> {
+        self.get_attribute("id")
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-element-classname
+    pub fn class_name(&self) -> Option<String> {
+        self.get_attribute("class")
+    }
+
+    /// class属性を空白で区切ったクラス名の一覧
+    pub fn class_list(&self) -> Vec<String> {
+        match self.class_name() {
+            Some(classes) => classes.split_ascii_whitespace().map(|c| c.to_string()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/links.html#attr-hyperlink-href
+    pub fn href(&self) -> Option<String> {
+        self.get_attribute("href")
+    }
+
+    /// https://html.spec.whatwg.org/multipage/embedded-content.html#attr-img-src
+    pub fn src(&self) -> Option<String> {
+        self.get_attribute("src")
+    }
+
+    /// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#encoding-parsing-a-url
+    /// <a>と<link>のhref属性、<img>のsrc属性、<form>のaction属性を `base` に対して解決する。
+    /// action属性がない<form>は `base` を返す。それ以外の要素や、属性がないか解決できない場合はNoneを返す
+    pub fn resolved_href(&self, base: &Url) -> Option<Url> {
+        let url = match self.kind {
+            ElementKind::A | ElementKind::Link => self.href()?,
+            ElementKind::Img => self.src()?,
+            ElementKind::Form => self.get_attribute("action").unwrap_or_default(),
+            _ => return None,
+        };
+        base.join(&url).ok()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/dom.html#the-style-attribute
+    pub fn style(&self) -> Option<String> {
+        self.get_attribute("style")
+    }
+
+    /// HTMLの要素では属性名の大文字と小文字を区別しない
+    fn normalize_attribute_name(&self, name: &str) -> String {
+        match self.namespace {
+            Namespace::Html => name.to_ascii_lowercase(),
+            _ => name.to_string(),
+        }
+    }
+}
+
+/// https://infra.spec.whatwg.org/#namespaces
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Namespace {
+    Html,
+    Svg,
+    MathMl,
+}
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#valid-custom-element-name
+pub fn is_valid_custom_element_name(name: &str) -> bool {
+    // SVGとMathMLで使われている、ハイフンを含む要素名
+    const RESERVED_NAMES: [&str; 8] = [
+        "annotation-xml",
+        "color-profile",
+        "font-face",
+        "font-face-src",
+        "font-face-uri",
+        "font-face-format",
+        "font-face-name",
+        "missing-glyph",
+    ];
+
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.contains('-')
+        && name.chars().all(is_potential_custom_element_name_char)
+        && !RESERVED_NAMES.contains(&name)
+}
+
+/// https://html.spec.whatwg.org/multipage/custom-elements.html#prod-pcenchar
+fn is_potential_custom_element_name_char(c: char) -> bool {
+    matches!(c,
+        '-' | '.' | '0'..='9' | '_' | 'a'..='z' | '\u{B7}'
+        | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}' | '\u{203F}'..='\u{2040}' | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}' | '\u{10000}'..='\u{EFFFF}')
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ElementKind {
+    Html,
+    Head,
+    Meta,
+    Base,
+    Title,
+    Link,
+    Style,
+    Script,
+    Noscript,
+    Template,
+    Body,
+    P,
+    Pre,
+    H1,
+    H2,
+    H3,
+    H4,
+    H5,
+    H6,
+    A,
+    B,
+    I,
+    Em,
+    Strong,
+    Div,
+    Span,
+    Xmp,
+    Plaintext,
+    Form,
+    Button,
+    Label,
+    Textarea,
+    Select,
+    Option,
+    Optgroup,
+    Ul,
+    Ol,
+    Li,
+    Dl,
+    Dt,
+    Dd,
+    Table,
+    Thead,
+    Tbody,
+    Tr,
+    Th,
+    Td,
+    Br,
+    Hr,
+    Img,
+    Input,
+    Audio,
+    Video,
+    Source,
+    Track,
+    Iframe,
+    Svg,
+    Math,
+    /// 種類を区別しない要素。要素名は `Element::local_name()` で取得する
+    Unknown,
+}
+
+impl FromStr for ElementKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(ElementKind::Html),
+            "head" => Ok(ElementKind::Head),
+            "meta" => Ok(ElementKind::Meta),
+            "base" => Ok(ElementKind::Base),
+            "title" => Ok(ElementKind::Title),
+            "link" => Ok(ElementKind::Link),
+            "style" => Ok(ElementKind::Style),
+            "script" => Ok(ElementKind::Script),
+            "noscript" => Ok(ElementKind::Noscript),
+            "template" => Ok(ElementKind::Template),
+            "body" => Ok(ElementKind::Body),
+            "p" => Ok(ElementKind::P),
+            "pre" => Ok(ElementKind::Pre),
+            "h1" => Ok(ElementKind::H1),
+            "h2" => Ok(ElementKind::H2),
+            "h3" => Ok(ElementKind::H3),
+            "h4" => Ok(ElementKind::H4),
+            "h5" => Ok(ElementKind::H5),
+            "h6" => Ok(ElementKind::H6),
+            "a" => Ok(ElementKind::A),
+            "b" => Ok(ElementKind::B),
+            "i" => Ok(ElementKind::I),
+            "em" => Ok(ElementKind::Em),
+            "strong" => Ok(ElementKind::Strong),
+            "div" => Ok(ElementKind::Div),
+            "span" => Ok(ElementKind::Span),
+            "xmp" => Ok(ElementKind::Xmp),
+            "plaintext" => Ok(ElementKind::Plaintext),
+            "form" => Ok(ElementKind::Form),
+            "button" => Ok(ElementKind::Button),
+            "label" => Ok(ElementKind::Label),
+            "textarea" => Ok(ElementKind::Textarea),
+            "select" => Ok(ElementKind::Select),
+            "option" => Ok(ElementKind::Option),
+            "optgroup" => Ok(ElementKind::Optgroup),
+            "ul" => Ok(ElementKind::Ul),
+            "ol" => Ok(ElementKind::Ol),
+            "li" => Ok(ElementKind::Li),
+            "dl" => Ok(ElementKind::Dl),
+            "dt" => Ok(ElementKind::Dt),
+            "dd" => Ok(ElementKind::Dd),
+            "table" => Ok(ElementKind::Table),
+            "thead" => Ok(ElementKind::Thead),
+            "tbody" => Ok(ElementKind::Tbody),
+            "tr" => Ok(ElementKind::Tr),
+            "th" => Ok(ElementKind::Th),
+            "td" => Ok(ElementKind::Td),
+            "br" => Ok(ElementKind::Br),
+            "hr" => Ok(ElementKind::Hr),
+            "img" => Ok(ElementKind::Img),
+            "input" => Ok(ElementKind::Input),
+            "audio" => Ok(ElementKind::Audio),
+            "video" => Ok(ElementKind::Video),
+            "source" => Ok(ElementKind::Source),
+            "track" => Ok(ElementKind::Track),
+            "iframe" => Ok(ElementKind::Iframe),
+            _ => Err(format!("Unimplemented element name {}", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::selector::query_selector;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    #[test]
+    fn test_collections() {
+        let html = "<html><head></head><body><form id=f1><input></form><p><a href=/a>a</a><a>b</a><img src=x.png></p><div><a href=/c><img name=y src=y.png></a></div><form></form></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let window = window.borrow();
+
+        assert_eq!(2, window.forms().len());
+
+        let links = window.links();
+        assert_eq!(2, links.len());
+        let hrefs: Vec<String> = links
+            .iter()
+            .map(|link| {
+                link.borrow()
+                    .get_element()
+                    .expect("Failed to get an element")
+                    .attributes()[0]
+                    .value()
+            })
+            .collect();
+        assert_eq!(vec!["/a".to_string(), "/c".to_string()], hrefs);
+
+        let images = window.images();
+        assert_eq!(2, images.len());
+        assert!(images.iter().all(|i| i.borrow().element_kind() == Some(ElementKind::Img)));
+        assert!(images.item(2).is_none());
+        assert!(Rc::ptr_eq(&images[1], &images.named_item("y").expect("Failed to find an image")));
+
+        let forms = window.forms();
+        assert!(Rc::ptr_eq(&forms.item(0).expect("Failed to get a form"), &forms.named_item("f1").expect("Failed to find a form")));
+        assert!(forms.named_item("").is_none());
+    }
+
+    #[test]
+    fn test_image_sources() {
+        let html = "<html><head></head><body><img src=a.png alt=\"A\"><p><img alt=none><img src=\"\"><img src=/b.gif></p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let window = window.borrow();
+
+        let first = window.images()[0]
+            .borrow()
+            .get_element()
+            .expect("Failed to get an element");
+        assert_eq!(Some("a.png".to_string()), first.get_attribute("src"));
+        assert_eq!(Some("A".to_string()), first.get_attribute("alt"));
+        assert_eq!(None, first.get_attribute("width"));
+
+        assert_eq!(vec!["a.png".to_string(), "/b.gif".to_string()], window.image_sources());
+    }
+
+    #[test]
+    fn test_document_title() {
+        let html = "<html><head><title>  a &amp; <b>\n c </title></head><body><title>second</title></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        assert_eq!("a & <b> c", window.borrow().title());
+
+        let html = "<html><head></head><body></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        assert_eq!("", window.borrow().title());
+    }
+
+    #[test]
+    fn test_document_accessors() {
+        let html = "<!DOCTYPE html><!--c--><html><head><title>t</title></head><body><p>a</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let window = window.borrow();
+
+        let html = window.document_element().expect("Failed to get the document element");
+        assert_eq!(Some(ElementKind::Html), html.borrow().element_kind());
+        let head = window.head().expect("Failed to get head");
+        assert_eq!(Some(ElementKind::Head), head.borrow().element_kind());
+        let body = window.body().expect("Failed to get body");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+        assert!(Rc::ptr_eq(&html, &body.borrow().parent().expect("Failed to get a parent")));
+
+        let window = Window::new();
+        assert!(window.document_element().is_none());
+        assert!(window.head().is_none());
+        assert!(window.body().is_none());
+    }
+
+    #[test]
+    fn test_owner_document() {
+        let html = "<html><head></head><body><p>a<b>b</b></p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        assert!(document.borrow().owner_document().is_none());
+        assert!(document.borrow().descendants().all(|n| n
+            .borrow()
+            .owner_document()
+            .is_some_and(|d| Rc::ptr_eq(&d, &document))));
+
+        // 挿入したときに、子孫も含めて文書が設定される
+        let div = element("div");
+        let span = element("span");
+        append_child(&div, &span).expect("Failed to append a child");
+        assert!(span.borrow().owner_document().is_none());
+        let body = window.borrow().body().expect("Failed to get body");
+        append_child(&body, &div).expect("Failed to append a child");
+        assert!(span.borrow().owner_document().is_some_and(|d| Rc::ptr_eq(&d, &document)));
+
+        // 取り除いても文書は変わらない
+        remove_child(&body, &div).expect("Failed to remove a child");
+        assert!(div.borrow().owner_document().is_some_and(|d| Rc::ptr_eq(&d, &document)));
+
+        let fragment = window.borrow().create_document_fragment();
+        assert!(fragment.borrow().owner_document().is_some_and(|d| Rc::ptr_eq(&d, &document)));
+    }
+
+    #[test]
+    fn test_base_url() {
+        let document_url = Url::new("http://example.com/dir/page.html".to_string()).expect("Failed to parse a URL");
+
+        let html = "<html><head><base target=_blank><base href=\"/static/\"><base href=\"/other/\"></head><body></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let base_url = window.borrow().base_url(&document_url);
+        assert_eq!("static/", base_url.path());
+        let link = base_url.join("a.html").expect("Failed to resolve a URL");
+        assert_eq!("static/a.html", link.path());
+
+        let html = "<html><head></head><body></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        assert_eq!(document_url, window.borrow().base_url(&document_url));
+    }
+
+    #[test]
+    fn test_custom_elements() {
+        assert!(is_valid_custom_element_name("my-widget"));
+        assert!(is_valid_custom_element_name("x-1.0_é"));
+        assert!(!is_valid_custom_element_name("widget"));
+        assert!(!is_valid_custom_element_name("-widget"));
+        assert!(!is_valid_custom_element_name("1-widget"));
+        assert!(!is_valid_custom_element_name("font-face"));
+
+        // 大文字は字句解析器で小文字になり、要素名はそのまま残る
+        let html = "<html><head></head><body><My-Widget size=2><span>a</span></my-widget><section></section></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let body = window
+            .borrow()
+            .collect_elements(|element| element.kind() == ElementKind::Body)
+            .pop()
+            .expect("Failed to find body");
+        let widget = body.borrow().first_child().expect("Failed to get a first child of body");
+        let element = widget.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(ElementKind::Unknown, element.kind());
+        assert_eq!("my-widget", element.local_name());
+        assert!(element.is_custom());
+        assert!(widget.borrow().first_child().is_some());
+
+        let section = widget.borrow().next_sibling().expect("Failed to get a next sibling of my-widget");
+        assert!(!section.borrow().get_element().expect("Failed to get an element").is_custom());
+    }
+
+    #[test]
+    fn test_attribute_api() {
+        let html = "<html><head></head><body><p id=a CLASS=x></p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let p = window.borrow().collect_elements(|element| element.kind() == ElementKind::P)[0].clone();
+
+        {
+            let element = p.borrow().get_element().expect("Failed to get an element");
+            assert!(element.has_attribute("Class"));
+            assert_eq!(Some("x".to_string()), element.get_attribute("class"));
+        }
+
+        set_attribute(&p, "ID", "b");
+        set_attribute(&p, "title", "t");
+        remove_attribute(&p, "class");
+
+        // 木の中の要素が書き換わっていて、上書きした属性は元の位置に残る
+        let element = p.borrow().get_element().expect("Failed to get an element");
+        assert!(!element.has_attribute("class"));
+        let names: Vec<String> = element.attributes().iter().map(|a| a.name()).collect();
+        assert_eq!(vec!["id".to_string(), "title".to_string()], names);
+        assert_eq!(Some("b".to_string()), element.get_attribute("id"));
+    }
+
+    #[test]
+    fn test_named_node_map() {
+        let attribute = |name: &str, value: &str| {
+            let mut attribute = Attribute::new();
+            attribute.add_str(name, true);
+            attribute.add_str(value, false);
+            attribute
+        };
+
+        // 同じ名前の属性は最初のものだけが残り、順序は書かれたまま
+        let element = Element::new("a", vec![attribute("b", "1"), attribute("a", "2"), attribute("b", "3")]);
+        let mut attributes = element.attributes();
+        assert_eq!(2, attributes.len());
+        assert_eq!("b", attributes[0].name());
+        assert_eq!(Some("2".to_string()), attributes.item(1).map(|a| a.value()));
+        assert!(attributes.item(2).is_none());
+        assert_eq!(Some("1".to_string()), attributes.get_named_item("b").map(|a| a.value()));
+
+        assert!(attributes.append(attribute("a", "4")).is_err());
+        attributes.append(attribute("c", "5")).expect("Failed to append an attribute");
+        let old = attributes.set_named_item(attribute("b", "6"));
+        assert_eq!(Some("1".to_string()), old.map(|a| a.value()));
+        assert_eq!(Some("2".to_string()), attributes.remove_named_item("a").map(|a| a.value()));
+        assert!(attributes.remove_named_item("a").is_none());
+
+        let pairs: Vec<(String, String)> = attributes.into_iter().map(|a| (a.name(), a.value())).collect();
+        assert_eq!(vec![("b".to_string(), "6".to_string()), ("c".to_string(), "5".to_string())], pairs);
+    }
+
+    #[test]
+    fn test_attribute_accessors() {
+        let html = "<html><head></head><body><a id=x class=\" a  b \" href=/p style=\"color:red\"><img src=i.png></a></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let link = window.borrow().links()[0].borrow().get_element().expect("Failed to get an element");
+        assert_eq!(Some("x".to_string()), link.id());
+        assert_eq!(Some(" a  b ".to_string()), link.class_name());
+        assert_eq!(vec!["a".to_string(), "b".to_string()], link.class_list());
+        assert_eq!(Some("/p".to_string()), link.href());
+        assert_eq!(Some("color:red".to_string()), link.style());
+        assert_eq!(None, link.src());
+
+        let image = window.borrow().images()[0].borrow().get_element().expect("Failed to get an element");
+        assert_eq!(Some("i.png".to_string()), image.src());
+        assert!(image.class_list().is_empty());
+    }
+
+    #[test]
+    fn test_resolved_href() {
+        let html = "<html><head><link rel=stylesheet href=../s.css></head><body><a href=/p>p</a><a>no href</a><img src=i.png><form></form><form action=\"post?x=1\"></form><p>text</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let base = Url::new("http://example.com/dir/index.html".to_string()).expect("Failed to parse a URL");
+        let resolved: Vec<Option<Url>> = window
+            .borrow()
+            .document()
+            .borrow()
+            .descendants()
+            .filter_map(|n| n.borrow().get_element())
+            .filter(|e| e.kind() != ElementKind::Html && e.kind() != ElementKind::Head && e.kind() != ElementKind::Body)
+            .map(|e| e.resolved_href(&base))
+            .collect();
+        let paths: Vec<Option<String>> = resolved.iter().map(|url| url.as_ref().map(|url| url.path())).collect();
+        assert_eq!(
+            vec![
+                Some("s.css".to_string()),
+                Some("p".to_string()),
+                None,
+                Some("dir/i.png".to_string()),
+                Some("dir/index.html".to_string()),
+                Some("dir/post".to_string()),
+                None,
+            ],
+            paths
+        );
+        assert_eq!(Some("x=1".to_string()), resolved[5].as_ref().map(|url| url.searchpart()));
+    }
+
+    fn children(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+        let mut children = Vec::new();
+        let mut child = node.borrow().first_child();
+        while let Some(c) = child {
+            child = c.borrow().next_sibling();
+            children.push(c);
+        }
+
+        // 逆向きにたどっても同じ順序になる
+        let mut reversed = Vec::new();
+        let mut child = node.borrow().last_child().upgrade();
+        while let Some(c) = child {
+            assert!(Rc::ptr_eq(&c.borrow().parent().expect("Failed to get a parent"), node));
+            child = c.borrow().previous_sibling().upgrade();
+            reversed.push(c);
+        }
+        reversed.reverse();
+        assert_eq!(children.len(), reversed.len());
+        assert!(children.iter().zip(reversed.iter()).all(|(a, b)| Rc::ptr_eq(a, b)));
+
+        children
+    }
+
+    fn element(name: &str) -> Rc<RefCell<Node>> {
+        Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))))
+    }
+
+    /// 部分木のすべてのノードで、親・子・兄弟のリンクが互いに一致することを確かめる
+    fn assert_tree_invariants(node: &Rc<RefCell<Node>>) {
+        let children = children(node);
+        match children.first() {
+            Some(first) => {
+                assert!(first.borrow().previous_sibling().upgrade().is_none());
+                assert!(Rc::ptr_eq(first, &node.borrow().first_child().expect("Failed to get a first child")));
+            }
+            None => assert!(node.borrow().last_child().upgrade().is_none()),
+        }
+        if let Some(last) = children.last() {
+            assert!(last.borrow().next_sibling().is_none());
+        }
+        for child in children.iter() {
+            assert_tree_invariants(child);
+        }
+        if let Some(content) = node.borrow().template_content() {
+            assert_tree_invariants(&content);
+        }
+    }
+
+    fn names(node: &Rc<RefCell<Node>>) -> Vec<String> {
+        children(node)
+            .iter()
+            .map(|c| c.borrow().get_element().expect("Failed to get an element").local_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_mutation() {
+        let parent = element("div");
+        let (a, b, c) = (element("a"), element("b"), element("p"));
+
+        append_child(&parent, &a).expect("Failed to append a child");
+        append_child(&parent, &c).expect("Failed to append a child");
+        insert_before(&parent, &b, Some(&c)).expect("Failed to insert a child");
+        assert_eq!(vec!["a", "b", "p"], names(&parent));
+
+        // 挿入済みのノードを追加すると移動になる
+        append_child(&parent, &a).expect("Failed to append a child");
+        assert_eq!(vec!["b", "p", "a"], names(&parent));
+
+        let span = element("span");
+        assert!(Rc::ptr_eq(&b, &replace_child(&parent, &span, &b).expect("Failed to replace a child")));
+        assert_eq!(vec!["span", "p", "a"], names(&parent));
+        assert!(b.borrow().parent().is_none());
+
+        remove_child(&parent, &a).expect("Failed to remove a child");
+        assert_eq!(vec!["span", "p"], names(&parent));
+        remove_child(&parent, &span).expect("Failed to remove a child");
+        remove_child(&parent, &c).expect("Failed to remove a child");
+        assert!(names(&parent).is_empty());
+        assert!(parent.borrow().last_child().upgrade().is_none());
+
+        // 文書片を挿入すると、その子が移される
+        let fragment = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+        append_child(&fragment, &a).expect("Failed to append a child");
+        append_child(&fragment, &b).expect("Failed to append a child");
+        append_child(&parent, &c).expect("Failed to append a child");
+        insert_before(&parent, &fragment, Some(&c)).expect("Failed to insert a fragment");
+        assert_eq!(vec!["a", "b", "p"], names(&parent));
+        assert!(fragment.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_tree_invariants() {
+        // 決まった種から作った疑似乱数で、さまざまな順序の変更を試す
+        let mut seed: u32 = 12345;
+        let mut random = |n: usize| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) as usize % n
+        };
+
+        let nodes: Vec<Rc<RefCell<Node>>> = (0..12)
+            .map(|i| match i % 3 {
+                0 => Rc::new(RefCell::new(Node::new(NodeKind::Text(format!("t{}", i))))),
+                _ => element(&format!("e{}", i)),
+            })
+            .collect();
+
+        for _ in 0..500 {
+            let parent = &nodes[random(nodes.len())];
+            let node = &nodes[random(nodes.len())];
+            let siblings = children(parent);
+            let child = match siblings.len() {
+                0 => None,
+                len => siblings.get(random(len + 1)).cloned(),
+            };
+
+            // 祖先を子孫に挿入するような、エラーになる変更も混ざる
+            let _ = match (random(4), child) {
+                (0, _) => append_child(parent, node),
+                (1, child) => insert_before(parent, node, child.as_ref()),
+                (2, Some(child)) => remove_child(parent, &child),
+                (3, Some(child)) => replace_child(parent, node, &child),
+                _ => append_child(parent, node),
+            };
+
+            for node in nodes.iter().filter(|n| n.borrow().parent().is_none()) {
+                assert_tree_invariants(node);
+            }
+        }
+
+        // 構文解析器は文字の追加も要素の挿入も同じ操作で行う
+        for html in [
+            "<html><head></head><body>a<b>b</b>c<p>d<i>e</p>f</i></body></html>",
+            "<html><head></head><body><table>a<tr><td>b</td></tr>c</table>d</body></html>",
+            "<html><head><template><p>a</p>b</template></head><body><b>1<p>2</b>3</p></body></html>",
+        ] {
+            let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+            assert_tree_invariants(&window.borrow().document());
+        }
+    }
+
+    #[test]
+    fn test_shadow_root() {
+        let html = "<html><head></head><body><div id=host>light</div><my-widget></my-widget><a></a></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let host = window.borrow().collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+
+        let shadow_root = attach_shadow(&host, ShadowRootMode::Open).expect("Failed to attach a shadow root");
+        let inner = element("span");
+        append_child(&shadow_root, &inner).expect("Failed to append a child");
+        append_child(&inner, &Rc::new(RefCell::new(Node::new(NodeKind::Text("shadow".to_string())))))
+            .expect("Failed to append a child");
+
+        assert!(Rc::ptr_eq(&shadow_root, &host.borrow().shadow_root().expect("Failed to get a shadow root")));
+        assert!(Rc::ptr_eq(&host, &shadow_root.borrow().host().expect("Failed to get a host")));
+        assert!(inner.borrow().owner_document().is_some_and(|d| Rc::ptr_eq(&d, &document)));
+
+        // シャドウツリーはホストの子孫として扱わない
+        assert!(shadow_root.borrow().parent().is_none());
+        assert_eq!(Some("light".to_string()), host.borrow().text_content());
+        assert_eq!("light", host.borrow().inner_html());
+        assert!(!document.borrow().descendants().any(|n| Rc::ptr_eq(&n, &inner)));
+        assert!(query_selector(&document, "span").expect("Failed to parse a selector").is_none());
+        assert_eq!("<span>shadow</span>", shadow_root.borrow().inner_html());
+
+        assert!(attach_shadow(&host, ShadowRootMode::Open).is_err());
+        let body = window.borrow().body().expect("Failed to get body");
+        assert!(append_child(&body, &shadow_root).is_err());
+        let anchor = body.borrow().last_element_child().expect("Failed to get a last element child");
+        assert!(attach_shadow(&anchor, ShadowRootMode::Open).is_err());
+
+        // 閉じたシャドウルートは `shadow_root()` から見えない
+        let widget = anchor.borrow().previous_element_sibling().expect("Failed to get a previous element sibling");
+        let closed = attach_shadow(&widget, ShadowRootMode::Closed).expect("Failed to attach a shadow root");
+        assert!(widget.borrow().shadow_root().is_none());
+        assert!(Rc::ptr_eq(&closed, &widget.borrow().attached_shadow_root().expect("Failed to get a shadow root")));
+    }
+
+    #[test]
+    fn test_document_fragment() {
+        let window = Window::new();
+        let fragment = window.create_document_fragment();
+        assert_eq!(NodeKind::DocumentFragment, fragment.borrow().kind());
+        for name in ["a", "b"] {
+            append_child(&fragment, &element(name)).expect("Failed to append a child");
+        }
+        assert_eq!(Some(String::new()), fragment.borrow().text_content());
+        assert_eq!("<a></a><b></b>", fragment.borrow().inner_html());
+
+        // 置き換えると、文書片の子が置き換えた位置に並ぶ
+        let parent = element("div");
+        let (x, y) = (element("x"), element("y"));
+        append_child(&parent, &x).expect("Failed to append a child");
+        append_child(&parent, &y).expect("Failed to append a child");
+        replace_child(&parent, &fragment, &x).expect("Failed to replace a child");
+        assert_eq!(vec!["a", "b", "y"], names(&parent));
+        assert!(fragment.borrow().first_child().is_none());
+        assert!(fragment.borrow().last_child().upgrade().is_none());
+
+        // 空の文書片を挿入しても何も変わらない
+        append_child(&parent, &fragment).expect("Failed to append a fragment");
+        assert_eq!(vec!["a", "b", "y"], names(&parent));
+    }
+
+    #[test]
+    fn test_mutation_errors() {
+        let parent = element("div");
+        let child = element("p");
+        append_child(&parent, &child).expect("Failed to append a child");
+
+        assert!(append_child(&child, &parent).is_err());
+        assert!(append_child(&parent, &parent).is_err());
+        assert!(remove_child(&child, &parent).is_err());
+        assert!(insert_before(&parent, &element("a"), Some(&element("b"))).is_err());
+
+        let text = Rc::new(RefCell::new(Node::new(NodeKind::Text("t".to_string()))));
+        assert!(append_child(&text, &element("a")).is_err());
+        let document = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+        assert!(append_child(&document, &text).is_err());
+        assert!(append_child(&parent, &document).is_err());
+        assert_eq!(vec!["p"], names(&parent));
+    }
+
+    #[test]
+    fn test_text_content() {
+        let html = "<html><head><title>t</title></head><body><p>a<b>b<i>c</i></b>d</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(None, document.borrow().text_content());
+
+        let html_element = document.borrow().first_child().expect("Failed to get a first child");
+        assert_eq!(Some("tabcd".to_string()), html_element.borrow().text_content());
+
+        let p = window.borrow().collect_elements(|element| element.kind() == ElementKind::P)[0].clone();
+        set_text_content(&p, "x<y");
+        let children = children(&p);
+        assert_eq!(1, children.len());
+        assert_eq!(Some("x<y".to_string()), children[0].borrow().text_content());
+        assert_eq!(Some("tx<y".to_string()), html_element.borrow().text_content());
+
+        set_text_content(&children[0], "z");
+        assert_eq!(Some("z".to_string()), p.borrow().text_content());
+
+        set_text_content(&p, "");
+        assert!(p.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_inner_html() {
+        let html = "<html><head><style>a > b {}</style></head><body><p class='a\"b' data-x=\"&amp;\">x &lt; y&nbsp;<br><img src=i.png>z</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let p = window.borrow().collect_elements(|element| element.kind() == ElementKind::P)[0].clone();
+        assert_eq!(
+            "x &lt; y&nbsp;<br><img src=\"i.png\">z",
+            p.borrow().inner_html()
+        );
+
+        let body = p.borrow().parent().expect("Failed to get a parent");
+        assert_eq!(
+            "<p class=\"a&quot;b\" data-x=\"&amp;\">x &lt; y&nbsp;<br><img src=\"i.png\">z</p>",
+            body.borrow().inner_html()
+        );
+
+        let style = window.borrow().collect_elements(|element| element.kind() == ElementKind::Style)[0].clone();
+        assert_eq!("a > b {}", style.borrow().inner_html());
+
+        set_inner_html(&p, "<b>bold</b>, & <i>it");
+        assert_eq!("<b>bold</b>, &amp; <i>it</i>", p.borrow().inner_html());
+        assert_eq!(Some("bold, & it".to_string()), p.borrow().text_content());
+        let b = p.borrow().first_child().expect("Failed to get a first child");
+        assert!(Rc::ptr_eq(&p, &b.borrow().parent().expect("Failed to get a parent")));
+
+        set_inner_html(&p, "");
+        assert!(p.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_outer_html() {
+        let html = "<html><head></head><body><div id=a title=\"x&y\" class=b>t<script>if (a < b) {}</script><input type=text></div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let div = window.borrow().collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+
+        let expected = "<div id=\"a\" title=\"x&amp;y\" class=\"b\">t<script>if (a < b) {}</script><input type=\"text\"></div>";
+        assert_eq!(expected, div.borrow().outer_html());
+        assert_eq!(expected, format!("{}", div.borrow()));
+
+        let input = div.borrow().last_child().upgrade().expect("Failed to get a last child");
+        assert_eq!("<input type=\"text\">", input.borrow().outer_html());
+        let text = div.borrow().first_child().expect("Failed to get a first child");
+        assert_eq!("t", text.borrow().to_string());
+
+        let document = window.borrow().document();
+        assert_eq!(document.borrow().inner_html(), document.borrow().outer_html());
+
+        // SVGの要素には空要素も生テキストの要素もなく、要素名の大文字も保つ
+        let html = "<html><head></head><body><svg viewBox=\"0 0 1 1\"><linearGradient id=g></lineargradient><style>a &lt; b</style><image href=x /></svg></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let svg = window.borrow().collect_elements(|element| element.kind() == ElementKind::Svg)[0].clone();
+        assert_eq!(
+            "<svg viewBox=\"0 0 1 1\"><linearGradient id=\"g\"></linearGradient><style>a &lt; b</style><image href=\"x\"></image></svg>",
+            svg.borrow().outer_html()
+        );
+    }
+
+    #[test]
+    fn test_clone_node() {
+        let html = "<html><head></head><body><div id=a><p>x<b>y</b></p><template><i>z</i></template></div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let div = window.borrow().collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+
+        let shallow = div.borrow().clone_node(false);
+        assert_eq!("<div id=\"a\"></div>", shallow.borrow().outer_html());
+        assert!(shallow.borrow().parent().is_none());
+
+        let deep = div.borrow().clone_node(true);
+        assert_eq!(div.borrow().outer_html(), deep.borrow().outer_html());
+        assert!(deep.borrow().parent().is_none());
+        assert!(deep.borrow().next_sibling().is_none());
+
+        // 複製を書き換えても元のノードは変わらない
+        let p = deep.borrow().first_child().expect("Failed to get a first child");
+        set_text_content(&p, "changed");
+        set_attribute(&p, "class", "c");
+        assert_eq!("<p>x<b>y</b></p><template><i>z</i></template>", div.borrow().inner_html());
+        assert_eq!("<p class=\"c\">changed</p><template><i>z</i></template>", deep.borrow().inner_html());
+
+        let template = div.borrow().last_child().upgrade().expect("Failed to get a last child");
+        let original_content = template.borrow().template_content().expect("Failed to get template content");
+        let copied_content = template.borrow().clone_node(true).borrow().template_content().expect("Failed to get template content");
+        assert!(!Rc::ptr_eq(&original_content, &copied_content));
+        let empty = template.borrow().clone_node(false).borrow().template_content().expect("Failed to get template content");
+        assert!(empty.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_deep_equals() {
+        let parse = |html: &str| {
+            let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+            let body = window.borrow().body();
+            body.expect("Failed to get body")
+        };
+
+        // 期待する木を組み立てて、解析結果の木全体と比べる
+        let expected = element("body");
+        let p = element("p");
+        set_attribute(&p, "class", "a");
+        append_child(&expected, &p).expect("Failed to append a child");
+        append_child(&p, &Rc::new(RefCell::new(Node::new(NodeKind::Text("x".to_string()))))).expect("Failed to append a child");
+        append_child(&expected, &element("br")).expect("Failed to append a child");
+        assert!(parse("<html><head></head><body><p class=a>x</p><br></body></html>").borrow().deep_equals(&expected.borrow()));
+
+        // 属性の順序は問わない
+        let a = parse("<html><head></head><body><a id=x href=/y>t</a></body></html>");
+        let b = parse("<html><head></head><body><a href=/y id=x>t</a></body></html>");
+        assert!(a.borrow().deep_equals(&b.borrow()));
+
+        // `PartialEq` では区別しない違いも区別する
+        for html in [
+            "<html><head></head><body><a id=x href=/z>t</a></body></html>",
+            "<html><head></head><body><a id=x href=/y>u</a></body></html>",
+            "<html><head></head><body><a id=x href=/y>t</a><br></body></html>",
+            "<html><head></head><body><a id=x href=/y>t<!--c--></a></body></html>",
+        ] {
+            let other = parse(html);
+            assert!(*a.borrow() == *other.borrow());
+            assert!(!a.borrow().deep_equals(&other.borrow()), "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_iterators() {
+        let html = "<html><head></head><body><div>a<p id=p>b<b id=b>c</b></p>d<i id=i></i></div><span id=s></span></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let div = window.borrow().collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+
+        assert_eq!(4, div.borrow().child_nodes().count());
+        let ids: Vec<String> = div
+            .borrow()
+            .children()
+            .filter_map(|c| c.borrow().get_element()?.id())
+            .collect();
+        assert_eq!(vec!["p".to_string(), "i".to_string()], ids);
+
+        // 子孫は行きがけ順で、<div>の兄弟の<span>は含めない
+        let descendants: Vec<String> = div
+            .borrow()
+            .descendants()
+            .map(|node| match node.borrow().get_element() {
+                Some(element) => element.local_name(),
+                None => node.borrow().text_content().unwrap_or_default(),
+            })
+            .collect();
+        assert_eq!(vec!["a", "p", "b", "b", "c", "d", "i"], descendants);
+
+        let b = div.borrow().descendants().find(|n| n.borrow().element_kind() == Some(ElementKind::B)).expect("Failed to find <b>");
+        let ancestors: Vec<NodeKind> = b.borrow().ancestors().map(|n| n.borrow().kind()).collect();
+        assert_eq!(5, ancestors.len());
+        assert!(Rc::ptr_eq(&div, &b.borrow().ancestors().nth(1).expect("Failed to get an ancestor")));
+        let p = b.borrow().parent().expect("Failed to get a parent");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+        assert!(window.borrow().document().borrow().parent().is_none());
+        assert_eq!(NodeKind::Document, ancestors[4]);
+    }
+
+    #[test]
+    fn test_element_navigation() {
+        let html = "<html><head></head><body><div>a<p id=p>b</p><!--c-->d<i id=i></i>e</div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let div = window.borrow().collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+        let id = |node: Option<Rc<RefCell<Node>>>| node.and_then(|n| n.borrow().get_element()?.id());
+
+        assert_eq!(Some("p".to_string()), id(div.borrow().first_element_child()));
+        assert_eq!(Some("i".to_string()), id(div.borrow().last_element_child()));
+
+        let p = div.borrow().first_element_child().expect("Failed to get a first element child");
+        assert_eq!(Some("i".to_string()), id(p.borrow().next_element_sibling()));
+        assert!(p.borrow().previous_element_sibling().is_none());
+        assert!(p.borrow().first_element_child().is_none());
+
+        let i = div.borrow().last_element_child().expect("Failed to get a last element child");
+        assert_eq!(Some("p".to_string()), id(i.borrow().previous_element_sibling()));
+        assert!(i.borrow().next_element_sibling().is_none());
+
+        // テキストノードからも兄弟の要素をたどれる
+        let a = div.borrow().first_child().expect("Failed to get a first child");
+        assert_eq!(Some("p".to_string()), id(a.borrow().next_element_sibling()));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let text = |s: &str| Rc::new(RefCell::new(Node::new(NodeKind::Text(s.to_string()))));
+        let parent = element("div");
+        let b = element("b");
+        for node in [text(""), text("a"), text("b"), b.clone(), text("c"), text(""), text("d")] {
+            append_child(&parent, &node).expect("Failed to append a child");
+        }
+        for node in [text("x"), text(""), text("y")] {
+            append_child(&b, &node).expect("Failed to append a child");
+        }
+        let only_empty = element("i");
+        append_child(&parent, &only_empty).expect("Failed to append a child");
+        append_child(&only_empty, &text("")).expect("Failed to append a child");
+
+        normalize(&parent);
+
+        let contents: Vec<Option<String>> = children(&parent).iter().map(|c| c.borrow().text_content()).collect();
+        let expected: Vec<Option<String>> = ["ab", "xy", "cd", ""].iter().map(|s| Some(s.to_string())).collect();
+        assert_eq!(expected, contents);
+        assert_eq!(1, children(&b).len());
+        assert!(only_empty.borrow().first_child().is_none());
+    }
+
+    #[test]
+    fn test_focus_navigation() {
+        let html = "<html><head></head><body><a href=/a>a</a><p tabindex=2>p</p><input disabled><input type=hidden><button>b</button><div tabindex=1>d</div><span tabindex=-1>s</span></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let mut window = window.borrow_mut();
+
+        let mut order = Vec::new();
+        for _ in 0..5 {
+            let focused = window.focus_next().expect("Failed to move focus");
+            order.push(focused.borrow().element_kind());
+        }
+        assert_eq!(
+            vec![
+                Some(ElementKind::Div),
+                Some(ElementKind::P),
+                Some(ElementKind::A),
+                Some(ElementKind::Button),
+                Some(ElementKind::Div),
+            ],
+            order
+        );
+
+        let previous = window.focus_previous().expect("Failed to move focus");
+        assert_eq!(Some(ElementKind::Button), previous.borrow().element_kind());
+
+        // tabindexが負の要素は、順番には含まれないがフォーカスできる
+        let span = window.collect_elements(|element| element.kind() == ElementKind::Span)[0].clone();
+        assert!(window.focus(&span));
+        let body = window.collect_elements(|element| element.kind() == ElementKind::Body)[0].clone();
+        assert!(!window.focus(&body));
+        assert!(Rc::ptr_eq(&span, &window.focused_element().expect("Failed to get a focused element")));
+
+        window.blur();
+        assert!(window.focused_element().is_none());
+    }
+
+    #[test]
+    fn test_focus_events() {
+        let html = "<html><head></head><body><div><input id=a><input id=b></div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let mut window = window.borrow_mut();
+        let inputs = window.collect_elements(|element| element.kind() == ElementKind::Input);
+        let div = window.collect_elements(|element| element.kind() == ElementKind::Div)[0].clone();
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        for node in inputs.iter().chain([&div]) {
+            for event_type in ["focus", "blur"] {
+                let log = log.clone();
+                node.borrow_mut().add_event_listener(
+                    event_type,
+                    move |event: &mut Event| {
+                        let target = event.target().expect("Failed to get a target");
+                        let current = event.current_target().expect("Failed to get a current target");
+                        let id =
+                            |n: &Rc<RefCell<Node>>| n.borrow().get_element().and_then(|e| e.id()).unwrap_or_default();
+                        log.borrow_mut().push(format!("{} {} at {}", event.event_type(), id(&target), id(&current)));
+                    },
+                    false,
+                );
+            }
+        }
+
+        assert!(window.focus(&inputs[0]));
+        // 同じ要素にもう一度フォーカスしても何も送らない
+        assert!(window.focus(&inputs[0]));
+        window.focus_next();
+        window.blur();
+        // 浮上しないので、親の<div>のリスナーは呼び出されない
+        assert_eq!(vec!["focus a at a", "blur a at a", "focus b at b", "blur b at b"], *log.borrow());
     }
 }
@@ -0,0 +1,567 @@
+use crate::renderer::dom::node::{insert_child, Node, NodeKind};
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::cmp::Ordering;
+
+/// https://dom.spec.whatwg.org/#dom-node-comparedocumentposition
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DocumentPosition {
+    Equal,
+    /// 2つのノードが同じツリーに属していない
+    Disconnected,
+    /// `other` は `node` より前にある
+    Preceding,
+    /// `other` は `node` より後にある
+    Following,
+    /// `other` は `node` の祖先
+    Contains,
+    /// `other` は `node` の子孫
+    ContainedBy,
+}
+
+/// `node` から見た `other` の位置を返す
+pub fn compare_document_position(
+    node: &Rc<RefCell<Node>>,
+    other: &Rc<RefCell<Node>>,
+) -> DocumentPosition {
+    if Rc::ptr_eq(node, other) {
+        return DocumentPosition::Equal;
+    }
+
+    let node_ancestors = inclusive_ancestors(node);
+    let other_ancestors = inclusive_ancestors(other);
+    if !Rc::ptr_eq(&node_ancestors[0], &other_ancestors[0]) {
+        return DocumentPosition::Disconnected;
+    }
+
+    let common = node_ancestors
+        .iter()
+        .zip(other_ancestors.iter())
+        .take_while(|(a, b)| Rc::ptr_eq(a, b))
+        .count();
+
+    if common == node_ancestors.len() {
+        return DocumentPosition::ContainedBy;
+    }
+    if common == other_ancestors.len() {
+        return DocumentPosition::Contains;
+    }
+
+    if index(&node_ancestors[common]) < index(&other_ancestors[common]) {
+        DocumentPosition::Following
+    } else {
+        DocumentPosition::Preceding
+    }
+}
+
+/// https://dom.spec.whatwg.org/#dom-node-contains
+/// `other` が `node` 自身か、その子孫の場合にtrueを返す
+pub fn contains(node: &Rc<RefCell<Node>>, other: &Rc<RefCell<Node>>) -> bool {
+    is_inclusive_ancestor(node, other)
+}
+
+/// https://dom.spec.whatwg.org/#concept-tree-order
+/// ノードを文書順に並べ替える。ルートからの子の位置の列を比べるので、祖先は子孫より前になる
+pub fn sort_in_tree_order(nodes: &mut [Rc<RefCell<Node>>]) {
+    nodes.sort_by_cached_key(|node| inclusive_ancestors(node).iter().map(index).collect::<Vec<usize>>());
+}
+
+/// https://dom.spec.whatwg.org/#concept-range
+/// 境界点のオフセットは、テキストノードでは文字の位置、それ以外では子ノードの位置を表す
+#[derive(Debug, Clone)]
+pub struct Range {
+    start_container: Rc<RefCell<Node>>,
+    start_offset: usize,
+    end_container: Rc<RefCell<Node>>,
+    end_offset: usize,
+}
+
+impl Range {
+    pub fn new(container: Rc<RefCell<Node>>, offset: usize) -> Self {
+        Self {
+            start_container: container.clone(),
+            start_offset: offset,
+            end_container: container,
+            end_offset: offset,
+        }
+    }
+
+    pub fn start_container(&self) -> Rc<RefCell<Node>> {
+        self.start_container.clone()
+    }
+
+    pub fn start_offset(&self) -> usize {
+        self.start_offset
+    }
+
+    pub fn end_container(&self) -> Rc<RefCell<Node>> {
+        self.end_container.clone()
+    }
+
+    pub fn end_offset(&self) -> usize {
+        self.end_offset
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-range-bp-set
+    /// 終了位置より後ろ、または別のツリーに開始位置を設定した場合は、範囲を開始位置に縮める
+    pub fn set_start(&mut self, container: Rc<RefCell<Node>>, offset: usize) {
+        let offset = offset.min(node_length(&container));
+        if compare_boundary_points(&container, offset, &self.end_container, self.end_offset)
+            != Some(Ordering::Less)
+        {
+            self.end_container = container.clone();
+            self.end_offset = offset;
+        }
+        self.start_container = container;
+        self.start_offset = offset;
+    }
+
+    pub fn set_end(&mut self, container: Rc<RefCell<Node>>, offset: usize) {
+        let offset = offset.min(node_length(&container));
+        if compare_boundary_points(&container, offset, &self.start_container, self.start_offset)
+            != Some(Ordering::Greater)
+        {
+            self.start_container = container.clone();
+            self.start_offset = offset;
+        }
+        self.end_container = container;
+        self.end_offset = offset;
+    }
+
+    pub fn collapsed(&self) -> bool {
+        Rc::ptr_eq(&self.start_container, &self.end_container)
+            && self.start_offset == self.end_offset
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-range-deletecontents
+    pub fn delete_contents(&mut self) {
+        self.extract_contents();
+    }
+
+    /// https://dom.spec.whatwg.org/#concept-range-extract
+    /// 範囲内のノードをツリーから取り除き、文書片にまとめて返す。部分的に含まれる文字データは分割して、
+    /// 範囲内の部分を複製に移す。部分的に含まれる要素は複製を作り、その中に範囲内の子孫を移す
+    pub fn extract_contents(&mut self) -> Rc<RefCell<Node>> {
+        let start = self.start_container.clone();
+        let end = self.end_container.clone();
+
+        let mut fragment = Node::new(NodeKind::DocumentFragment);
+        if let Some(document) = start.borrow().owner_document() {
+            fragment.set_owner_document(Rc::downgrade(&document));
+        }
+        let fragment = Rc::new(RefCell::new(fragment));
+        if self.collapsed() {
+            return fragment;
+        }
+
+        if Rc::ptr_eq(&start, &end) && is_character_data(&start) {
+            let data = replace_data(&start, self.start_offset, self.end_offset);
+            insert_child(&fragment, &clone_with_data(&start, data), None);
+            self.end_offset = self.start_offset;
+            return fragment;
+        }
+
+        let mut common_ancestor = start.clone();
+        while !is_inclusive_ancestor(&common_ancestor, &end) {
+            let parent = common_ancestor
+                .borrow()
+                .parent()
+                .expect("the start and the end of a range should be in the same tree");
+            common_ancestor = parent;
+        }
+
+        let mut first_partially_contained = None;
+        let mut last_partially_contained = None;
+        let mut contained = Vec::new();
+        let mut child = common_ancestor.borrow().first_child();
+        while let Some(c) = child {
+            if self.contains_node(&c) {
+                contained.push(c.clone());
+            } else if self.partially_contains_node(&c) {
+                if is_inclusive_ancestor(&c, &start) && !is_inclusive_ancestor(&start, &end) {
+                    first_partially_contained = Some(c.clone());
+                }
+                if is_inclusive_ancestor(&c, &end) && !is_inclusive_ancestor(&end, &start) {
+                    last_partially_contained = Some(c.clone());
+                }
+            }
+            child = c.borrow().next_sibling();
+        }
+
+        let (new_container, new_offset) = if is_inclusive_ancestor(&start, &end) {
+            (start.clone(), self.start_offset)
+        } else {
+            let mut reference = start.clone();
+            loop {
+                let parent = reference
+                    .borrow()
+                    .parent()
+                    .expect("the start of a range should have an ancestor containing the end");
+                if is_inclusive_ancestor(&parent, &end) {
+                    break (parent, index(&reference) + 1);
+                }
+                reference = parent;
+            }
+        };
+
+        if let Some(first) = first_partially_contained {
+            if is_character_data(&first) {
+                let data = replace_data(&start, self.start_offset, node_length(&start));
+                insert_child(&fragment, &clone_with_data(&start, data), None);
+            } else {
+                let clone = first.borrow().clone_node(false);
+                insert_child(&fragment, &clone, None);
+                let mut subrange = Range {
+                    start_container: start.clone(),
+                    start_offset: self.start_offset,
+                    end_container: first.clone(),
+                    end_offset: node_length(&first),
+                };
+                insert_child(&clone, &subrange.extract_contents(), None);
+            }
+        }
+
+        for node in contained {
+            insert_child(&fragment, &node, None);
+        }
+
+        if let Some(last) = last_partially_contained {
+            if is_character_data(&last) {
+                let data = replace_data(&end, 0, self.end_offset);
+                insert_child(&fragment, &clone_with_data(&end, data), None);
+            } else {
+                let clone = last.borrow().clone_node(false);
+                insert_child(&fragment, &clone, None);
+                let mut subrange = Range {
+                    start_container: last.clone(),
+                    start_offset: 0,
+                    end_container: end.clone(),
+                    end_offset: self.end_offset,
+                };
+                insert_child(&clone, &subrange.extract_contents(), None);
+            }
+        }
+
+        self.start_container = new_container.clone();
+        self.start_offset = new_offset;
+        self.end_container = new_container;
+        self.end_offset = new_offset;
+
+        fragment
+    }
+
+    /// https://dom.spec.whatwg.org/#partially-contained
+    /// 範囲の開始位置と終了位置の片方だけを含むノード
+    fn partially_contains_node(&self, node: &Rc<RefCell<Node>>) -> bool {
+        is_inclusive_ancestor(node, &self.start_container)
+            != is_inclusive_ancestor(node, &self.end_container)
+    }
+
+    /// https://dom.spec.whatwg.org/#contained
+    fn contains_node(&self, node: &Rc<RefCell<Node>>) -> bool {
+        compare_boundary_points(node, 0, &self.start_container, self.start_offset)
+            == Some(Ordering::Greater)
+            && compare_boundary_points(
+                node,
+                node_length(node),
+                &self.end_container,
+                self.end_offset,
+            ) == Some(Ordering::Less)
+    }
+}
+
+/// https://dom.spec.whatwg.org/#concept-range-bp-position
+fn compare_boundary_points(
+    node_a: &Rc<RefCell<Node>>,
+    offset_a: usize,
+    node_b: &Rc<RefCell<Node>>,
+    offset_b: usize,
+) -> Option<Ordering> {
+    match compare_document_position(node_a, node_b) {
+        DocumentPosition::Equal => Some(offset_a.cmp(&offset_b)),
+        DocumentPosition::Disconnected => None,
+        DocumentPosition::Preceding => {
+            compare_boundary_points(node_b, offset_b, node_a, offset_a).map(|o| o.reverse())
+        }
+        DocumentPosition::Following => Some(Ordering::Less),
+        DocumentPosition::Contains => {
+            compare_boundary_points(node_b, offset_b, node_a, offset_a).map(|o| o.reverse())
+        }
+        DocumentPosition::ContainedBy => {
+            let mut child = node_b.clone();
+            loop {
+                let parent = child
+                    .borrow()
+                    .parent()
+                    .expect("node_b should be a descendant of node_a");
+                if Rc::ptr_eq(&parent, node_a) {
+                    break;
+                }
+                child = parent;
+            }
+
+            if index(&child) < offset_a {
+                Some(Ordering::Greater)
+            } else {
+                Some(Ordering::Less)
+            }
+        }
+    }
+}
+
+/// ルートから `node` 自身までの祖先を返す
+fn inclusive_ancestors(node: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    let mut ancestors = Vec::new();
+    let mut current = Some(node.clone());
+    while let Some(n) = current {
+        current = n.borrow().parent();
+        ancestors.push(n);
+    }
+    ancestors.reverse();
+    ancestors
+}
+
+fn is_inclusive_ancestor(ancestor: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) -> bool {
+    inclusive_ancestors(node)
+        .iter()
+        .any(|n| Rc::ptr_eq(n, ancestor))
+}
+
+/// 兄弟ノードの中での位置
+fn index(node: &Rc<RefCell<Node>>) -> usize {
+    let parent = match node.borrow().parent() {
+        Some(parent) => parent,
+        None => return 0,
+    };
+
+    let mut i = 0;
+    let mut child = parent.borrow().first_child();
+    while let Some(c) = child {
+        if Rc::ptr_eq(&c, node) {
+            break;
+        }
+        i += 1;
+        child = c.borrow().next_sibling();
+    }
+    i
+}
+
+/// https://dom.spec.whatwg.org/#concept-node-length
+fn node_length(node: &Rc<RefCell<Node>>) -> usize {
+    if let NodeKind::Text(ref s) | NodeKind::Comment(ref s) = node.borrow().kind {
+        return s.chars().count();
+    }
+
+    let mut length = 0;
+    let mut child = node.borrow().first_child();
+    while let Some(c) = child {
+        length += 1;
+        child = c.borrow().next_sibling();
+    }
+    length
+}
+
+/// https://dom.spec.whatwg.org/#characterdata
+fn is_character_data(node: &Rc<RefCell<Node>>) -> bool {
+    matches!(node.borrow().kind, NodeKind::Text(_) | NodeKind::Comment(_))
+}
+
+/// 文字データの `start` から `end` までの文字を取り除き、取り除いた文字列を返す
+fn replace_data(node: &Rc<RefCell<Node>>, start: usize, end: usize) -> String {
+    let mut removed = String::new();
+    if let NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) = node.borrow_mut().kind {
+        let mut kept = String::new();
+        for (i, c) in s.chars().enumerate() {
+            if start <= i && i < end {
+                removed.push(c);
+            } else {
+                kept.push(c);
+            }
+        }
+        *s = kept;
+    }
+    removed
+}
+
+/// 文字データの複製を作り、文字列を `data` にする
+fn clone_with_data(node: &Rc<RefCell<Node>>, data: String) -> Rc<RefCell<Node>> {
+    let clone = node.borrow().clone_node(false);
+    if let NodeKind::Text(ref mut s) | NodeKind::Comment(ref mut s) = clone.borrow_mut().kind {
+        *s = data;
+    }
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{ElementKind, Window};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn body(html: &str) -> (Rc<RefCell<Window>>, Rc<RefCell<Node>>) {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let body = window
+            .borrow()
+            .document()
+            .borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+        (window, body)
+    }
+
+    fn child(node: &Rc<RefCell<Node>>, i: usize) -> Rc<RefCell<Node>> {
+        let mut c = node
+            .borrow()
+            .first_child()
+            .expect("Failed to get a first child");
+        for _ in 0..i {
+            let next = c
+                .borrow()
+                .next_sibling()
+                .expect("Failed to get a next sibling");
+            c = next;
+        }
+        c
+    }
+
+    fn text_of(node: &Rc<RefCell<Node>>) -> String {
+        match node.borrow().kind() {
+            NodeKind::Text(s) => s,
+            _ => panic!("node should be a text node"),
+        }
+    }
+
+    #[test]
+    fn test_compare_document_position() {
+        let (window, body) = body("<html><head></head><body><p>abc</p><h1>x</h1></body></html>");
+        let p = child(&body, 0);
+        let h1 = child(&body, 1);
+        let text = child(&p, 0);
+
+        assert_eq!(DocumentPosition::Equal, compare_document_position(&p, &p));
+        assert_eq!(
+            DocumentPosition::Following,
+            compare_document_position(&p, &h1)
+        );
+        assert_eq!(
+            DocumentPosition::Preceding,
+            compare_document_position(&h1, &text)
+        );
+        assert_eq!(
+            DocumentPosition::ContainedBy,
+            compare_document_position(&body, &text)
+        );
+        assert_eq!(
+            DocumentPosition::Contains,
+            compare_document_position(&text, &body)
+        );
+
+        let detached = Rc::new(RefCell::new(Node::new(NodeKind::Text("x".to_string()))));
+        assert_eq!(
+            DocumentPosition::Disconnected,
+            compare_document_position(&p, &detached)
+        );
+        drop(window);
+    }
+
+    #[test]
+    fn test_contains_and_tree_order() {
+        let (_window, body) = body("<html><head></head><body><p>abc</p><h1>x</h1></body></html>");
+        let p = child(&body, 0);
+        let h1 = child(&body, 1);
+        let text = child(&p, 0);
+
+        assert!(contains(&body, &text));
+        assert!(contains(&p, &p));
+        assert!(!contains(&text, &p));
+        assert!(!contains(&p, &h1));
+
+        let mut nodes = vec![h1.clone(), text.clone(), body.clone(), p.clone()];
+        sort_in_tree_order(&mut nodes);
+        let expected = [&body, &p, &text, &h1];
+        assert!(nodes.iter().zip(expected).all(|(a, b)| Rc::ptr_eq(a, b)));
+    }
+
+    #[test]
+    fn test_delete_contents_in_text() {
+        let (_window, body) = body("<html><head></head><body><p>abcdef</p></body></html>");
+        let text = child(&child(&body, 0), 0);
+
+        let mut range = Range::new(text.clone(), 1);
+        range.set_end(text.clone(), 4);
+        range.delete_contents();
+
+        assert_eq!("aef", text_of(&text));
+        assert!(range.collapsed());
+        assert_eq!(1, range.start_offset());
+    }
+
+    #[test]
+    fn test_extract_contents() {
+        let (_window, body) =
+            body("<html><head></head><body><p>abc</p><h1>x</h1><p>def</p></body></html>");
+        let p1_text = child(&child(&body, 0), 0);
+        let h1 = child(&body, 1);
+        let p2_text = child(&child(&body, 2), 0);
+
+        let mut range = Range::new(p1_text.clone(), 1);
+        range.set_end(p2_text.clone(), 2);
+        let fragment = range.extract_contents();
+
+        // 部分的に含まれる<p>は複製され、範囲内の文字だけを持つ
+        assert_eq!(NodeKind::DocumentFragment, fragment.borrow().kind());
+        assert_eq!(3, node_length(&fragment));
+        let p1_clone = child(&fragment, 0);
+        assert_eq!(Some(ElementKind::P), p1_clone.borrow().element_kind());
+        assert_eq!("bc", text_of(&child(&p1_clone, 0)));
+        assert!(Rc::ptr_eq(&h1, &child(&fragment, 1)));
+        let p2_clone = child(&fragment, 2);
+        assert_eq!(Some(ElementKind::P), p2_clone.borrow().element_kind());
+        assert_eq!("de", text_of(&child(&p2_clone, 0)));
+
+        assert_eq!("a", text_of(&p1_text));
+        assert_eq!("f", text_of(&p2_text));
+        assert_eq!(2, node_length(&body));
+        assert!(Rc::ptr_eq(&fragment, &h1.borrow().parent().expect("Failed to get a parent")));
+
+        assert!(range.collapsed());
+        assert!(Rc::ptr_eq(&body, &range.start_container()));
+        assert_eq!(1, range.start_offset());
+    }
+
+    #[test]
+    fn test_extract_contents_in_comment() {
+        let (_window, body) = body("<html><head></head><body><!--abcdef--><p>x</p></body></html>");
+        let comment = child(&body, 0);
+        assert_eq!(6, node_length(&comment));
+
+        let mut range = Range::new(comment.clone(), 1);
+        range.set_end(comment.clone(), 4);
+        let fragment = range.extract_contents();
+
+        assert_eq!(NodeKind::Comment("bcd".to_string()), child(&fragment, 0).borrow().kind());
+        assert_eq!(NodeKind::Comment("aef".to_string()), comment.borrow().kind());
+        assert!(range.collapsed());
+        assert_eq!(1, range.start_offset());
+    }
+
+    #[test]
+    fn test_set_start_after_end_collapses() {
+        let (_window, body) = body("<html><head></head><body><p>abc</p><h1>x</h1></body></html>");
+        let mut range = Range::new(body.clone(), 0);
+        range.set_end(body.clone(), 1);
+        range.set_start(child(&body, 1), 0);
+
+        assert!(range.collapsed());
+    }
+}
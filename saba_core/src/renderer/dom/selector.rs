@@ -0,0 +1,376 @@
+use crate::error::Error;
+use crate::renderer::dom::collection::NodeList;
+use crate::renderer::dom::node::{Element, Namespace, Node};
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// https://drafts.csswg.org/selectors/#simple
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimpleSelector {
+    /// `*`
+    Universal,
+    /// `p`
+    Type(String),
+    /// `.class`
+    Class(String),
+    /// `#id`
+    Id(String),
+    /// `[name]` または `[name=value]`
+    Attribute(String, Option<String>),
+}
+
+/// https://drafts.csswg.org/selectors/#combinators
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+}
+
+/// https://drafts.csswg.org/selectors/#complex
+/// 複合セレクタを結合子でつないだもの。`compounds[i]` と `compounds[i + 1]` の間が `combinators[i]` になる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComplexSelector {
+    compounds: Vec<Vec<SimpleSelector>>,
+    combinators: Vec<Combinator>,
+}
+
+/// https://drafts.csswg.org/selectors/#selector-list
+/// カンマ区切りのセレクタのうち、どれか1つに一致すれば一致とする
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorList {
+    selectors: Vec<ComplexSelector>,
+}
+
+impl SelectorList {
+    /// https://drafts.csswg.org/selectors/#parse-a-selector
+    pub fn parse(input: &str) -> Result<Self, Error> {
+        let mut selectors = Vec::new();
+        for part in input.split(',') {
+            selectors.push(parse_complex_selector(part)?);
+        }
+        Ok(Self { selectors })
+    }
+
+    /// https://drafts.csswg.org/selectors/#match-a-selector-against-an-element
+    pub fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        self.selectors.iter().any(|selector| selector.matches(node))
+    }
+}
+
+impl ComplexSelector {
+    /// 右端の複合セレクタから順に、親をたどりながら照合する
+    fn matches(&self, node: &Rc<RefCell<Node>>) -> bool {
+        self.matches_from(self.compounds.len() - 1, node)
+    }
+
+    fn matches_from(&self, i: usize, node: &Rc<RefCell<Node>>) -> bool {
+        if !matches_compound(&self.compounds[i], node) {
+            return false;
+        }
+        if i == 0 {
+            return true;
+        }
+
+        let mut parent = node.borrow().parent();
+        match self.combinators[i - 1] {
+            Combinator::Child => match parent {
+                Some(p) => self.matches_from(i - 1, &p),
+                None => false,
+            },
+            Combinator::Descendant => {
+                while let Some(p) = parent {
+                    if self.matches_from(i - 1, &p) {
+                        return true;
+                    }
+                    parent = p.borrow().parent();
+                }
+                false
+            }
+        }
+    }
+}
+
+fn matches_compound(compound: &[SimpleSelector], node: &Rc<RefCell<Node>>) -> bool {
+    match node.borrow().get_element() {
+        Some(element) => compound.iter().all(|s| matches_simple(s, &element)),
+        None => false,
+    }
+}
+
+fn matches_simple(selector: &SimpleSelector, element: &Element) -> bool {
+    match selector {
+        SimpleSelector::Universal => true,
+        // https://html.spec.whatwg.org/multipage/semantics-other.html#case-sensitivity-of-selectors
+        // HTMLの要素だけ大文字と小文字を区別しない
+        SimpleSelector::Type(name) => match element.namespace() {
+            Namespace::Html => element.local_name().eq_ignore_ascii_case(name),
+            Namespace::Svg | Namespace::MathMl => element.local_name() == *name,
+        },
+        SimpleSelector::Class(class) => element.class_list().contains(class),
+        SimpleSelector::Id(id) => element.id().as_ref() == Some(id),
+        SimpleSelector::Attribute(name, value) => match (element.get_attribute(name), value) {
+            (Some(actual), Some(expected)) => actual == *expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        },
+    }
+}
+
+fn parse_complex_selector(input: &str) -> Result<ComplexSelector, Error> {
+    let mut chars = input.trim().chars().peekable();
+    let mut compounds = Vec::new();
+    let mut combinators = Vec::new();
+
+    loop {
+        compounds.push(parse_compound_selector(&mut chars, input)?);
+
+        let mut combinator = None;
+        while let Some(c) = chars.peek() {
+            match c {
+                ' ' | '\t' | '\n' | '\r' | '\x0c' => {
+                    combinator.get_or_insert(Combinator::Descendant);
+                }
+                '>' => combinator = Some(Combinator::Child),
+                _ => break,
+            }
+            chars.next();
+        }
+
+        match (combinator, chars.peek()) {
+            (None, None) => break,
+            (Some(combinator), Some(_)) => combinators.push(combinator),
+            _ => return Err(invalid_selector(input)),
+        }
+    }
+
+    Ok(ComplexSelector {
+        compounds,
+        combinators,
+    })
+}
+
+fn parse_compound_selector(chars: &mut Peekable<Chars>, input: &str) -> Result<Vec<SimpleSelector>, Error> {
+    let mut compound = Vec::new();
+
+    match chars.peek() {
+        Some('*') => {
+            chars.next();
+            compound.push(SimpleSelector::Universal);
+        }
+        Some(c) if is_name_char(*c) => compound.push(SimpleSelector::Type(consume_name(chars))),
+        _ => {}
+    }
+
+    loop {
+        match chars.peek() {
+            Some('.') => {
+                chars.next();
+                compound.push(SimpleSelector::Class(consume_non_empty_name(chars, input)?));
+            }
+            Some('#') => {
+                chars.next();
+                compound.push(SimpleSelector::Id(consume_non_empty_name(chars, input)?));
+            }
+            Some('[') => {
+                chars.next();
+                compound.push(parse_attribute_selector(chars, input)?);
+            }
+            _ => break,
+        }
+    }
+
+    if compound.is_empty() {
+        return Err(invalid_selector(input));
+    }
+    Ok(compound)
+}
+
+/// `[` の直後から `]` までを読み取る。値は引用符で囲んでもよい
+fn parse_attribute_selector(chars: &mut Peekable<Chars>, input: &str) -> Result<SimpleSelector, Error> {
+    let name = consume_non_empty_name(chars, input)?;
+    let value = match chars.next() {
+        Some(']') => return Ok(SimpleSelector::Attribute(name, None)),
+        Some('=') => match chars.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                let quote = *quote;
+                chars.next();
+                chars.by_ref().take_while(|c| *c != quote).collect()
+            }
+            _ => consume_name(chars),
+        },
+        _ => return Err(invalid_selector(input)),
+    };
+
+    match chars.next() {
+        Some(']') => Ok(SimpleSelector::Attribute(name, Some(value))),
+        _ => Err(invalid_selector(input)),
+    }
+}
+
+fn consume_name(chars: &mut Peekable<Chars>) -> String {
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|c| is_name_char(*c)) {
+        name.push(c);
+    }
+    name
+}
+
+fn consume_non_empty_name(chars: &mut Peekable<Chars>, input: &str) -> Result<String, Error> {
+    let name = consume_name(chars);
+    if name.is_empty() {
+        return Err(invalid_selector(input));
+    }
+    Ok(name)
+}
+
+/// https://drafts.csswg.org/css-syntax/#ident-code-point
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()
+}
+
+fn invalid_selector(input: &str) -> Error {
+    Error::UnexpectedInput(format!("invalid selector: {}", input))
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-matches
+/// 要素でないノードは一致しない
+pub fn matches(node: &Rc<RefCell<Node>>, selectors: &str) -> Result<bool, Error> {
+    Ok(SelectorList::parse(selectors)?.matches(node))
+}
+
+/// https://dom.spec.whatwg.org/#dom-element-closest
+/// `node` 自身から祖先に向かって、セレクタに一致する最初の要素を返す
+pub fn closest(node: &Rc<RefCell<Node>>, selectors: &str) -> Result<Option<Rc<RefCell<Node>>>, Error> {
+    let selectors = SelectorList::parse(selectors)?;
+    if selectors.matches(node) {
+        return Ok(Some(node.clone()));
+    }
+    Ok(node.borrow().ancestors().find(|n| selectors.matches(n)))
+}
+
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselector
+/// `node` の子孫のうち、セレクタに一致する最初の要素を文書順で返す
+pub fn query_selector(node: &Rc<RefCell<Node>>, selectors: &str) -> Result<Option<Rc<RefCell<Node>>>, Error> {
+    Ok(query_selector_all(node, selectors)?.item(0))
+}
+
+/// https://dom.spec.whatwg.org/#dom-parentnode-queryselectorall
+/// `node` 自身は含めずに、子孫のうちセレクタに一致する要素をすべて文書順で返す
+pub fn query_selector_all(node: &Rc<RefCell<Node>>, selectors: &str) -> Result<NodeList, Error> {
+    let selectors = SelectorList::parse(selectors)?;
+    Ok(NodeList::new(node.borrow().descendants().filter(|n| selectors.matches(n)).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::ElementKind;
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn ids(nodes: &NodeList) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|node| {
+                node.borrow()
+                    .get_element()
+                    .expect("Failed to get an element")
+                    .get_attribute("id")
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_query_selector() {
+        let html = "<html><head></head><body><div id=a class=\"x y\"><p id=b class=y><span id=c></span></p></div><p id=d data-k=v></p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+
+        let first = query_selector(&document, "p")
+            .expect("Failed to parse a selector")
+            .expect("Failed to find an element");
+        assert_eq!(Some(ElementKind::P), first.borrow().element_kind());
+
+        let all = |s: &str| ids(&query_selector_all(&document, s).expect("Failed to parse a selector"));
+        assert_eq!(vec!["b", "d"], all("p"));
+        assert_eq!(vec!["a", "b"], all(".y"));
+        assert_eq!(vec!["a"], all("div.x.y"));
+        assert_eq!(vec!["c"], all("#a span"));
+        assert_eq!(vec!["b"], all("div > p"));
+        assert!(all("body > span").is_empty());
+        assert_eq!(vec!["d"], all("[data-k=\"v\"]"));
+        assert_eq!(vec!["a", "c"], all("span, #a"));
+
+        // 子孫だけを探すので、起点の要素自身は含まない
+        let div = query_selector(&document, "#a")
+            .expect("Failed to parse a selector")
+            .expect("Failed to find an element");
+        assert!(query_selector(&div, "div").expect("Failed to parse a selector").is_none());
+    }
+
+    #[test]
+    fn test_matches_and_closest() {
+        let html = "<html><head></head><body><div class=menu><ul><li><a id=l href=/x>link</a></li></ul></div></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let link = query_selector(&document, "#l")
+            .expect("Failed to parse a selector")
+            .expect("Failed to find an element");
+
+        assert!(matches(&link, "a[href]").expect("Failed to parse a selector"));
+        assert!(matches(&link, ".menu li > a").expect("Failed to parse a selector"));
+        assert!(!matches(&link, "div > a").expect("Failed to parse a selector"));
+        assert!(matches(&link, "p").is_ok_and(|m| !m));
+        assert!(matches(&link, "a >").is_err());
+
+        let closest_of = |s: &str| {
+            closest(&link, s)
+                .expect("Failed to parse a selector")
+                .and_then(|n| n.borrow().element_kind())
+        };
+        assert_eq!(Some(ElementKind::A), closest_of("a"));
+        assert_eq!(Some(ElementKind::Div), closest_of(".menu"));
+        assert_eq!(Some(ElementKind::Ul), closest_of("div ul"));
+        assert_eq!(None, closest_of("p"));
+
+        // テキストノードは一致しないが、祖先の要素は探せる
+        let text = link.borrow().first_child().expect("Failed to get a first child");
+        assert!(!matches(&text, "*").expect("Failed to parse a selector"));
+        assert_eq!(Some(ElementKind::Li), closest(&text, "li").expect("Failed to parse a selector").and_then(|n| n.borrow().element_kind()));
+    }
+
+    #[test]
+    fn test_foreign_elements() {
+        let html = "<html><head></head><body><svg><linearGradient id=g></linearGradient><a id=s></a></svg><A id=h></A><math><mi id=m>x</mi></math></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let select = |s: &str| ids(&query_selector_all(&document, s).expect("Failed to parse a selector"));
+
+        // SVGの要素名は大文字と小文字を区別する
+        assert_eq!(vec!["g"], select("linearGradient"));
+        assert!(select("lineargradient").is_empty());
+        assert_eq!(vec!["s", "h"], select("a"));
+        assert_eq!(vec!["h"], select("body > A"));
+        assert_eq!(vec!["m"], select("math mi"));
+        assert!(select("MI").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        assert!(SelectorList::parse("").is_err());
+        assert!(SelectorList::parse("p >").is_err());
+        assert!(SelectorList::parse("a,").is_err());
+        assert!(SelectorList::parse(".").is_err());
+        assert!(SelectorList::parse("[href").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::renderer::dom::node::Node;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+
+/// https://dom.spec.whatwg.org/#interface-nodefilter
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FilterResult {
+    Accept,
+    /// ノードとその子孫をすべて飛ばす
+    Reject,
+    /// ノードだけを飛ばし、子孫は調べる
+    Skip,
+}
+
+type NodeFilterFn = dyn Fn(&Node) -> FilterResult;
+
+/// https://dom.spec.whatwg.org/#interface-treewalker
+/// `root` の部分木のうち、`filter` が `FilterResult::Accept` を返すノードだけを文書順にたどる
+#[derive(Clone)]
+pub struct TreeWalker {
+    root: Rc<RefCell<Node>>,
+    current: Rc<RefCell<Node>>,
+    filter: Rc<NodeFilterFn>,
+}
+
+impl fmt::Debug for TreeWalker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TreeWalker")
+            .field("root", &self.root)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl TreeWalker {
+    pub fn new(root: Rc<RefCell<Node>>, filter: impl Fn(&Node) -> FilterResult + 'static) -> Self {
+        Self {
+            current: root.clone(),
+            root,
+            filter: Rc::new(filter),
+        }
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn current_node(&self) -> Rc<RefCell<Node>> {
+        self.current.clone()
+    }
+
+    pub fn set_current_node(&mut self, node: Rc<RefCell<Node>>) {
+        self.current = node;
+    }
+
+    fn filter(&self, node: &Rc<RefCell<Node>>) -> FilterResult {
+        (self.filter)(&node.borrow())
+    }
+
+    fn accept(&mut self, node: Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+        self.current = node;
+        Some(self.current.clone())
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-parentnode
+    pub fn parent_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        while !Rc::ptr_eq(&node, &self.root) {
+            let parent = node.borrow().parent()?;
+            node = parent;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+        None
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-nextnode
+    pub fn next_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+        let mut result = FilterResult::Accept;
+
+        loop {
+            while result != FilterResult::Reject {
+                let child = match node.borrow().first_child() {
+                    Some(child) => child,
+                    None => break,
+                };
+                node = child;
+                result = self.filter(&node);
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+            }
+
+            // 子孫がなければ、自分か祖先の次の兄弟に進む
+            let mut temporary = Some(node.clone());
+            let mut sibling = None;
+            while let Some(t) = temporary {
+                if Rc::ptr_eq(&t, &self.root) {
+                    return None;
+                }
+                sibling = t.borrow().next_sibling();
+                if sibling.is_some() {
+                    break;
+                }
+                temporary = t.borrow().parent();
+            }
+
+            node = sibling?;
+            result = self.filter(&node);
+            if result == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+    }
+
+    /// https://dom.spec.whatwg.org/#dom-treewalker-previousnode
+    pub fn previous_node(&mut self) -> Option<Rc<RefCell<Node>>> {
+        let mut node = self.current.clone();
+
+        while !Rc::ptr_eq(&node, &self.root) {
+            let mut sibling = node.borrow().previous_sibling().upgrade();
+            while let Some(s) = sibling {
+                node = s;
+                let mut result = self.filter(&node);
+                // 前の兄弟の子孫のうち、最後のものから調べる
+                while result != FilterResult::Reject {
+                    let child = match node.borrow().last_child().upgrade() {
+                        Some(child) => child,
+                        None => break,
+                    };
+                    node = child;
+                    result = self.filter(&node);
+                }
+                if result == FilterResult::Accept {
+                    return self.accept(node);
+                }
+                sibling = node.borrow().previous_sibling().upgrade();
+            }
+
+            if Rc::ptr_eq(&node, &self.root) {
+                return None;
+            }
+            let parent = node.borrow().parent()?;
+            node = parent;
+            if self.filter(&node) == FilterResult::Accept {
+                return self.accept(node);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{ElementKind, NodeKind};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    fn label(node: &Rc<RefCell<Node>>) -> String {
+        match node.borrow().kind() {
+            NodeKind::Element(e) => e.local_name(),
+            NodeKind::Text(s) => s,
+            _ => String::new(),
+        }
+    }
+
+    fn body(html: &str) -> Rc<RefCell<Node>> {
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let body = document
+            .borrow()
+            .descendants()
+            .find(|n| n.borrow().element_kind() == Some(ElementKind::Body));
+        body.expect("Failed to find body")
+    }
+
+    #[test]
+    fn test_next_and_previous_node() {
+        let body = body("<html><head></head><body><p>a<b>b</b></p><div>c</div></body></html>");
+        let mut walker = TreeWalker::new(body.clone(), |node| match node.kind {
+            NodeKind::Text(_) => FilterResult::Accept,
+            _ => FilterResult::Skip,
+        });
+
+        let mut forward = Vec::new();
+        while let Some(node) = walker.next_node() {
+            forward.push(label(&node));
+        }
+        assert_eq!(vec!["a", "b", "c"], forward);
+        assert_eq!("c", label(&walker.current_node()));
+
+        let mut backward = Vec::new();
+        while let Some(node) = walker.previous_node() {
+            backward.push(label(&node));
+        }
+        assert_eq!(vec!["b", "a"], backward);
+        assert!(walker.parent_node().is_none());
+    }
+
+    #[test]
+    fn test_reject_skips_subtree() {
+        let body = body("<html><head></head><body><p>a<b>b</b></p><div><i>c</i></div></body></html>");
+        let mut walker = TreeWalker::new(body, |node| match node.element_kind() {
+            Some(ElementKind::P) => FilterResult::Reject,
+            Some(_) => FilterResult::Accept,
+            None => FilterResult::Skip,
+        });
+
+        assert_eq!(Some("div".to_string()), walker.next_node().map(|n| label(&n)));
+        assert_eq!(Some("i".to_string()), walker.next_node().map(|n| label(&n)));
+        assert!(walker.next_node().is_none());
+
+        assert_eq!(Some("div".to_string()), walker.parent_node().map(|n| label(&n)));
+        assert_eq!(Some("body".to_string()), walker.parent_node().map(|n| label(&n)));
+        assert!(walker.parent_node().is_none());
+    }
+}
@@ -1,16 +1,17 @@
-use alloc::string::String;
+use crate::renderer::html::token_string::TokenString;
+use alloc::string::{String, ToString};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Attribute {
-    name: String,
-    value: String,
+    name: TokenString,
+    value: TokenString,
 }
 
 impl Attribute {
     pub fn new() -> Self {
         Self {
-            name: String::new(),
-            value: String::new(),
+            name: TokenString::new(),
+            value: TokenString::new(),
         }
     }
 
@@ -25,11 +26,50 @@ impl Attribute {
         }
     }
 
+    pub fn add_str(&mut self, s: &str, is_name: bool) {
+        match is_name {
+            true => {
+                self.name.push_str(s);
+            }
+            false => {
+                self.value.push_str(s);
+            }
+        }
+    }
+
+    /// 字句解析器が読んだ入力の範囲を追加する
+    pub fn add_token_string(&mut self, s: TokenString, is_name: bool) {
+        match is_name {
+            true => {
+                self.name.append(s);
+            }
+            false => {
+                self.value.append(s);
+            }
+        }
+    }
+
+    /// 入力の範囲を指している名前と値を、それぞれの文字列にする。DOMに持たせる前に呼ぶ
+    pub fn into_owned(self) -> Self {
+        Self {
+            name: self.name.into_owned(),
+            value: self.value.into_owned(),
+        }
+    }
+
     pub fn name(&self) -> String {
-        self.name.clone()
+        self.name.to_string()
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.into();
     }
 
     pub fn value(&self) -> String {
-        self.value.clone()
+        self.value.to_string()
+    }
+
+    pub fn set_value(&mut self, value: &str) {
+        self.value = value.into();
     }
 }
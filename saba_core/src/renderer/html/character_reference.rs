@@ -0,0 +1,303 @@
+/// https://html.spec.whatwg.org/multipage/named-characters.html
+/// 仕様の一覧のうちよく使われるものだけを持つ。セミコロンのない名前は、レガシーなHTMLとの互換性のために仕様で許されているもの
+const NAMED_CHARACTER_REFERENCES: [(&str, &str); 162] = [
+    ("AMP", "&"),
+    ("AMP;", "&"),
+    ("Aacute", "\u{c1}"),
+    ("Aacute;", "\u{c1}"),
+    ("Agrave", "\u{c0}"),
+    ("Agrave;", "\u{c0}"),
+    ("Auml", "\u{c4}"),
+    ("Auml;", "\u{c4}"),
+    ("COPY", "\u{a9}"),
+    ("COPY;", "\u{a9}"),
+    ("Ccedil", "\u{c7}"),
+    ("Ccedil;", "\u{c7}"),
+    ("Dagger;", "\u{2021}"),
+    ("Eacute", "\u{c9}"),
+    ("Eacute;", "\u{c9}"),
+    ("GT", ">"),
+    ("GT;", ">"),
+    ("LT", "<"),
+    ("LT;", "<"),
+    ("Ouml", "\u{d6}"),
+    ("Ouml;", "\u{d6}"),
+    ("QUOT", "\""),
+    ("QUOT;", "\""),
+    ("REG", "\u{ae}"),
+    ("REG;", "\u{ae}"),
+    ("Uuml", "\u{dc}"),
+    ("Uuml;", "\u{dc}"),
+    ("aacute", "\u{e1}"),
+    ("aacute;", "\u{e1}"),
+    ("acute", "\u{b4}"),
+    ("acute;", "\u{b4}"),
+    ("agrave", "\u{e0}"),
+    ("agrave;", "\u{e0}"),
+    ("alpha;", "\u{3b1}"),
+    ("amp", "&"),
+    ("amp;", "&"),
+    ("apos;", "'"),
+    ("auml", "\u{e4}"),
+    ("auml;", "\u{e4}"),
+    ("bdquo;", "\u{201e}"),
+    ("beta;", "\u{3b2}"),
+    ("brvbar", "\u{a6}"),
+    ("brvbar;", "\u{a6}"),
+    ("bull;", "\u{2022}"),
+    ("ccedil", "\u{e7}"),
+    ("ccedil;", "\u{e7}"),
+    ("cedil", "\u{b8}"),
+    ("cedil;", "\u{b8}"),
+    ("cent", "\u{a2}"),
+    ("cent;", "\u{a2}"),
+    ("copy", "\u{a9}"),
+    ("copy;", "\u{a9}"),
+    ("curren", "\u{a4}"),
+    ("curren;", "\u{a4}"),
+    ("dagger;", "\u{2020}"),
+    ("darr;", "\u{2193}"),
+    ("deg", "\u{b0}"),
+    ("deg;", "\u{b0}"),
+    ("divide", "\u{f7}"),
+    ("divide;", "\u{f7}"),
+    ("eacute", "\u{e9}"),
+    ("eacute;", "\u{e9}"),
+    ("egrave", "\u{e8}"),
+    ("egrave;", "\u{e8}"),
+    ("emsp;", "\u{2003}"),
+    ("ensp;", "\u{2002}"),
+    ("euro;", "\u{20ac}"),
+    ("frac12", "\u{bd}"),
+    ("frac12;", "\u{bd}"),
+    ("frac14", "\u{bc}"),
+    ("frac14;", "\u{bc}"),
+    ("frac34", "\u{be}"),
+    ("frac34;", "\u{be}"),
+    ("ge;", "\u{2265}"),
+    ("gt", ">"),
+    ("gt;", ">"),
+    ("hArr;", "\u{21d4}"),
+    ("harr;", "\u{2194}"),
+    ("hearts;", "\u{2665}"),
+    ("hellip;", "\u{2026}"),
+    ("iexcl", "\u{a1}"),
+    ("iexcl;", "\u{a1}"),
+    ("infin;", "\u{221e}"),
+    ("iquest", "\u{bf}"),
+    ("iquest;", "\u{bf}"),
+    ("laquo", "\u{ab}"),
+    ("laquo;", "\u{ab}"),
+    ("larr;", "\u{2190}"),
+    ("ldquo;", "\u{201c}"),
+    ("le;", "\u{2264}"),
+    ("lsaquo;", "\u{2039}"),
+    ("lsquo;", "\u{2018}"),
+    ("lt", "<"),
+    ("lt;", "<"),
+    ("macr", "\u{af}"),
+    ("macr;", "\u{af}"),
+    ("mdash;", "\u{2014}"),
+    ("micro", "\u{b5}"),
+    ("micro;", "\u{b5}"),
+    ("middot", "\u{b7}"),
+    ("middot;", "\u{b7}"),
+    ("minus;", "\u{2212}"),
+    ("nbsp", "\u{a0}"),
+    ("nbsp;", "\u{a0}"),
+    ("ndash;", "\u{2013}"),
+    ("ne;", "\u{2260}"),
+    ("not", "\u{ac}"),
+    ("not;", "\u{ac}"),
+    ("ntilde", "\u{f1}"),
+    ("ntilde;", "\u{f1}"),
+    ("ordf", "\u{aa}"),
+    ("ordf;", "\u{aa}"),
+    ("ordm", "\u{ba}"),
+    ("ordm;", "\u{ba}"),
+    ("ouml", "\u{f6}"),
+    ("ouml;", "\u{f6}"),
+    ("para", "\u{b6}"),
+    ("para;", "\u{b6}"),
+    ("permil;", "\u{2030}"),
+    ("pi;", "\u{3c0}"),
+    ("plusmn", "\u{b1}"),
+    ("plusmn;", "\u{b1}"),
+    ("pound", "\u{a3}"),
+    ("pound;", "\u{a3}"),
+    ("prime;", "\u{2032}"),
+    ("quot", "\""),
+    ("quot;", "\""),
+    ("rArr;", "\u{21d2}"),
+    ("raquo", "\u{bb}"),
+    ("raquo;", "\u{bb}"),
+    ("rarr;", "\u{2192}"),
+    ("rdquo;", "\u{201d}"),
+    ("reg", "\u{ae}"),
+    ("reg;", "\u{ae}"),
+    ("rsaquo;", "\u{203a}"),
+    ("rsquo;", "\u{2019}"),
+    ("sbquo;", "\u{201a}"),
+    ("sect", "\u{a7}"),
+    ("sect;", "\u{a7}"),
+    ("shy", "\u{ad}"),
+    ("shy;", "\u{ad}"),
+    ("sup1", "\u{b9}"),
+    ("sup1;", "\u{b9}"),
+    ("sup2", "\u{b2}"),
+    ("sup2;", "\u{b2}"),
+    ("sup3", "\u{b3}"),
+    ("sup3;", "\u{b3}"),
+    ("szlig", "\u{df}"),
+    ("szlig;", "\u{df}"),
+    ("thinsp;", "\u{2009}"),
+    ("times", "\u{d7}"),
+    ("times;", "\u{d7}"),
+    ("trade;", "\u{2122}"),
+    ("uarr;", "\u{2191}"),
+    ("uml", "\u{a8}"),
+    ("uml;", "\u{a8}"),
+    ("uuml", "\u{fc}"),
+    ("uuml;", "\u{fc}"),
+    ("yen", "\u{a5}"),
+    ("yen;", "\u{a5}"),
+    ("zwj;", "\u{200d}"),
+    ("zwnj;", "\u{200c}"),
+];
+
+/// `input` の先頭に一致する最も長い名前付き文字参照を探し、一致した名前のバイト数と置き換える文字列を返す
+pub fn match_named_character_reference(input: &str) -> Option<(usize, &'static str)> {
+    let mut longest: Option<(usize, &'static str)> = None;
+
+    for (name, value) in NAMED_CHARACTER_REFERENCES.iter() {
+        let len = name.len();
+        if longest.is_some_and(|(l, _)| l >= len) {
+            continue;
+        }
+
+        if input.as_bytes().starts_with(name.as_bytes()) {
+            longest = Some((len, value));
+        }
+    }
+
+    longest
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+/// C1制御文字の範囲の数値文字参照は、Windows-1252として解釈した文字に置き換える
+const NUMERIC_REPLACEMENTS: [(u32, char); 27] = [
+    (0x80, '\u{20ac}'),
+    (0x82, '\u{201a}'),
+    (0x83, '\u{192}'),
+    (0x84, '\u{201e}'),
+    (0x85, '\u{2026}'),
+    (0x86, '\u{2020}'),
+    (0x87, '\u{2021}'),
+    (0x88, '\u{2c6}'),
+    (0x89, '\u{2030}'),
+    (0x8a, '\u{160}'),
+    (0x8b, '\u{2039}'),
+    (0x8c, '\u{152}'),
+    (0x8e, '\u{17d}'),
+    (0x91, '\u{2018}'),
+    (0x92, '\u{2019}'),
+    (0x93, '\u{201c}'),
+    (0x94, '\u{201d}'),
+    (0x95, '\u{2022}'),
+    (0x96, '\u{2013}'),
+    (0x97, '\u{2014}'),
+    (0x98, '\u{2dc}'),
+    (0x99, '\u{2122}'),
+    (0x9a, '\u{161}'),
+    (0x9b, '\u{203a}'),
+    (0x9c, '\u{153}'),
+    (0x9e, '\u{17e}'),
+    (0x9f, '\u{178}'),
+];
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-state
+/// `input` の先頭にある `#` から始まる数値文字参照を読み取り、消費したバイト数と置き換える文字を返す
+/// 数字が1つもなければ文字参照ではないので `None` を返す
+pub fn match_numeric_character_reference(input: &str) -> Option<(usize, char)> {
+    let input = input.as_bytes();
+    if input.first() != Some(&b'#') {
+        return None;
+    }
+
+    let (radix, start) = match input.get(1) {
+        Some(b'x') | Some(b'X') => (16, 2),
+        _ => (10, 1),
+    };
+
+    let mut len = start;
+    let mut code: u32 = 0;
+    while let Some(digit) = input.get(len).and_then(|c| (*c as char).to_digit(radix)) {
+        // 大きすぎる値はどのみち範囲外として扱うので、あふれないように止めておく
+        code = code.saturating_mul(radix).saturating_add(digit);
+        len += 1;
+    }
+
+    if len == start {
+        return None;
+    }
+
+    if input.get(len) == Some(&b';') {
+        len += 1;
+    }
+
+    Some((len, numeric_character_reference_value(code)))
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state
+fn numeric_character_reference_value(code: u32) -> char {
+    if code == 0 || code > 0x10ffff || (0xd800..=0xdfff).contains(&code) {
+        return '\u{fffd}';
+    }
+
+    if let Some((_, c)) = NUMERIC_REPLACEMENTS.iter().find(|(n, _)| *n == code) {
+        return *c;
+    }
+
+    char::from_u32(code).unwrap_or('\u{fffd}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_named_character_reference() {
+        assert_eq!(Some((4, "&")), match_named_character_reference("amp;rest"));
+        assert_eq!(Some((3, "&")), match_named_character_reference("amprest"));
+        assert_eq!(Some((5, "\u{a0}")), match_named_character_reference("nbsp;"));
+        assert_eq!(Some((4, "\u{ac}")), match_named_character_reference("not;in"));
+        assert_eq!(Some((3, "\u{ac}")), match_named_character_reference("notin"));
+        assert_eq!(None, match_named_character_reference("hellip"));
+        assert_eq!(None, match_named_character_reference("foo;"));
+    }
+
+    #[test]
+    fn test_match_numeric_character_reference() {
+        assert_eq!(Some((7, '\u{3042}')), match_numeric_character_reference("#x3042;"));
+        assert_eq!(Some((7, '\u{3042}')), match_numeric_character_reference("#12354;"));
+        assert_eq!(Some((4, 'A')), match_numeric_character_reference("#X41 b"));
+        assert_eq!(Some((4, 'A')), match_numeric_character_reference("#65;"));
+        assert_eq!(None, match_numeric_character_reference("#;"));
+        assert_eq!(None, match_numeric_character_reference("#xg;"));
+        assert_eq!(None, match_numeric_character_reference("amp;"));
+    }
+
+    #[test]
+    fn test_numeric_character_reference_replacement() {
+        assert_eq!(Some((3, '\u{fffd}')), match_numeric_character_reference("#0;"));
+        assert_eq!(Some((9, '\u{fffd}')), match_numeric_character_reference("#x110000;"));
+        assert_eq!(Some((7, '\u{fffd}')), match_numeric_character_reference("#xD800;"));
+        assert_eq!(
+            Some((22, '\u{fffd}')),
+            match_numeric_character_reference("#99999999999999999999;")
+        );
+        assert_eq!(Some((5, '\u{20ac}')), match_numeric_character_reference("#x80;"));
+        assert_eq!(Some((5, '\u{178}')), match_numeric_character_reference("#159;"));
+        assert_eq!(Some((5, '\u{81}')), match_numeric_character_reference("#x81;"));
+    }
+}
@@ -1,3 +1,7 @@
 pub mod attribute;
+pub mod character_reference;
+pub mod parse_error;
 pub mod token;
+pub mod token_string;
 pub mod parser;
+pub mod serializer;
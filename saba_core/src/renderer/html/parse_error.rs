@@ -0,0 +1,53 @@
+use alloc::string::{String, ToString};
+
+/// 入力の中の位置。`line` と `column` は1から数える
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SourcePosition {
+    /// 入力の先頭からのバイト数
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl SourcePosition {
+    pub fn new(offset: usize, line: usize, column: usize) -> Self {
+        Self { offset, line, column }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 仕様で定められたエラーの名前。木構築のエラーには仕様上の名前がないため、独自の名前を使う
+    code: String,
+    position: SourcePosition,
+}
+
+impl ParseError {
+    pub fn new(code: &str, position: SourcePosition) -> Self {
+        Self {
+            code: code.to_string(),
+            position,
+        }
+    }
+
+    pub fn code(&self) -> String {
+        self.code.clone()
+    }
+
+    pub fn position(&self) -> SourcePosition {
+        self.position
+    }
+}
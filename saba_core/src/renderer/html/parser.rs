@@ -1,19 +1,62 @@
-use crate::renderer::dom::node::{Element, ElementKind, Node, NodeKind, Window};
+use crate::renderer::dom::node::{detach, insert_child, DocumentType, Element, ElementKind, Namespace, Node, NodeKind, Window};
 use crate::renderer::html::attribute::Attribute;
-use crate::renderer::html::token::{HtmlToken, HtmlTokenizer};
+use crate::renderer::html::parse_error::{ParseError, SourcePosition};
+use crate::renderer::html::token::{is_html_whitespace, HtmlToken, HtmlTokenizer, State};
 use alloc::rc::Rc;
-use alloc::string::String;
+use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::fmt;
 use core::str::FromStr;
 
+type ScriptHookFn = dyn Fn(&str, &Rc<RefCell<Window>>);
+
+/// `</script>` で<script>が閉じられたときに、スクリプトの中身とそこまでに構築した木を受け取る関数
+#[derive(Clone)]
+pub struct ScriptHook(Rc<ScriptHookFn>);
+
+impl fmt::Debug for ScriptHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ScriptHook")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HtmlParser {
     window: Rc<RefCell<Window>>,
     mode: InsertionMode,
     original_insertion_mode: InsertionMode,
     stack_of_open_elements: Vec<Rc<RefCell<Node>>>,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#list-of-active-formatting-elements
+    /// Noneはmarkerを表す
+    active_formatting_elements: Vec<Option<Rc<RefCell<Node>>>>,
+    /// 表の中に直接書かれたトークンを、in bodyの規則で処理し直す
+    reprocess_in_body: bool,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#foster-parent
+    foster_parenting: bool,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#scripting-flag
+    /// trueのとき<noscript>の中身をテキストとして扱う
+    scripting: bool,
+    script_hook: Option<ScriptHook>,
+    whitespace_policy: WhitespacePolicy,
+    /// 要素ごとに `whitespace_policy` を上書きする。開いている要素のうち最も内側のものを優先する
+    whitespace_overrides: Vec<(ElementKind, WhitespacePolicy)>,
+    /// 入力を復号した文字コード
+    encoding: String,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+    /// 先頭1024バイトの中の<meta>で宣言された文字コード
+    declared_encoding: Option<String>,
+    /// 解析中に見つかった、読み込みが必要な資源
+    resources: Vec<SubResource>,
+    /// 処理中のトークンの直後の位置
+    position: SourcePosition,
+    errors: Vec<ParseError>,
     t: HtmlTokenizer,
+    quirks_mode: QuirksMode,
+    /// https://html.spec.whatwg.org/multipage/parsing.html#concept-frag-parse-context
+    /// HTMLの断片を解析するときに、断片を挿入する先の要素
+    context_element: Option<Rc<RefCell<Node>>>,
 }
 
 impl HtmlParser {
@@ -23,37 +66,251 @@ impl HtmlParser {
             mode: InsertionMode::Initial,
             original_insertion_mode: InsertionMode::Initial,
             stack_of_open_elements: Vec::new(),
+            active_formatting_elements: Vec::new(),
+            reprocess_in_body: false,
+            foster_parenting: false,
+            scripting: false,
+            script_hook: None,
+            whitespace_policy: WhitespacePolicy::Drop,
+            // <pre>の中の空白は表示に影響するので残す
+            whitespace_overrides: vec![(ElementKind::Pre, WhitespacePolicy::Preserve)],
+            encoding: "utf-8".to_string(),
+            declared_encoding: None,
+            resources: Vec::new(),
+            position: SourcePosition::new(0, 1, 1),
+            errors: Vec::new(),
             t,
+            quirks_mode: QuirksMode::NoQuirks,
+            context_element: None,
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#html-fragment-parsing-algorithm
+    /// `context` の子として `html` を解析し、できたノードを文書片の子として返す
+    pub fn parse_fragment(context: &Rc<RefCell<Node>>, html: String) -> Rc<RefCell<Node>> {
+        let mut t = HtmlTokenizer::new(html);
+        let context_name = match context.borrow().get_element() {
+            Some(element) if element.namespace() == Namespace::Html => element.local_name(),
+            _ => String::new(),
+        };
+        match context_name.as_str() {
+            "title" | "textarea" => t.switch_to(State::Rcdata),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" => t.switch_to(State::Rawtext),
+            "script" => t.switch_to(State::ScriptData),
+            "plaintext" => t.switch_to(State::Plaintext),
+            _ => {}
+        }
+
+        let mut parser = HtmlParser::new(t);
+        let root = Rc::new(RefCell::new(create_element_node("html", Vec::new())));
+        let document = parser.window.borrow().document();
+        insert_child(&document, &root, None);
+        parser.stack_of_open_elements.push(root.clone());
+        parser.context_element = Some(context.clone());
+        parser.reset_insertion_mode();
+        parser.construct_tree();
+
+        let fragment = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+        insert_child(&fragment, &root, None);
+        // <html>の子を文書片へ移してから、<html>自身を取り除く
+        loop {
+            let child = match root.borrow().first_child() {
+                Some(child) => child,
+                None => break,
+            };
+            insert_child(&fragment, &child, None);
         }
+        detach(&root);
+        fragment
+    }
+
+    pub fn quirks_mode(&self) -> QuirksMode {
+        self.quirks_mode
+    }
+
+    /// `HtmlTokenizer::new_streaming()` で作った字句解析器に受信したHTMLの一部を渡し、そこまでの木を構築する
+    pub fn feed(&mut self, chunk: &str) -> Rc<RefCell<Window>> {
+        self.t.feed(chunk);
+        self.construct_tree()
+    }
+
+    /// 入力の終わりを伝え、残りの木を構築する
+    pub fn finish(&mut self) -> Rc<RefCell<Window>> {
+        self.t.finish();
+        self.construct_tree()
+    }
+
+    /// 字句解析と木構築で見つかったエラーを、入力の位置の順に返す
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors = self.t.errors();
+        errors.extend(self.errors.iter().cloned());
+        errors.sort_by_key(|e| e.position().offset());
+        errors
+    }
+
+    /// 解析中に見つかったスタイルシートや画像などを、見つかった順に返す。
+    /// DOMツリーをたどり直さずに読み込みを始められる
+    pub fn resources(&self) -> Vec<SubResource> {
+        self.resources.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+    fn parse_error(&mut self, code: &str) {
+        self.errors.push(ParseError::new(code, self.position));
+    }
+
+    /// JavaScriptを実行できる場合はtrueにする
+    pub fn set_scripting(&mut self, scripting: bool) {
+        self.scripting = scripting;
+    }
+
+    /// 空白だけのテキストノードを作るかどうかを設定する
+    pub fn set_whitespace_policy(&mut self, policy: WhitespacePolicy) {
+        self.whitespace_policy = policy;
+    }
+
+    /// `kind` の要素の中でだけ、空白だけのテキストノードを作るかどうかを設定する
+    pub fn set_whitespace_override(&mut self, kind: ElementKind, policy: WhitespacePolicy) {
+        self.whitespace_overrides.retain(|(k, _)| *k != kind);
+        self.whitespace_overrides.push((kind, policy));
+    }
+
+    /// 入力を復号した文字コードを設定する。デフォルトはUTF-8
+    pub fn set_encoding(&mut self, encoding: &str) {
+        self.encoding = normalize_encoding_label(encoding);
+    }
+
+    /// <meta>で宣言された文字コードが入力を復号した文字コードと異なる場合に、その文字コードを返す。
+    /// 呼び出し側は入力をその文字コードで復号し直してから、もう一度解析する
+    pub fn encoding_change(&self) -> Option<String> {
+        self.declared_encoding.clone().filter(|e| *e != self.encoding)
+    }
+
+    /// <script>が閉じられるたびに `hook` を呼ぶ。`hook` は続きを解析する前に呼ばれるので、
+    /// スクリプトは文書の中の正しい位置で実行できる
+    pub fn set_script_hook(&mut self, hook: impl Fn(&str, &Rc<RefCell<Window>>) + 'static) {
+        self.script_hook = Some(ScriptHook(Rc::new(hook)));
     }
 
     pub fn construct_tree(&mut self) -> Rc<RefCell<Window>> {
         let mut token = self.t.next();
 
-        while token.is_some() {
-            match self.mode {
-                InsertionMode::Initial => {
-                    // DOCTYPEをサポートしていないためそれは文字トークンとして扱われる
-                    // 本実装ではそれを無視することにしている
-                    if let Some(HtmlToken::Char(_)) = token {
+        'tokens: while token.is_some() {
+            self.position = self.t.position();
+
+            if let Some(HtmlToken::Comment(ref data)) = token {
+                self.insert_comment(data);
+                token = self.t.next();
+                continue;
+            }
+
+            // foster parentingは表の中に直接書かれたトークンを処理する間だけ有効にする
+            self.foster_parenting = core::mem::take(&mut self.reprocess_in_body);
+            let mode = if self.foster_parenting {
+                InsertionMode::InBody
+            } else {
+                self.mode
+            };
+
+            // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+            if self.is_in_foreign_content(&token) {
+                match token {
+                    Some(HtmlToken::Char(c)) => {
+                        self.insert_char(c);
+                        token = self.t.next();
+                        continue;
+                    }
+                    Some(HtmlToken::StartTag {
+                        ref tag,
+                        self_closing,
+                        ref attributes,
+                    }) => {
+                        if !is_breakout_tag(tag, attributes) {
+                            let namespace = self.current_node().borrow().get_element().map_or(Namespace::Html, |e| e.namespace());
+                            self.insert_foreign_element(tag, attributes.to_vec(), namespace, self_closing);
+                            token = self.t.next();
+                            continue;
+                        }
+
+                        // Failed to parse. HTMLの要素まで閉じてから処理し直す
+                        self.parse_error("unexpected-html-element-in-foreign-content");
+                        while self.is_in_foreign_content(&token) {
+                            self.stack_of_open_elements.pop();
+                        }
+                    }
+                    Some(HtmlToken::EndTag { ref tag }) => {
+                        // HTMLの要素より内側に同じ名前の要素があれば閉じる。なければHTMLの規則で処理する
+                        for i in (0..self.stack_of_open_elements.len()).rev() {
+                            let element = match self.stack_of_open_elements[i].borrow().get_element() {
+                                Some(e) if e.namespace() != Namespace::Html => e,
+                                _ => break,
+                            };
+                            if element.local_name().eq_ignore_ascii_case(tag) {
+                                self.stack_of_open_elements.truncate(i);
+                                token = self.t.next();
+                                continue 'tokens;
+                            }
+                        }
+                    }
+                    Some(HtmlToken::Doctype { .. }) => {
+                        // Failed to parse. Skip the token.
+                        self.parse_error("unexpected-doctype");
                         token = self.t.next();
                         continue;
                     }
+                    Some(HtmlToken::Comment(_)) | Some(HtmlToken::Eof) | None => {}
+                }
+            }
+
+            match mode {
+                InsertionMode::Initial => {
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            if is_html_whitespace(c) {
+                                token = self.t.next();
+                                continue;
+                            }
+                        }
+                        Some(HtmlToken::Doctype {
+                                 ref name,
+                                 ref public_id,
+                                 ref system_id,
+                                 force_quirks,
+                             }) => {
+                            let doctype = DocumentType::new(
+                                name.as_deref().unwrap_or(""),
+                                public_id.as_deref().unwrap_or(""),
+                                system_id.as_deref().unwrap_or(""),
+                            );
+                            let node = Rc::new(RefCell::new(Node::new(NodeKind::DocumentType(doctype))));
+                            node.borrow_mut().set_source_location(Some(self.t.token_position()));
+                            let document = self.window.borrow().document();
+                            insert_child(&document, &node, None);
+
+                            self.set_quirks_mode(determine_quirks_mode(name, public_id, system_id, force_quirks));
+                            self.mode = InsertionMode::BeforeHtml;
+                            token = self.t.next();
+                            continue;
+                        }
+                        _ => {}
+                    }
 
+                    // DOCTYPEがない文書はquirks modeで扱う
+                    self.set_quirks_mode(QuirksMode::Quirks);
                     self.mode = InsertionMode::BeforeHtml;
                     continue;
                 }
                 InsertionMode::BeforeHtml => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
+                            if is_html_whitespace(c) {
                                 token = self.t.next();
                                 continue;
                             }
                         }
                         Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
                             if tag == "html" {
-                                self.insert_element(tag, attributes.to_vec());
+                                self.insert_element(tag, attributes.to_vec(), false);
                                 self.mode = InsertionMode::BeforeHead;
                                 token = self.t.next();
                                 continue;
@@ -65,21 +322,21 @@ impl HtmlParser {
                         _ => {}
                     }
 
-                    self.insert_element("html", Vec::new());
+                    self.insert_element("html", Vec::new(), false);
                     self.mode = InsertionMode::BeforeHead;
                     continue;
                 }
                 InsertionMode::BeforeHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
+                            if is_html_whitespace(c) {
                                 token = self.t.next();
                                 continue;
                             }
                         }
                         Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
                             if tag == "head" {
-                                self.insert_element(tag, attributes.to_vec());
+                                self.insert_element(tag, attributes.to_vec(), false);
                                 self.mode = InsertionMode::InHead;
                                 token = self.t.next();
                                 continue;
@@ -91,24 +348,68 @@ impl HtmlParser {
                         _ => {}
                     }
 
-                    self.insert_element("head", Vec::new());
+                    self.insert_element("head", Vec::new(), false);
                     self.mode = InsertionMode::InHead;
                     continue;
                 }
                 InsertionMode::InHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
+                            if is_html_whitespace(c) {
                                 self.insert_char(c);
                                 token = self.t.next();
                                 continue;
                             }
+
+                            // 空白以外の文字は<head>を閉じてから、<body>の中身として処理し直す
+                            self.pop_until(ElementKind::Head);
+                            self.mode = InsertionMode::AfterHead;
+                            continue;
                         }
                         Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
-                            if tag == "style" || tag == "script" {
-                                self.insert_element(tag, attributes.to_vec());
-                                self.original_insertion_mode = self.mode;
-                                self.mode = InsertionMode::Text;
+                            if tag == "title" {
+                                self.insert_text_element(tag, attributes.to_vec(), State::Rcdata);
+                                token = self.t.next();
+                                continue;
+                            }
+
+                            if tag == "style" {
+                                self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                token = self.t.next();
+                                continue;
+                            }
+
+                            if tag == "script" {
+                                self.insert_text_element(tag, attributes.to_vec(), State::ScriptData);
+                                token = self.t.next();
+                                continue;
+                            }
+
+                            if tag == "template" {
+                                self.insert_template(attributes.to_vec());
+                                token = self.t.next();
+                                continue;
+                            }
+
+                            // スクリプトを実行しない場合は、<noscript>の中身も<head>の中身として扱う
+                            if tag == "noscript" {
+                                if self.scripting {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                } else {
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                }
+                                token = self.t.next();
+                                continue;
+                            }
+
+                            if tag == "meta" || tag == "link" || tag == "base" {
+                                if tag == "meta" {
+                                    self.detect_encoding(attributes);
+                                }
+                                if tag == "base" {
+                                    self.set_base_href(attributes);
+                                }
+                                self.insert_element(tag, attributes.to_vec(), false);
                                 token = self.t.next();
                                 continue;
                             }
@@ -121,11 +422,9 @@ impl HtmlParser {
                                 continue;
                             }
 
-                            if let Ok(_) = ElementKind::from_str(tag) {
-                                self.pop_until(ElementKind::Head);
-                                self.mode = InsertionMode::AfterHead;
-                                continue;
-                            }
+                            self.pop_until(ElementKind::Head);
+                            self.mode = InsertionMode::AfterHead;
+                            continue;
                         }
                         Some(HtmlToken::EndTag { ref tag }) => {
                             if tag == "head" {
@@ -134,7 +433,20 @@ impl HtmlParser {
                                 self.pop_until(ElementKind::Head);
                                 continue;
                             }
+
+                            if tag == "noscript" {
+                                token = self.t.next();
+                                self.pop_current_node(ElementKind::Noscript);
+                                continue;
+                            }
+
+                            if tag == "body" || tag == "html" || tag == "br" {
+                                self.pop_until(ElementKind::Head);
+                                self.mode = InsertionMode::AfterHead;
+                                continue;
+                            }
                         }
+                        Some(HtmlToken::Doctype { .. }) | Some(HtmlToken::Comment(_)) => {}
                         Some(HtmlToken::Eof) | None => {
                             return self.window.clone();
                         }
@@ -146,7 +458,7 @@ impl HtmlParser {
                 InsertionMode::AfterHead => {
                     match token {
                         Some(HtmlToken::Char(c)) => {
-                            if c == ' ' || c == '\n' {
+                            if is_html_whitespace(c) {
                                 self.insert_char(c);
                                 token = self.t.next();
                                 continue;
@@ -158,7 +470,7 @@ impl HtmlParser {
                                  ..
                              }) => {
                             if tag == "body" {
-                                self.insert_element(tag, attributes.to_vec());
+                                self.insert_element(tag, attributes.to_vec(), false);
                                 token = self.t.next();
                                 self.mode = InsertionMode::InBody;
                                 continue;
@@ -170,21 +482,242 @@ impl HtmlParser {
                         _ => {}
                     }
 
-                    self.insert_element("body", Vec::new());
+                    self.insert_element("body", Vec::new(), false);
                     self.mode = InsertionMode::InBody;
                     continue;
                 }
-                InsertionMode::InBody => {
+                InsertionMode::InBody | InsertionMode::InCell => {
+                    if self.mode == InsertionMode::InCell {
+                        match token {
+                            Some(HtmlToken::StartTag { ref tag, .. })
+                                if matches!(tag.as_str(), "td" | "th" | "tr" | "tbody" | "thead") =>
+                            {
+                                self.close_cell();
+                                continue;
+                            }
+                            Some(HtmlToken::EndTag { ref tag }) => {
+                                match tag.as_str() {
+                                    "td" | "th" => {
+                                        let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                        token = self.t.next();
+                                        if self.has_in_table_scope(element_kind) {
+                                            self.pop_until(element_kind);
+                                            self.clear_active_formatting_elements_to_last_marker();
+                                            self.mode = InsertionMode::InRow;
+                                        }
+                                        continue;
+                                    }
+                                    "table" | "tbody" | "thead" | "tr" => {
+                                        let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                        if self.has_in_table_scope(element_kind) {
+                                            self.close_cell();
+                                        } else {
+                                            token = self.t.next();
+                                        }
+                                        continue;
+                                    }
+                                    "body" | "html" => {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                        token = self.t.next();
+                                        continue;
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
                     match token {
-                        Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
+                        Some(HtmlToken::StartTag {
+                            ref tag,
+                            self_closing,
+                            ref attributes,
+                        }) => {
+                            if closes_p_element(tag, self.quirks_mode) && self.has_in_button_scope(ElementKind::P) {
+                                self.close_p_element();
+                            }
+
                             match tag.as_str() {
-                                "p" | "h1" | "h2" | "a" => {
-                                    self.insert_element(tag, attributes.to_vec());
+                                "title" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rcdata);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "style" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "script" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::ScriptData);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                // 入れ子の閲覧文脈はまだないので、src属性を持つ要素だけを置いておく。
+                                // 中身はiframeに対応していないブラウザ向けのものなので、テキストとして扱う
+                                "iframe" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "xmp" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "plaintext" => {
+                                    // 文書の最後までをテキストとして扱う
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Plaintext);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "textarea" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::Rcdata);
+                                    token = self.t.next();
+                                    // 開始タグの直後の改行は無視する
+                                    if token == Some(HtmlToken::Char('\n')) {
+                                        token = self.t.next();
+                                    }
+                                    continue;
+                                }
+                                "template" => {
+                                    self.insert_template(attributes.to_vec());
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "select" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    self.mode = match self.mode {
+                                        InsertionMode::InTable
+                                        | InsertionMode::InTableBody
+                                        | InsertionMode::InRow
+                                        | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                                        _ => InsertionMode::InSelect,
+                                    };
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "option" | "optgroup" => {
+                                    self.pop_current_node(ElementKind::Option);
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "svg" | "math" => {
+                                    let namespace = if tag == "svg" { Namespace::Svg } else { Namespace::MathMl };
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_foreign_element(tag, attributes.to_vec(), namespace, self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "noscript" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    if self.scripting {
+                                        self.insert_text_element(tag, attributes.to_vec(), State::Rawtext);
+                                    } else {
+                                        self.insert_element(tag, attributes.to_vec(), false);
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "table" => {
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    self.mode = InsertionMode::InTable;
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "li" => {
+                                    self.close_list_item();
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "dt" | "dd" => {
+                                    self.close_definition_item();
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                                    // 見出しの中に見出しは入れられないので、開いている見出しを閉じる
+                                    if self.current_node_is_heading() {
+                                        self.stack_of_open_elements.pop();
+                                    }
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "button" => {
+                                    // <button>の中に<button>は入れられないので、開いている<button>を閉じる
+                                    if self.contain_in_stack(ElementKind::Button) {
+                                        self.pop_until(ElementKind::Button);
+                                    }
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "a" => {
+                                    // <a>の中に<a>は入れられないので、開いている<a>を閉じる
+                                    if let Some(a) = self.active_formatting_element(ElementKind::A) {
+                                        self.run_adoption_agency(ElementKind::A);
+                                        self.active_formatting_elements.retain(|e| !e.as_ref().is_some_and(|e| Rc::ptr_eq(e, &a)));
+                                        self.stack_of_open_elements.retain(|e| !Rc::ptr_eq(e, &a));
+                                    }
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_formatting_element(tag, attributes.to_vec());
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "b" | "i" | "em" | "strong" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_formatting_element(tag, attributes.to_vec());
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "span" | "label" | "br" | "img" | "input" | "audio" | "video" => {
+                                    self.reconstruct_active_formatting_elements();
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                // <source>と<track>は空要素なので、開いている要素のスタックに積まれない
+                                "p" | "div" | "form" | "ul" | "ol" | "dl" | "hr" | "source" | "track" => {
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "pre" => {
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
+                                    // 開始タグの直後の改行は、HTMLを読みやすくするためのものなので無視する
+                                    token = self.t.next();
+                                    if let Some(HtmlToken::Char('\n')) = token {
+                                        token = self.t.next();
+                                    }
+                                    continue;
+                                }
+                                // 表の外に書かれた表の部品と、2つ目の<html>、<head>、<body>は無視する
+                                "html" | "head" | "body" | "frameset" | "frame" | "caption" | "col" | "colgroup" | "tbody"
+                                | "thead" | "tfoot" | "tr" | "td" | "th" => {
+                                    // Failed to parse. Skip the token.
+                                    self.parse_error("unexpected-start-tag");
                                     token = self.t.next();
                                     continue;
                                 }
+                                // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+                                // section、article、navなどの種類を区別しない要素も、要素名をそのまま持つ要素として挿入する
                                 _ => {
+                                    if !closes_p_element(tag, self.quirks_mode) {
+                                        self.reconstruct_active_formatting_elements();
+                                    }
+                                    self.insert_element(tag, attributes.to_vec(), self_closing);
                                     token = self.t.next();
+                                    continue;
                                 }
                             }
                         }
@@ -195,73 +728,464 @@ impl HtmlParser {
                                     token = self.t.next();
                                     if !self.contain_in_stack(ElementKind::Body) {
                                         // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
                                         continue;
                                     }
                                     self.pop_until(ElementKind::Body);
                                     continue;
                                 }
                                 "html" => {
-                                    if self.pop_current_node(ElementKind::Body) {
-                                        self.mode = InsertionMode::AfterBody;
-                                        assert!(self.pop_current_node(ElementKind::Html))
-                                    } else {
+                                    // <html>と<body>はスタックに残したまま、after bodyで処理し直す
+                                    if !self.contain_in_stack(ElementKind::Body) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
                                         token = self.t.next();
+                                        continue;
                                     }
+                                    self.mode = InsertionMode::AfterBody;
                                     continue;
                                 }
-                                "p" | "h1" | "h2" | "a" => {
-                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                "template" => {
                                     token = self.t.next();
-                                    self.pop_until(element_kind);
+                                    if !self.contain_in_stack(ElementKind::Template) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                        continue;
+                                    }
+                                    self.generate_implied_end_tags(None);
+                                    self.pop_until(ElementKind::Template);
+                                    self.clear_active_formatting_elements_to_last_marker();
+                                    self.reset_insertion_mode();
                                     continue;
                                 }
-                                _ => {
+                                "br" => {
+                                    // </br>は<br>として扱う
+                                    // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+                                    self.insert_element(tag, Vec::new(), false);
                                     token = self.t.next();
+                                    continue;
                                 }
-                            }
-                        }
-                        Some(HtmlToken::Char(c)) => {
-                            self.insert_char(c);
-                            token = self.t.next();
-                            continue;
-                        }
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                    }
-                }
-                InsertionMode::Text => {
-                    match token {
-                        Some(HtmlToken::Eof) | None => {
-                            return self.window.clone();
-                        }
-                        Some(HtmlToken::EndTag { ref tag }) => {
-                            match tag.as_str() {
-                                "style" => {
-                                    self.pop_until(ElementKind::Style);
-                                    self.mode = self.original_insertion_mode;
+                                "p" => {
                                     token = self.t.next();
+                                    if !self.has_in_button_scope(ElementKind::P) {
+                                        // Failed to parse. 対応する<p>がない</p>は空の<p>として扱う
+                                        self.parse_error("unexpected-end-tag");
+                                        self.insert_element("p", Vec::new(), false);
+                                    }
+                                    self.close_p_element();
                                     continue;
                                 }
-                                "script" => {
-                                    self.pop_until(ElementKind::Script);
-                                    self.mode = self.original_insertion_mode;
+                                "li" => {
                                     token = self.t.next();
+                                    if self.has_in_list_item_scope(ElementKind::Li) {
+                                        self.generate_implied_end_tags(Some(ElementKind::Li));
+                                        self.pop_until(ElementKind::Li);
+                                    }
                                     continue;
                                 }
-                                _ => {}
-                            }
-                        }
-                        Some(HtmlToken::Char(c)) => {
-                            self.insert_char(c);
-                            token = self.t.next();
-                            continue;
-                        }
-                        _ => {}
+                                "dt" | "dd" => {
+                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                    token = self.t.next();
+                                    if !self.has_in_scope(element_kind, &[]) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                        continue;
+                                    }
+                                    self.generate_implied_end_tags(Some(element_kind));
+                                    self.pop_until(element_kind);
+                                    continue;
+                                }
+                                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                                    // 開始タグと異なるレベルの終了タグでも、開いている見出しを閉じる
+                                    token = self.t.next();
+                                    if !self.stack_of_open_elements.iter().any(|n| is_heading(n.borrow().element_kind())) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                        continue;
+                                    }
+                                    while let Some(node) = self.stack_of_open_elements.pop() {
+                                        if is_heading(node.borrow().element_kind()) {
+                                            break;
+                                        }
+                                    }
+                                    continue;
+                                }
+                                "a" | "b" | "i" | "em" | "strong" => {
+                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                    if !self.run_adoption_agency(element_kind) {
+                                        self.close_element(tag);
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "div" | "pre" | "span" | "form" | "button" | "label" | "ul" | "ol" | "dl" | "noscript"
+                                | "audio" | "video" | "option" | "optgroup" => {
+                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                    token = self.t.next();
+                                    if !self.contain_in_stack(element_kind) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                        continue;
+                                    }
+                                    self.pop_until(element_kind);
+                                    continue;
+                                }
+                                _ => {
+                                    self.close_element(tag);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                            }
+                        }
+                        Some(HtmlToken::Char(c)) => {
+                            self.reconstruct_active_formatting_elements();
+                            self.insert_char(c);
+                            token = self.t.next();
+                            continue;
+                        }
+                        Some(HtmlToken::Doctype { .. }) => {
+                            // Failed to parse. Skip the token.
+                            self.parse_error("unexpected-doctype");
+                            token = self.t.next();
+                        }
+                        Some(HtmlToken::Comment(_)) => {
+                            token = self.t.next();
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                    }
+                }
+                InsertionMode::Text => {
+                    match token {
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                        Some(HtmlToken::EndTag { .. }) => {
+                            // 字句解析器は開いている要素に対応する終了タグしか出力しない
+                            let node = self.stack_of_open_elements.pop();
+                            self.mode = self.original_insertion_mode;
+                            if let Some(node) = node {
+                                if node.borrow().element_kind() == Some(ElementKind::Script) {
+                                    self.run_script_hook(&node);
+                                    self.insert_pending_writes();
+                                }
+                            }
+                            token = self.t.next();
+                            continue;
+                        }
+                        Some(HtmlToken::Char(c)) => {
+                            self.insert_char(c);
+                            token = self.t.next();
+                            continue;
+                        }
+                        _ => {}
                     }
 
                     self.mode = self.original_insertion_mode;
                 }
+                InsertionMode::InTable => {
+                    match token {
+                        Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
+                            match tag.as_str() {
+                                "tbody" | "thead" => {
+                                    self.clear_stack_back_to(&[ElementKind::Table]);
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    self.mode = InsertionMode::InTableBody;
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "tr" | "td" | "th" => {
+                                    // <tbody>が省略されている
+                                    self.clear_stack_back_to(&[ElementKind::Table]);
+                                    self.insert_element("tbody", Vec::new(), false);
+                                    self.mode = InsertionMode::InTableBody;
+                                    continue;
+                                }
+                                "table" => {
+                                    // Failed to parse. 開いている<table>を閉じてから処理し直す
+                                    self.parse_error("unexpected-start-tag");
+                                    if self.close_table() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) if tag == "table" => {
+                            token = self.t.next();
+                            self.close_table();
+                            continue;
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    if self.is_foster_parented(&token) {
+                        self.parse_error("unexpected-token-in-table");
+                        self.reprocess_in_body = true;
+                        continue;
+                    }
+
+                    // Failed to parse. Skip the token.
+                    self.parse_error("unexpected-token-in-table");
+                    token = self.t.next();
+                }
+                InsertionMode::InTableBody => {
+                    match token {
+                        Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
+                            match tag.as_str() {
+                                "tr" => {
+                                    self.clear_stack_back_to(&[ElementKind::Tbody, ElementKind::Thead]);
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    self.mode = InsertionMode::InRow;
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "td" | "th" => {
+                                    // <tr>が省略されている
+                                    self.clear_stack_back_to(&[ElementKind::Tbody, ElementKind::Thead]);
+                                    self.insert_element("tr", Vec::new(), false);
+                                    self.mode = InsertionMode::InRow;
+                                    continue;
+                                }
+                                "tbody" | "thead" | "table" => {
+                                    if self.close_table_body() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            match tag.as_str() {
+                                "tbody" | "thead" => {
+                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                    token = self.t.next();
+                                    if self.has_in_table_scope(element_kind) {
+                                        self.close_table_body();
+                                    }
+                                    continue;
+                                }
+                                "table" => {
+                                    if self.close_table_body() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    if self.is_foster_parented(&token) {
+                        self.parse_error("unexpected-token-in-table");
+                        self.reprocess_in_body = true;
+                        continue;
+                    }
+
+                    // Failed to parse. Skip the token.
+                    self.parse_error("unexpected-token-in-table");
+                    token = self.t.next();
+                }
+                InsertionMode::InRow => {
+                    match token {
+                        Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
+                            match tag.as_str() {
+                                "td" | "th" => {
+                                    self.clear_stack_back_to(&[ElementKind::Tr]);
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    self.active_formatting_elements.push(None);
+                                    self.mode = InsertionMode::InCell;
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "tr" | "tbody" | "thead" | "table" => {
+                                    if self.close_row() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            match tag.as_str() {
+                                "tr" => {
+                                    token = self.t.next();
+                                    self.close_row();
+                                    continue;
+                                }
+                                "table" => {
+                                    if self.close_row() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "tbody" | "thead" => {
+                                    let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                    if self.has_in_table_scope(element_kind) && self.close_row() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    if self.is_foster_parented(&token) {
+                        self.parse_error("unexpected-token-in-table");
+                        self.reprocess_in_body = true;
+                        continue;
+                    }
+
+                    // Failed to parse. Skip the token.
+                    self.parse_error("unexpected-token-in-table");
+                    token = self.t.next();
+                }
+                // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselect
+                InsertionMode::InSelect | InsertionMode::InSelectInTable => {
+                    // https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inselectintable
+                    // 表の部品のタグは<select>を閉じてから処理し直す
+                    if self.mode == InsertionMode::InSelectInTable {
+                        match token {
+                            Some(HtmlToken::StartTag { ref tag, .. })
+                                if matches!(tag.as_str(), "table" | "tbody" | "thead" | "tr" | "td" | "th") =>
+                            {
+                                self.parse_error("unexpected-start-tag-in-select");
+                                self.close_select();
+                                continue;
+                            }
+                            Some(HtmlToken::EndTag { ref tag })
+                                if matches!(tag.as_str(), "table" | "tbody" | "thead" | "tr" | "td" | "th") =>
+                            {
+                                self.parse_error("unexpected-end-tag-in-select");
+                                let element_kind = ElementKind::from_str(tag).expect("Failed to convert string to ElementKind");
+                                if !self.has_in_table_scope(element_kind) {
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                self.close_select();
+                                continue;
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    match token {
+                        Some(HtmlToken::Char(c)) => {
+                            self.insert_char(c);
+                            token = self.t.next();
+                            continue;
+                        }
+                        Some(HtmlToken::StartTag { ref tag, ref attributes, .. }) => {
+                            match tag.as_str() {
+                                "option" => {
+                                    self.pop_current_node(ElementKind::Option);
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "optgroup" | "hr" => {
+                                    self.pop_current_node(ElementKind::Option);
+                                    self.pop_current_node(ElementKind::Optgroup);
+                                    self.insert_element(tag, attributes.to_vec(), false);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                // 入れ子の<select>は、開いている<select>の終了タグとして扱う
+                                "select" => {
+                                    self.parse_error("unexpected-start-tag-in-select");
+                                    token = self.t.next();
+                                    self.close_select();
+                                    continue;
+                                }
+                                "input" | "textarea" => {
+                                    self.parse_error("unexpected-start-tag-in-select");
+                                    if self.close_select() {
+                                        continue;
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "script" => {
+                                    self.insert_text_element(tag, attributes.to_vec(), State::ScriptData);
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "template" => {
+                                    self.insert_template(attributes.to_vec());
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::EndTag { ref tag }) => {
+                            match tag.as_str() {
+                                "optgroup" => {
+                                    let length = self.stack_of_open_elements.len();
+                                    let parent_is_optgroup = length >= 2
+                                        && self.stack_of_open_elements[length - 2].borrow().element_kind()
+                                            == Some(ElementKind::Optgroup);
+                                    if parent_is_optgroup {
+                                        self.pop_current_node(ElementKind::Option);
+                                    }
+                                    if !self.pop_current_node(ElementKind::Optgroup) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "option" => {
+                                    if !self.pop_current_node(ElementKind::Option) {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                    }
+                                    token = self.t.next();
+                                    continue;
+                                }
+                                "select" => {
+                                    token = self.t.next();
+                                    if !self.close_select() {
+                                        // Failed to parse. Skip the token.
+                                        self.parse_error("unexpected-end-tag");
+                                    }
+                                    continue;
+                                }
+                                _ => {}
+                            }
+                        }
+                        Some(HtmlToken::Eof) | None => {
+                            return self.window.clone();
+                        }
+                        _ => {}
+                    }
+
+                    // Failed to parse. Skip the token.
+                    self.parse_error("unexpected-token-in-select");
+                    token = self.t.next();
+                }
                 InsertionMode::AfterBody => {
                     match token {
                         Some(HtmlToken::Char(_)) => {
@@ -295,236 +1219,2472 @@ impl HtmlParser {
                         _ => {}
                     }
 
-                    // Failed to parse
-                    self.mode = InsertionMode::InBody;
-                }
-            }
+                    // Failed to parse
+                    self.parse_error("unexpected-token-after-body");
+                    self.mode = InsertionMode::InBody;
+                }
+            }
+        }
+
+        self.window.clone()
+    }
+
+    /// 空要素と `self_closing` がtrueの要素は子を持たないので、開いている要素のスタックに積まない
+    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>, self_closing: bool) {
+        self.discover_resource(tag, &attributes);
+        let node = Rc::new(RefCell::new(create_element_node(tag, attributes)));
+        let current = self.current_node();
+        self.insert_node(&current, &node);
+        if !self_closing && !is_void_element(tag) {
+            self.stack_of_open_elements.push(node);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inhead
+    /// <template>の中身は、in bodyの規則で文書とは別の断片に構築する
+    fn insert_template(&mut self, attributes: Vec<Attribute>) {
+        self.insert_element("template", attributes, false);
+        let content = Rc::new(RefCell::new(Node::new(NodeKind::DocumentFragment)));
+        self.current_node().borrow_mut().set_template_content(Some(content));
+        self.active_formatting_elements.push(None);
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// 構文解析器と文書の両方に設定する
+    fn set_quirks_mode(&mut self, quirks_mode: QuirksMode) {
+        self.quirks_mode = quirks_mode;
+        self.window.borrow_mut().set_quirks_mode(quirks_mode);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-comment
+    /// <html>の外に書かれたコメントは文書に、</body>の後に書かれたコメントは<html>に挿入する
+    fn insert_comment(&mut self, data: &str) {
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Comment(data.to_string()))));
+        match self.mode {
+            InsertionMode::Initial | InsertionMode::BeforeHtml | InsertionMode::AfterAfterBody => {
+                node.borrow_mut().set_source_location(Some(self.t.token_position()));
+                let document = self.window.borrow().document();
+                insert_child(&document, &node, None);
+            }
+            InsertionMode::AfterBody => {
+                node.borrow_mut().set_source_location(Some(self.t.token_position()));
+                if let Some(html) = self.stack_of_open_elements.first() {
+                    insert_child(html, &node, None);
+                }
+            }
+            _ => self.insert_node(&self.current_node(), &node),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insert-a-foreign-element
+    fn insert_foreign_element(&mut self, tag: &str, attributes: Vec<Attribute>, namespace: Namespace, self_closing: bool) {
+        let name = match namespace {
+            Namespace::Svg => adjust_svg_tag_name(tag),
+            _ => tag.to_string(),
+        };
+        let attributes = attributes
+            .into_iter()
+            .map(|a| adjust_foreign_attribute(a.into_owned(), namespace))
+            .collect();
+        let element = Element::new_foreign(&name, attributes, namespace);
+        let node = Rc::new(RefCell::new(Node::new(NodeKind::Element(element))));
+        let current = self.current_node();
+        self.insert_node(&current, &node);
+        if !self_closing {
+            self.stack_of_open_elements.push(node);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tree-construction-dispatcher
+    /// 現在のノードがSVGやMathMLの要素で、トークンを外部コンテンツの規則で処理するときはtrueを返す
+    fn is_in_foreign_content(&self, token: &Option<HtmlToken>) -> bool {
+        let element = match self.stack_of_open_elements.last().and_then(|n| n.borrow().get_element()) {
+            Some(e) => e,
+            None => return false,
+        };
+        if element.namespace() == Namespace::Html {
+            return false;
+        }
+
+        match token {
+            Some(HtmlToken::StartTag { tag, .. }) => {
+                if is_mathml_text_integration_point(&element) && tag != "mglyph" && tag != "malignmark" {
+                    return false;
+                }
+                if element.namespace() == Namespace::MathMl && element.local_name() == "annotation-xml" && tag == "svg" {
+                    return false;
+                }
+                !is_html_integration_point(&element)
+            }
+            Some(HtmlToken::Char(_)) => {
+                !is_mathml_text_integration_point(&element) && !is_html_integration_point(&element)
+            }
+            Some(HtmlToken::Eof) | None => false,
+            _ => true,
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#scriptEndTag
+    fn run_script_hook(&self, script: &Rc<RefCell<Node>>) {
+        let hook = match self.script_hook {
+            Some(ref hook) => hook.clone(),
+            None => return,
+        };
+
+        let mut text = String::new();
+        let mut child = script.borrow().first_child();
+        while let Some(node) = child {
+            if let NodeKind::Text(ref s) = node.borrow().kind {
+                text.push_str(s);
+            }
+            child = node.borrow().next_sibling();
+        }
+
+        (hook.0)(&text, &self.window);
+    }
+
+    /// スクリプトが `document.write()` で書き込んだ文字列を、スクリプトの直後に挿入する
+    fn insert_pending_writes(&mut self) {
+        let written = self.window.borrow_mut().take_pending_writes();
+        if !written.is_empty() {
+            self.t.insert_input(&written);
+        }
+    }
+
+    fn current_node(&self) -> Rc<RefCell<Node>> {
+        match self.stack_of_open_elements.last() {
+            Some(n) => n.clone(),
+            None => self.window.borrow().document(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#appropriate-place-for-inserting-a-node
+    fn insert_node(&self, target: &Rc<RefCell<Node>>, node: &Rc<RefCell<Node>>) {
+        // 省略された開始タグから作った要素は、省略の原因になったトークンの位置を持つ。
+        // 位置はノードを作るときにだけ求め、テキストノードに追加するだけの文字では求めない
+        if node.borrow().source_location().is_none() {
+            node.borrow_mut().set_source_location(Some(self.t.token_position()));
+        }
+
+        let target = &insertion_target(target);
+        if let Some(table) = self.foster_parenting_table(target) {
+            let parent = table.borrow().parent();
+            if let Some(parent) = parent {
+                insert_child(&parent, node, Some(&table));
+                return;
+            }
+        }
+
+        insert_child(target, node, None);
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#foster-parent
+    /// 表の部品の中に直接置けないノードは、最後に開いた<table>の前に挿入するので、その<table>を返す
+    fn foster_parenting_table(&self, target: &Rc<RefCell<Node>>) -> Option<Rc<RefCell<Node>>> {
+        if !self.foster_parenting || !is_table_part(target.borrow().element_kind()) {
+            return None;
+        }
+
+        self.stack_of_open_elements
+            .iter()
+            .rev()
+            .find(|n| n.borrow().element_kind() == Some(ElementKind::Table))
+            .cloned()
+    }
+
+    /// 表の中に直接書かれたトークンのうち、in bodyの規則で処理するものか
+    fn is_foster_parented(&self, token: &Option<HtmlToken>) -> bool {
+        match token {
+            // 対応する要素が開いていない表の部品の終了タグは無視する
+            Some(HtmlToken::EndTag { tag }) => {
+                !matches!(tag.as_str(), "body" | "html" | "table" | "tbody" | "thead" | "tr" | "td" | "th")
+            }
+            // 表の部品の間にある空白は無視する
+            Some(HtmlToken::Char(c)) if is_html_whitespace(*c) => {
+                !is_table_part(self.current_node().borrow().element_kind())
+            }
+            Some(HtmlToken::Doctype { .. }) => false,
+            _ => true,
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generic-rcdata-element-parsing-algorithm
+    /// 中身をタグとして解釈しない要素を挿入し、終了タグまでの文字をその子として扱う
+    fn insert_text_element(&mut self, tag: &str, attributes: Vec<Attribute>, state: State) {
+        self.insert_element(tag, attributes, false);
+        self.t.switch_to(state);
+        self.original_insertion_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
+        let current = match self.stack_of_open_elements.last() {
+            Some(n) => n,
+            None => return false,
+        };
+
+        if current.borrow().element_kind() == Some(element_kind) {
+            self.stack_of_open_elements.pop();
+            return true;
+        }
+
+        false
+    }
+
+    fn pop_until(&mut self, element_kind: ElementKind) {
+        assert!(
+            self.contain_in_stack(element_kind),
+            "stack doesn't have an element {:?}",
+            element_kind,
+        );
+
+        loop {
+            let current = match self.stack_of_open_elements.pop() {
+                Some(n) => n,
+                None => return,
+            };
+
+            if current.borrow().element_kind() == Some(element_kind) {
+                return;
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    /// <dt>と<dd>の開始タグは、開いている<dt>または<dd>を閉じる
+    fn close_definition_item(&mut self) {
+        let mut item = None;
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = node.borrow().element_kind();
+            match kind {
+                Some(ElementKind::Dt) | Some(ElementKind::Dd) => {
+                    item = kind;
+                    break;
+                }
+                // <address>、<div>、<p>以外の特別な要素の外側にある<dt>と<dd>は閉じない
+                Some(ElementKind::Div) | Some(ElementKind::P) => {}
+                _ if is_special_node(node) && !is_element_named(node, "address") => return,
+                _ => {}
+            }
+        }
+
+        if let Some(kind) = item {
+            self.generate_implied_end_tags(Some(kind));
+            self.pop_until(kind);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    /// <li>の開始タグは、同じリストの中で開いている<li>を閉じる
+    fn close_list_item(&mut self) {
+        for node in self.stack_of_open_elements.iter().rev() {
+            match node.borrow().element_kind() {
+                Some(ElementKind::Li) => break,
+                // リストの外側にある<li>は閉じない
+                Some(ElementKind::Ul) | Some(ElementKind::Ol) => return,
+                _ => {}
+            }
+        }
+
+        if self.contain_in_stack(ElementKind::Li) {
+            self.pop_until(ElementKind::Li);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-the-specific-scope
+    /// スタックの上から順に探し、`element_kind` より先に境界となる要素が見つかればfalseを返す
+    fn has_in_scope(&self, element_kind: ElementKind, boundaries: &[ElementKind]) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = match node.borrow().element_kind() {
+                Some(kind) => kind,
+                None => continue,
+            };
+            if kind == element_kind {
+                return true;
+            }
+            if matches!(kind, ElementKind::Html | ElementKind::Table | ElementKind::Td | ElementKind::Th)
+                || boundaries.contains(&kind)
+            {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-button-scope
+    fn has_in_button_scope(&self, element_kind: ElementKind) -> bool {
+        self.has_in_scope(element_kind, &[ElementKind::Button])
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-list-item-scope
+    fn has_in_list_item_scope(&self, element_kind: ElementKind) -> bool {
+        self.has_in_scope(element_kind, &[ElementKind::Ol, ElementKind::Ul])
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#generate-implied-end-tags
+    /// 終了タグを省略できる要素を、`exception` 以外について閉じる
+    fn generate_implied_end_tags(&mut self, exception: Option<ElementKind>) {
+        while let Some(current) = self.stack_of_open_elements.last() {
+            let kind = current.borrow().element_kind();
+            if kind == exception
+                || !matches!(
+                    kind,
+                    Some(ElementKind::P) | Some(ElementKind::Li) | Some(ElementKind::Dt) | Some(ElementKind::Dd)
+                )
+            {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-a-p-element
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some(ElementKind::P));
+        self.pop_until(ElementKind::P);
+    }
+
+    fn current_node_is_heading(&self) -> bool {
+        match self.stack_of_open_elements.last() {
+            Some(current) => is_heading(current.borrow().element_kind()),
+            None => false,
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-table-scope
+    fn has_in_table_scope(&self, element_kind: ElementKind) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = node.borrow().element_kind();
+            if kind == Some(element_kind) {
+                return true;
+            }
+            if kind == Some(ElementKind::Html) || kind == Some(ElementKind::Table) {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-stack-back-to-a-table-context
+    /// 現在のノードが `element_kinds` のいずれかか<html>になるまでスタックから取り除く
+    fn clear_stack_back_to(&mut self, element_kinds: &[ElementKind]) {
+        while let Some(current) = self.stack_of_open_elements.last() {
+            let kind = current.borrow().element_kind();
+            if kind == Some(ElementKind::Html) || kind.is_some_and(|k| element_kinds.contains(&k)) {
+                return;
+            }
+            self.stack_of_open_elements.pop();
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reset-the-insertion-mode-appropriately
+    /// 断片の解析では、最も外側の要素の代わりに断片を挿入する先の要素を使う
+    fn reset_insertion_mode(&mut self) {
+        for (i, node) in self.stack_of_open_elements.iter().enumerate().rev() {
+            let last = i == 0;
+            let node = match self.context_element {
+                Some(ref context) if last => context,
+                _ => node,
+            };
+            self.mode = match node.borrow().element_kind() {
+                Some(ElementKind::Select) if self.contain_in_stack(ElementKind::Table) => InsertionMode::InSelectInTable,
+                Some(ElementKind::Select) => InsertionMode::InSelect,
+                Some(ElementKind::Td) | Some(ElementKind::Th) if !last => InsertionMode::InCell,
+                Some(ElementKind::Tr) => InsertionMode::InRow,
+                Some(ElementKind::Tbody) | Some(ElementKind::Thead) => InsertionMode::InTableBody,
+                Some(ElementKind::Table) => InsertionMode::InTable,
+                Some(ElementKind::Body) => InsertionMode::InBody,
+                Some(ElementKind::Head) if !last => InsertionMode::InHead,
+                Some(ElementKind::Html) if self.context_element.is_some() => InsertionMode::BeforeHead,
+                _ => continue,
+            };
+            return;
+        }
+
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#has-an-element-in-select-scope
+    /// <option>と<optgroup>以外のすべての要素が境界になる
+    fn has_in_select_scope(&self, element_kind: ElementKind) -> bool {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = node.borrow().element_kind();
+            if kind == Some(element_kind) {
+                return true;
+            }
+            if kind != Some(ElementKind::Option) && kind != Some(ElementKind::Optgroup) {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// 開いている<select>を閉じる。閉じる<select>がなければfalseを返す
+    fn close_select(&mut self) -> bool {
+        if !self.has_in_select_scope(ElementKind::Select) {
+            return false;
+        }
+
+        self.pop_until(ElementKind::Select);
+        self.reset_insertion_mode();
+        true
+    }
+
+    /// 開いている<table>を閉じる。閉じる<table>がなければfalseを返す
+    fn close_table(&mut self) -> bool {
+        if !self.has_in_table_scope(ElementKind::Table) {
+            return false;
+        }
+
+        self.pop_until(ElementKind::Table);
+        self.reset_insertion_mode();
+        true
+    }
+
+    /// 開いている<tbody>または<thead>を閉じる。閉じるものがなければfalseを返す
+    fn close_table_body(&mut self) -> bool {
+        if !self.has_in_table_scope(ElementKind::Tbody) && !self.has_in_table_scope(ElementKind::Thead) {
+            return false;
+        }
+
+        self.clear_stack_back_to(&[ElementKind::Tbody, ElementKind::Thead]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    /// 開いている<tr>を閉じる。閉じる<tr>がなければfalseを返す
+    fn close_row(&mut self) -> bool {
+        if !self.has_in_table_scope(ElementKind::Tr) {
+            return false;
+        }
+
+        self.clear_stack_back_to(&[ElementKind::Tr]);
+        self.stack_of_open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#close-the-cell
+    fn close_cell(&mut self) {
+        let cell = if self.has_in_table_scope(ElementKind::Td) {
+            ElementKind::Td
+        } else {
+            ElementKind::Th
+        };
+        if self.contain_in_stack(cell) {
+            self.pop_until(cell);
+        }
+        self.clear_active_formatting_elements_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn insert_formatting_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
+        self.insert_element(tag, attributes, false);
+        let node = self.stack_of_open_elements.last().cloned();
+        self.active_formatting_elements.push(node);
+    }
+
+    /// 最後のmarkerより後ろにある `element_kind` の書式要素を返す
+    fn active_formatting_element(&self, element_kind: ElementKind) -> Option<Rc<RefCell<Node>>> {
+        self.active_formatting_elements
+            .iter()
+            .rev()
+            .map_while(|e| e.as_ref())
+            .find(|e| e.borrow().element_kind() == Some(element_kind))
+            .cloned()
+    }
+
+    fn index_in_active_formatting_elements(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.active_formatting_elements
+            .iter()
+            .position(|e| e.as_ref().is_some_and(|e| Rc::ptr_eq(e, node)))
+    }
+
+    fn index_in_stack(&self, node: &Rc<RefCell<Node>>) -> Option<usize> {
+        self.stack_of_open_elements.iter().position(|e| Rc::ptr_eq(e, node))
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#reconstruct-the-active-formatting-elements
+    /// 閉じられた書式要素を作り直し、以降の内容にも書式が引き継がれるようにする
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let mut index = self.active_formatting_elements.len();
+        while index > 0 {
+            match self.active_formatting_elements[index - 1] {
+                Some(ref node) if self.index_in_stack(node).is_none() => index -= 1,
+                _ => break,
+            }
+        }
+
+        for i in index..self.active_formatting_elements.len() {
+            let entry = self.active_formatting_elements[i].clone().expect("Failed to get a formatting element");
+            let node = clone_element_node(&entry);
+            let current = self.current_node();
+            self.insert_node(&current, &node);
+            self.stack_of_open_elements.push(node.clone());
+            self.active_formatting_elements[i] = Some(node);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#clear-the-list-of-active-formatting-elements-up-to-the-last-marker
+    fn clear_active_formatting_elements_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting_elements.pop() {
+            if entry.is_none() {
+                return;
+            }
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#adoption-agency-algorithm
+    /// 入れ子が崩れた書式要素の終了タグを処理する。対応する書式要素がなく、通常の終了タグとして扱うべきときはfalseを返す
+    fn run_adoption_agency(&mut self, subject: ElementKind) -> bool {
+        if let Some(current) = self.stack_of_open_elements.last() {
+            if current.borrow().element_kind() == Some(subject)
+                && self.index_in_active_formatting_elements(current).is_none()
+            {
+                self.stack_of_open_elements.pop();
+                return true;
+            }
+        }
+
+        for _ in 0..8 {
+            let formatting_element = match self.active_formatting_element(subject) {
+                Some(e) => e,
+                None => return false,
+            };
+
+            let formatting_element_index = match self.index_in_stack(&formatting_element) {
+                Some(i) => i,
+                None => {
+                    // Failed to parse. 既に閉じられた書式要素はリストから取り除く
+                    self.parse_error("unexpected-end-tag");
+                    self.active_formatting_elements
+                        .retain(|e| !e.as_ref().is_some_and(|e| Rc::ptr_eq(e, &formatting_element)));
+                    return true;
+                }
+            };
+            if !self.has_in_scope(subject, &[]) {
+                // Failed to parse. Skip the token.
+                self.parse_error("unexpected-end-tag");
+                return true;
+            }
+
+            // 書式要素より内側にある、最も外側の特別な要素
+            let furthest_block_index = match (formatting_element_index + 1..self.stack_of_open_elements.len())
+                .find(|&i| is_special_node(&self.stack_of_open_elements[i]))
+            {
+                Some(i) => i,
+                None => {
+                    self.stack_of_open_elements.truncate(formatting_element_index);
+                    self.active_formatting_elements
+                        .retain(|e| !e.as_ref().is_some_and(|e| Rc::ptr_eq(e, &formatting_element)));
+                    return true;
+                }
+            };
+            let furthest_block = self.stack_of_open_elements[furthest_block_index].clone();
+            let common_ancestor = self.stack_of_open_elements[formatting_element_index - 1].clone();
+            let mut bookmark = self
+                .index_in_active_formatting_elements(&formatting_element)
+                .expect("Failed to find a formatting element");
+
+            // 書式要素と特別な要素の間にある書式要素を作り直し、特別な要素をその中へ移す
+            let mut last_node = furthest_block.clone();
+            let mut node_index = furthest_block_index;
+            let mut inner_loop_counter = 0;
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let node = self.stack_of_open_elements[node_index].clone();
+                if Rc::ptr_eq(&node, &formatting_element) {
+                    break;
+                }
+
+                let mut list_index = self.index_in_active_formatting_elements(&node);
+                if let Some(i) = list_index {
+                    if inner_loop_counter > 3 {
+                        self.active_formatting_elements.remove(i);
+                        if i < bookmark {
+                            bookmark -= 1;
+                        }
+                        list_index = None;
+                    }
+                }
+                let list_index = match list_index {
+                    Some(i) => i,
+                    None => {
+                        self.stack_of_open_elements.remove(node_index);
+                        continue;
+                    }
+                };
+
+                let new_node = clone_element_node(&node);
+                self.active_formatting_elements[list_index] = Some(new_node.clone());
+                self.stack_of_open_elements[node_index] = new_node.clone();
+                if Rc::ptr_eq(&last_node, &furthest_block) {
+                    bookmark = list_index + 1;
+                }
+
+                insert_child(&new_node, &last_node, None);
+                last_node = new_node;
+            }
+
+            detach(&last_node);
+            self.insert_node(&common_ancestor, &last_node);
+
+            // 特別な要素の子を、作り直した書式要素の中へ移す
+            let new_element = clone_element_node(&formatting_element);
+            loop {
+                let child = match furthest_block.borrow().first_child() {
+                    Some(c) => c,
+                    None => break,
+                };
+                insert_child(&new_element, &child, None);
+            }
+            insert_child(&furthest_block, &new_element, None);
+
+            let list_index = self
+                .index_in_active_formatting_elements(&formatting_element)
+                .expect("Failed to find a formatting element");
+            self.active_formatting_elements.remove(list_index);
+            if list_index < bookmark {
+                bookmark -= 1;
+            }
+            self.active_formatting_elements.insert(bookmark, Some(new_element.clone()));
+
+            self.stack_of_open_elements.retain(|e| !Rc::ptr_eq(e, &formatting_element));
+            let furthest_block_index = self.index_in_stack(&furthest_block).expect("Failed to find a furthest block");
+            self.stack_of_open_elements.insert(furthest_block_index + 1, new_element);
+        }
+
+        true
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+    /// 特別な要素より内側で開いている場合だけ、要素名が `tag` の要素を閉じる
+    fn close_element(&mut self, tag: &str) {
+        for i in (0..self.stack_of_open_elements.len()).rev() {
+            let node = self.stack_of_open_elements[i].clone();
+            let is_target = node
+                .borrow()
+                .get_element()
+                .is_some_and(|e| e.namespace() == Namespace::Html && e.local_name() == tag);
+            if is_target {
+                self.generate_implied_end_tags(ElementKind::from_str(tag).ok());
+                self.stack_of_open_elements.truncate(i);
+                return;
+            }
+            if is_special_node(&node) {
+                // Failed to parse. Skip the token.
+                self.parse_error("unexpected-end-tag");
+                return;
+            }
+        }
+    }
+
+    fn contain_in_stack(&self, element_kind: ElementKind) -> bool {
+
+        for i in 0..self.stack_of_open_elements.len() {
+            if self.stack_of_open_elements[i].borrow().element_kind() == Some(element_kind) {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#prescan-a-byte-stream-to-determine-its-encoding
+    /// 最初に見つかった文字コードの宣言だけを使う
+    fn detect_encoding(&mut self, attributes: &[Attribute]) {
+        if self.declared_encoding.is_some() || self.t.token_position().offset() >= 1024 {
+            return;
+        }
+        self.declared_encoding = extract_encoding(attributes);
+    }
+
+    /// <template>の中身は文書に含まれないので、資源を読み込まない
+    fn discover_resource(&mut self, tag: &str, attributes: &[Attribute]) {
+        let in_template = self
+            .stack_of_open_elements
+            .iter()
+            .any(|n| n.borrow().element_kind() == Some(ElementKind::Template));
+        if in_template {
+            return;
+        }
+
+        let value = |name: &str| attributes.iter().find(|a| a.name() == name).map(|a| a.value());
+        let resource = match tag {
+            "link" => {
+                let is_stylesheet = value("rel")
+                    .is_some_and(|rel| rel.split_ascii_whitespace().any(|r| r.eq_ignore_ascii_case("stylesheet")));
+                value("href").filter(|_| is_stylesheet).map(SubResource::Stylesheet)
+            }
+            "img" => value("src").map(SubResource::Image),
+            "script" => value("src").map(SubResource::Script),
+            _ => None,
+        };
+        if let Some(resource) = resource.filter(|r| !r.url().is_empty()) {
+            self.resources.push(resource);
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/semantics.html#set-the-frozen-base-url
+    /// href属性を持つ最初の<base>だけを使う
+    fn set_base_href(&mut self, attributes: &[Attribute]) {
+        if self.window.borrow().base_href().is_some() {
+            return;
+        }
+        if let Some(href) = attributes.iter().find(|a| a.name() == "href") {
+            self.window.borrow_mut().set_base_href(Some(href.value()));
+        }
+    }
+
+    fn current_whitespace_policy(&self) -> WhitespacePolicy {
+        for node in self.stack_of_open_elements.iter().rev() {
+            let kind = node.borrow().element_kind();
+            if let Some(&(_, policy)) = self.whitespace_overrides.iter().find(|(k, _)| Some(*k) == kind) {
+                return policy;
+            }
+        }
+        self.whitespace_policy
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let current = match self.stack_of_open_elements.last() {
+            Some(n) => insertion_target(n),
+            None => return,
+        };
+
+        // 直前のノードがテキストノードであれば、そこに文字を追加する
+        let previous = match self.foster_parenting_table(&current) {
+            Some(table) => table.borrow().previous_sibling().upgrade(),
+            None => current.borrow().last_child().upgrade(),
+        };
+        if let Some(previous) = previous {
+            if let NodeKind::Text(ref mut s) = previous.borrow_mut().kind {
+                s.push(c);
+                return;
+            }
+        }
+
+        // <textarea>などの中身は空白も含めてそのまま残す
+        if is_html_whitespace(c) && self.mode != InsertionMode::Text && self.current_whitespace_policy() == WhitespacePolicy::Drop {
+            return;
+        }
+
+        let node = Rc::new(RefCell::new(create_char_node(c)));
+        self.insert_node(&current, &node);
+    }
+}
+
+/// トークンの属性は入力の範囲を指しているので、ここで要素が持つ文字列にする
+pub(crate) fn create_element_node(tag: &str, attributes: Vec<Attribute>) -> Node {
+    let attributes = attributes.into_iter().map(Attribute::into_owned).collect();
+    Node::new(NodeKind::Element(Element::new(tag, attributes)))
+}
+
+/// 同じ要素名と属性を持つ要素を新しく作る
+fn clone_element_node(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    node.borrow().clone_node(false)
+}
+
+/// <template>の子は、テンプレートの内容に挿入する
+fn insertion_target(target: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    let content = target.borrow().template_content();
+    match content {
+        Some(content) => content,
+        None => target.clone(),
+    }
+}
+
+fn create_char_node(c: char) -> Node {
+
+    Node::new(NodeKind::Text(String::from(c)))
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inbody
+/// 開いている<p>を閉じてから挿入する要素
+fn closes_p_element(tag: &str, quirks_mode: QuirksMode) -> bool {
+    match tag {
+        "p" | "div" | "pre" | "ul" | "ol" | "li" | "dl" | "dt" | "dd" | "form" | "hr" | "xmp" | "plaintext"
+        | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "address" | "article" | "aside" | "blockquote" | "center"
+        | "details" | "dialog" | "dir" | "fieldset" | "figcaption" | "figure" | "footer" | "header" | "hgroup"
+        | "main" | "menu" | "nav" | "search" | "section" | "summary" => true,
+        // quirks modeでは<table>を<p>の中に入れる
+        "table" => quirks_mode != QuirksMode::Quirks,
+        _ => false,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/urls-and-fetching.html#extracting-character-encodings-from-meta-elements
+/// `charset` 属性か、`http-equiv="Content-Type"` の `content` 属性から文字コードを取り出す
+fn extract_encoding(attributes: &[Attribute]) -> Option<String> {
+    if let Some(charset) = attributes.iter().find(|a| a.name() == "charset") {
+        let label = charset.value().trim().to_string();
+        return if label.is_empty() { None } else { Some(normalize_encoding_label(&label)) };
+    }
+
+    let is_content_type = attributes
+        .iter()
+        .any(|a| a.name() == "http-equiv" && a.value().eq_ignore_ascii_case("content-type"));
+    if !is_content_type {
+        return None;
+    }
+    let content = attributes.iter().find(|a| a.name() == "content")?.value();
+
+    let start = content.to_ascii_lowercase().find("charset")? + "charset".len();
+    let rest = content[start..].trim_start().strip_prefix('=')?.trim_start();
+    let label = match rest.chars().next() {
+        Some(quote) if quote == '"' || quote == '\'' => rest[1..].split(quote).next()?,
+        _ => rest.split(|c: char| c == ';' || c.is_ascii_whitespace()).next()?,
+    };
+    if label.is_empty() {
+        return None;
+    }
+
+    Some(normalize_encoding_label(label))
+}
+
+/// https://encoding.spec.whatwg.org/#concept-encoding-get
+/// UTF-8の別名をまとめる。<meta>でUTF-16が宣言されていてもUTF-8として扱う
+fn normalize_encoding_label(label: &str) -> String {
+    let label = label.trim().to_ascii_lowercase();
+    match label.as_str() {
+        "utf8" | "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "x-unicode20utf8" | "utf-16"
+        | "utf-16le" | "utf-16be" => "utf-8".to_string(),
+        "x-user-defined" => "windows-1252".to_string(),
+        _ => label,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#html-integration-point
+/// この要素の中ではHTMLの要素を扱う
+fn is_html_integration_point(element: &Element) -> bool {
+    match element.namespace() {
+        Namespace::Svg => matches!(element.local_name().as_str(), "foreignObject" | "desc" | "title"),
+        Namespace::MathMl => {
+            element.local_name() == "annotation-xml"
+                && element
+                    .get_attribute("encoding")
+                    .is_some_and(|e| e.eq_ignore_ascii_case("text/html") || e.eq_ignore_ascii_case("application/xhtml+xml"))
+        }
+        Namespace::Html => false,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#mathml-text-integration-point
+fn is_mathml_text_integration_point(element: &Element) -> bool {
+    element.namespace() == Namespace::MathMl && matches!(element.local_name().as_str(), "mi" | "mo" | "mn" | "ms" | "mtext")
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign
+/// 外部コンテンツの中に書かれていても、HTMLの要素として扱う開始タグ
+fn is_breakout_tag(tag: &str, attributes: &[Attribute]) -> bool {
+    match tag {
+        "b" | "big" | "blockquote" | "body" | "br" | "center" | "code" | "dd" | "div" | "dl" | "dt" | "em"
+        | "embed" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "head" | "hr" | "i" | "img" | "li" | "listing"
+        | "menu" | "meta" | "nobr" | "ol" | "p" | "pre" | "ruby" | "s" | "small" | "span" | "strong"
+        | "strike" | "sub" | "sup" | "table" | "tt" | "u" | "ul" | "var" => true,
+        "font" => attributes
+            .iter()
+            .any(|a| matches!(a.name().as_str(), "color" | "face" | "size")),
+        _ => false,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+/// 字句解析器が小文字にしたSVGの要素名を、大文字を含む本来の名前に戻す
+fn adjust_svg_tag_name(tag: &str) -> String {
+    let name = match tag {
+        "altglyph" => "altGlyph",
+        "altglyphdef" => "altGlyphDef",
+        "altglyphitem" => "altGlyphItem",
+        "animatecolor" => "animateColor",
+        "animatemotion" => "animateMotion",
+        "animatetransform" => "animateTransform",
+        "clippath" => "clipPath",
+        "feblend" => "feBlend",
+        "fecolormatrix" => "feColorMatrix",
+        "fecomponenttransfer" => "feComponentTransfer",
+        "fecomposite" => "feComposite",
+        "feconvolvematrix" => "feConvolveMatrix",
+        "fediffuselighting" => "feDiffuseLighting",
+        "fedisplacementmap" => "feDisplacementMap",
+        "fedistantlight" => "feDistantLight",
+        "fedropshadow" => "feDropShadow",
+        "feflood" => "feFlood",
+        "fefunca" => "feFuncA",
+        "fefuncb" => "feFuncB",
+        "fefuncg" => "feFuncG",
+        "fefuncr" => "feFuncR",
+        "fegaussianblur" => "feGaussianBlur",
+        "feimage" => "feImage",
+        "femerge" => "feMerge",
+        "femergenode" => "feMergeNode",
+        "femorphology" => "feMorphology",
+        "feoffset" => "feOffset",
+        "fepointlight" => "fePointLight",
+        "fespecularlighting" => "feSpecularLighting",
+        "fespotlight" => "feSpotLight",
+        "fetile" => "feTile",
+        "feturbulence" => "feTurbulence",
+        "foreignobject" => "foreignObject",
+        "glyphref" => "glyphRef",
+        "lineargradient" => "linearGradient",
+        "radialgradient" => "radialGradient",
+        "textpath" => "textPath",
+        _ => tag,
+    };
+    name.to_string()
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-svg-attributes
+/// https://html.spec.whatwg.org/multipage/parsing.html#adjust-mathml-attributes
+/// 字句解析器が小文字にした属性名を、大文字を含む本来の名前に戻す
+fn adjust_foreign_attribute(mut attribute: Attribute, namespace: Namespace) -> Attribute {
+    let name = attribute.name();
+    let adjusted = match namespace {
+        Namespace::Svg => match name.as_str() {
+            "attributename" => "attributeName",
+            "attributetype" => "attributeType",
+            "basefrequency" => "baseFrequency",
+            "baseprofile" => "baseProfile",
+            "calcmode" => "calcMode",
+            "clippathunits" => "clipPathUnits",
+            "diffuseconstant" => "diffuseConstant",
+            "edgemode" => "edgeMode",
+            "filterunits" => "filterUnits",
+            "glyphref" => "glyphRef",
+            "gradienttransform" => "gradientTransform",
+            "gradientunits" => "gradientUnits",
+            "kernelmatrix" => "kernelMatrix",
+            "kernelunitlength" => "kernelUnitLength",
+            "keypoints" => "keyPoints",
+            "keysplines" => "keySplines",
+            "keytimes" => "keyTimes",
+            "lengthadjust" => "lengthAdjust",
+            "limitingconeangle" => "limitingConeAngle",
+            "markerheight" => "markerHeight",
+            "markerunits" => "markerUnits",
+            "markerwidth" => "markerWidth",
+            "maskcontentunits" => "maskContentUnits",
+            "maskunits" => "maskUnits",
+            "numoctaves" => "numOctaves",
+            "pathlength" => "pathLength",
+            "patterncontentunits" => "patternContentUnits",
+            "patterntransform" => "patternTransform",
+            "patternunits" => "patternUnits",
+            "pointsatx" => "pointsAtX",
+            "pointsaty" => "pointsAtY",
+            "pointsatz" => "pointsAtZ",
+            "preservealpha" => "preserveAlpha",
+            "preserveaspectratio" => "preserveAspectRatio",
+            "primitiveunits" => "primitiveUnits",
+            "refx" => "refX",
+            "refy" => "refY",
+            "repeatcount" => "repeatCount",
+            "repeatdur" => "repeatDur",
+            "requiredextensions" => "requiredExtensions",
+            "requiredfeatures" => "requiredFeatures",
+            "specularconstant" => "specularConstant",
+            "specularexponent" => "specularExponent",
+            "spreadmethod" => "spreadMethod",
+            "startoffset" => "startOffset",
+            "stddeviation" => "stdDeviation",
+            "stitchtiles" => "stitchTiles",
+            "surfacescale" => "surfaceScale",
+            "systemlanguage" => "systemLanguage",
+            "tablevalues" => "tableValues",
+            "targetx" => "targetX",
+            "targety" => "targetY",
+            "textlength" => "textLength",
+            "viewbox" => "viewBox",
+            "viewtarget" => "viewTarget",
+            "xchannelselector" => "xChannelSelector",
+            "ychannelselector" => "yChannelSelector",
+            "zoomandpan" => "zoomAndPan",
+            _ => return attribute,
+        },
+        Namespace::MathMl if name == "definitionurl" => "definitionURL",
+        _ => return attribute,
+    };
+    attribute.set_name(adjusted);
+    attribute
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+fn is_special_element(element_kind: ElementKind) -> bool {
+    !matches!(
+        element_kind,
+        ElementKind::A
+            | ElementKind::B
+            | ElementKind::I
+            | ElementKind::Em
+            | ElementKind::Strong
+            | ElementKind::Span
+            | ElementKind::Label
+            | ElementKind::Audio
+            | ElementKind::Video
+            | ElementKind::Option
+            | ElementKind::Optgroup
+            | ElementKind::Svg
+            | ElementKind::Math
+            | ElementKind::Unknown
+    )
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#special
+/// 種類を区別しない要素は、要素名で判定する
+fn is_special_node(node: &Rc<RefCell<Node>>) -> bool {
+    match node.borrow().get_element() {
+        Some(element) if element.kind() == ElementKind::Unknown => {
+            element.namespace() == Namespace::Html && SPECIAL_ELEMENT_NAMES.contains(&element.local_name().as_str())
+        }
+        Some(element) => is_special_element(element.kind()),
+        None => false,
+    }
+}
+
+fn is_element_named(node: &Rc<RefCell<Node>>, name: &str) -> bool {
+    node.borrow().get_element().is_some_and(|e| e.local_name() == name)
+}
+
+/// 特別な要素のうち、`ElementKind` で種類を区別しないもの
+const SPECIAL_ELEMENT_NAMES: [&str; 37] = [
+    "address", "applet", "area", "article", "aside", "basefont", "bgsound", "blockquote", "caption", "center",
+    "col", "colgroup", "details", "dialog", "dir", "embed", "fieldset", "figcaption", "figure", "footer",
+    "frame", "frameset", "header", "hgroup", "keygen", "listing", "main", "marquee", "menu", "nav", "noembed",
+    "noframes", "object", "param", "search", "section", "summary",
+];
+
+fn is_table_part(element_kind: Option<ElementKind>) -> bool {
+    matches!(
+        element_kind,
+        Some(ElementKind::Table) | Some(ElementKind::Tbody) | Some(ElementKind::Thead) | Some(ElementKind::Tr)
+    )
+}
+
+fn is_heading(element_kind: Option<ElementKind>) -> bool {
+    matches!(
+        element_kind,
+        Some(ElementKind::H1)
+            | Some(ElementKind::H2)
+            | Some(ElementKind::H3)
+            | Some(ElementKind::H4)
+            | Some(ElementKind::H5)
+            | Some(ElementKind::H6)
+    )
+}
+
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// 空要素は終了タグを持たず、子を持つこともない
+fn is_void_element(tag: &str) -> bool {
+    VOID_ELEMENTS.contains(&tag)
+}
+
+/// 解析中に見つかった、文書の表示に必要な資源。URLは属性の値のままで、ベースURLに対して解決していない
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubResource {
+    /// `<link rel=stylesheet href>`
+    Stylesheet(String),
+    /// `<img src>`
+    Image(String),
+    /// `<script src>`
+    Script(String),
+}
+
+impl SubResource {
+    pub fn url(&self) -> String {
+        match self {
+            SubResource::Stylesheet(url) | SubResource::Image(url) | SubResource::Script(url) => url.clone(),
+        }
+    }
+}
+
+/// 空白だけのテキストノードの扱い
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WhitespacePolicy {
+    /// 空白から始まるテキストノードを作らない
+    Drop,
+    /// インライン要素の間の空白なども含めて、すべての空白を残す
+    Preserve,
+}
+
+/// https://dom.spec.whatwg.org/#concept-document-quirks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+/// quirks modeになる公開識別子の接頭辞（仕様の一覧の一部）
+const QUIRKS_PUBLIC_ID_PREFIXES: [&str; 14] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0",
+    "-//ietf//dtd html 3",
+    "-//ietf//dtd html level",
+    "-//ietf//dtd html strict",
+    "-//ietf//dtd html//",
+    "-//microsoft//dtd internet explorer",
+    "-//netscape comm. corp.//dtd",
+    "-//w3c//dtd html 3",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental",
+    "-//w3c//dtd w3 html//",
+];
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+fn determine_quirks_mode(
+    name: &Option<String>,
+    public_id: &Option<String>,
+    system_id: &Option<String>,
+    force_quirks: bool,
+) -> QuirksMode {
+    if force_quirks || name.as_deref() != Some("html") {
+        return QuirksMode::Quirks;
+    }
+
+    let public_id = public_id.as_deref().unwrap_or("").to_ascii_lowercase();
+    let system_id = system_id.as_ref().map(|id| id.to_ascii_lowercase());
+    let is_html401_frameset_or_transitional = public_id.starts_with("-//w3c//dtd html 4.01 frameset//")
+        || public_id.starts_with("-//w3c//dtd html 4.01 transitional//");
+
+    if public_id == "-//w3o//dtd w3 html strict 3.0//en//"
+        || public_id == "-/w3c/dtd html 4.0 transitional/en"
+        || public_id == "html"
+        || system_id.as_deref() == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        || QUIRKS_PUBLIC_ID_PREFIXES.iter().any(|prefix| public_id.starts_with(prefix))
+        || (system_id.is_none() && is_html401_frameset_or_transitional)
+    {
+        return QuirksMode::Quirks;
+    }
+
+    if public_id.starts_with("-//w3c//dtd xhtml 1.0 frameset//")
+        || public_id.starts_with("-//w3c//dtd xhtml 1.0 transitional//")
+        || (system_id.is_some() && is_html401_frameset_or_transitional)
+    {
+        return QuirksMode::LimitedQuirks;
+    }
+
+    QuirksMode::NoQuirks
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    AfterBody,
+    AfterAfterBody,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::dom::node::{Element, ElementKind, Namespace, Node, NodeKind};
+    use crate::renderer::html::attribute::Attribute;
+    use crate::renderer::html::parse_error::SourcePosition;
+    use crate::renderer::html::parser::{HtmlParser, QuirksMode, SubResource, WhitespacePolicy};
+    use crate::renderer::html::token::HtmlTokenizer;
+    use crate::utils::dump_tree;
+    use alloc::rc::Rc;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    #[test]
+    fn test_empty() {
+        let html = "".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+
+        assert_eq!(expected, window.borrow().document());
+    }
+    #[test]
+    fn test_body() {
+        let html = "<html><head></head><body></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Document))), document);
+
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("html", Vec::new()))))), html);
+
+        let head = html.borrow().first_child().expect("Failed to get a first child of html");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("head", Vec::new()))))), head);
+
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+    }
+
+    #[test]
+    fn test_text() {
+        let html = "<html><head></head><body>text</body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Document))), document);
+
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("html", Vec::new()))))), html);
+
+        let body = html.borrow().first_child().expect("Failed to get a first child of html").borrow().next_sibling().expect("Failed to get a next sibling of head");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+
+        let text = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))), text);
+    }
+
+    #[test]
+    fn test_multiple_nodes() {
+        let html = "<html><head></head><body><p><a foo=bar>text</a></p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .first_child()
+            .expect("Failed to get a first child of html")
+            .borrow()
+            .next_sibling()
+            .expect("Failed to get a next sibling of head");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+
+        let p = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("p", Vec::new()))))), p);
+
+        let mut attr = Attribute::new();
+        "foo".chars().for_each(|c| attr.add_char(c, true));
+        "bar".chars().for_each(|c| attr.add_char(c, false));
+        let a = p.borrow().first_child().expect("Failed to get a first child of p");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("a", vec![attr]))))), a);
+
+        let text = a.borrow().first_child().expect("Failed to get a first child of a");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))), text);
+    }
+
+    #[test]
+    fn test_body_accessor() {
+        let html = "<html><head></head><body><p><a foo=bar>text</a></p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        // body()は<html>の子をたどって見つけた<body>と同じノードを返す
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        assert!(Rc::ptr_eq(&body, &window.borrow().body().expect("Failed to get body")));
+        assert!(Rc::ptr_eq(&head, &window.borrow().head().expect("Failed to get head")));
+
+        let p = window.borrow().body().expect("Failed to get body").borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html>\n<html><head></head><body></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let mut parser = HtmlParser::new(t);
+        let window = parser.construct_tree();
+        assert_eq!(QuirksMode::NoQuirks, parser.quirks_mode());
+        assert_eq!(QuirksMode::NoQuirks, window.borrow().quirks_mode());
+
+        // DOCTYPEは文書の最初の子になる
+        let document = window.borrow().document();
+        let doctype = document.borrow().first_child().expect("Failed to get a first child of document");
+        let kind = doctype.borrow().kind();
+        match kind {
+            NodeKind::DocumentType(d) => {
+                assert_eq!("html", d.name());
+                assert_eq!("", d.public_id());
+                assert_eq!("", d.system_id());
+            }
+            _ => panic!("Failed to get a doctype node"),
+        }
+        assert!(Rc::ptr_eq(&doctype, &window.borrow().doctype().expect("Failed to get a doctype")));
+        assert_eq!("<!DOCTYPE html><html>", &document.borrow().inner_html()[..21]);
+
+        let html = doctype.borrow().next_sibling().expect("Failed to get a next sibling of doctype");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("html", Vec::new()))))), html);
+    }
+
+    #[test]
+    fn test_quirks_mode() {
+        let cases = [
+            ("<html></html>", QuirksMode::Quirks),
+            ("<!DOCTYPE html><html></html>", QuirksMode::NoQuirks),
+            ("<!DOCTYPE foo><html></html>", QuirksMode::Quirks),
+            ("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\"><html></html>", QuirksMode::Quirks),
+            ("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\"><html></html>", QuirksMode::LimitedQuirks),
+        ];
+
+        for (html, expected) in cases {
+            let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+            let window = parser.construct_tree();
+            assert_eq!(expected, parser.quirks_mode(), "{}", html);
+            assert_eq!(expected, window.borrow().quirks_mode(), "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_self_closing_tag() {
+        let html = "<html><head></head><body><p/><h1>text</h1></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+
+        let p = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("p", Vec::new()))))), p);
+        assert!(p.borrow().first_child().is_none());
+
+        let h1 = p.borrow().next_sibling().expect("Failed to get a next sibling of p");
+        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("h1", Vec::new()))))), h1);
+    }
+
+    #[test]
+    fn test_void_elements() {
+        let html = "<html><head><link rel=stylesheet href=a.css></head><body><p>a<br>b<img src=x.png>c</p><hr><input></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html.borrow().first_child().expect("Failed to get a first child of html");
+        let link = head.borrow().first_child().expect("Failed to get a first child of head");
+        assert_eq!(Some(ElementKind::Link), link.borrow().element_kind());
+        assert!(link.borrow().next_sibling().is_none());
+
+        let body = html.borrow().last_child().upgrade().expect("Failed to get a last child of html");
+        let p = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+
+        let mut children = Vec::new();
+        let mut child = p.borrow().first_child();
+        while let Some(node) = child {
+            assert!(node.borrow().first_child().is_none());
+            child = node.borrow().next_sibling();
+            children.push(node);
+        }
+        let summary: Vec<String> = children
+            .iter()
+            .map(|node| match node.borrow().kind() {
+                NodeKind::Text(s) => s,
+                _ => format!("{:?}", node.borrow().element_kind()),
+            })
+            .collect();
+        assert_eq!(vec!["a", "Some(Br)", "b", "Some(Img)", "c"], summary);
+
+        let hr = p.borrow().next_sibling().expect("Failed to get a next sibling of p");
+        assert_eq!(Some(ElementKind::Hr), hr.borrow().element_kind());
+        let input = hr.borrow().next_sibling().expect("Failed to get a next sibling of hr");
+        assert_eq!(Some(ElementKind::Input), input.borrow().element_kind());
+        assert!(input.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_div_and_span() {
+        let html = "<html><head></head><body><div><span>a</span>b</div></span><div>c</div></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+
+        let div1 = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Div), div1.borrow().element_kind());
+
+        let span = div1.borrow().first_child().expect("Failed to get a first child of div");
+        assert_eq!(Some(ElementKind::Span), span.borrow().element_kind());
+        let a = span.borrow().first_child().expect("Failed to get a first child of span");
+        assert_eq!(NodeKind::Text("a".to_string()), a.borrow().kind());
+
+        let b = span.borrow().next_sibling().expect("Failed to get a next sibling of span");
+        assert_eq!(NodeKind::Text("b".to_string()), b.borrow().kind());
+
+        let div2 = div1.borrow().next_sibling().expect("Failed to get a next sibling of div");
+        assert_eq!(Some(ElementKind::Div), div2.borrow().element_kind());
+        assert!(div2.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_list_items() {
+        let html = "<html><head></head><body><ul><li>a<li>b<ol><li>c<li>d</ol><li>e</ul></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        let expected = r#"<body>
+  <ul>
+    <li>
+      "a"
+    <li>
+      "b"
+      <ol>
+        <li>
+          "c"
+        <li>
+          "d"
+    <li>
+      "e"
+"#;
+        assert_eq!(expected, dump_tree(&body));
+    }
+
+    #[test]
+    fn test_table() {
+        let html = "<html><head></head><body><table><thead><tr><th>h</th></tr></thead><tr><td>a<td>b</tr><tr><td><p>c</p></table><p>d</p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        // <tbody>は省略されていても作られる
+        let expected = r#"<body>
+  <table>
+    <thead>
+      <tr>
+        <th>
+          "h"
+    <tbody>
+      <tr>
+        <td>
+          "a"
+        <td>
+          "b"
+      <tr>
+        <td>
+          <p>
+            "c"
+  <p>
+    "d"
+"#;
+        assert_eq!(expected, dump_tree(&body));
+    }
+
+    #[test]
+    fn test_headings() {
+        let html = "<html><head></head><body><h3>a<h4>b</h4><h5>c</h6><p>d</p></h2><h6>e</h6></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+
+        let mut kinds = Vec::new();
+        let mut child = body.borrow().first_child();
+        while let Some(node) = child {
+            kinds.push(node.borrow().element_kind());
+            child = node.borrow().next_sibling();
+        }
+        assert_eq!(
+            vec![
+                Some(ElementKind::H3),
+                Some(ElementKind::H4),
+                Some(ElementKind::H5),
+                Some(ElementKind::P),
+                Some(ElementKind::H6),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_form_controls() {
+        let html = "<html><head></head><body><form action=/search><label>q<input type=text name=q value=saba></label><button>a<button>b</button></form></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let form = window.borrow().forms()[0].clone();
+
+        let label = form.borrow().first_child().expect("Failed to get a first child of form");
+        assert_eq!(Some(ElementKind::Label), label.borrow().element_kind());
+
+        let input = label
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of label");
+        let element = input.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(ElementKind::Input, element.kind());
+        assert_eq!(Some("text".to_string()), element.get_attribute("type"));
+        assert_eq!(Some("q".to_string()), element.get_attribute("name"));
+        assert_eq!(Some("saba".to_string()), element.get_attribute("value"));
+
+        // 入れ子になった<button>は兄弟になる
+        let button1 = label.borrow().next_sibling().expect("Failed to get a next sibling of label");
+        assert_eq!(Some(ElementKind::Button), button1.borrow().element_kind());
+        let button2 = button1.borrow().next_sibling().expect("Failed to get a next sibling of button");
+        assert_eq!(Some(ElementKind::Button), button2.borrow().element_kind());
+        assert!(button2.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_raw_text_elements() {
+        let html = "<html><head><title>a<b>&lt;</title><style>p > a { }</style><script>if (a<b) { x = \"</p>\"; }</script></head><body></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let head = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .first_child()
+            .expect("Failed to get a first child of html");
+
+        let mut texts = Vec::new();
+        let mut child = head.borrow().first_child();
+        while let Some(node) = child {
+            let text = node.borrow().first_child().expect("Failed to get a first child");
+            match text.borrow().kind() {
+                NodeKind::Text(s) => texts.push((node.borrow().element_kind(), s)),
+                _ => panic!("child should be a text node"),
+            }
+            child = node.borrow().next_sibling();
+        }
+
+        assert_eq!(
+            vec![
+                (Some(ElementKind::Title), "a<b><".to_string()),
+                (Some(ElementKind::Style), "p > a { }".to_string()),
+                (Some(ElementKind::Script), "if (a<b) { x = \"</p>\"; }".to_string()),
+            ],
+            texts
+        );
+    }
+
+    #[test]
+    fn test_textarea() {
+        let html = "<html><head></head><body><textarea name=t>\n\n  <p>a&amp;b</p></textarea><p>c</p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        let textarea = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Textarea), textarea.borrow().element_kind());
+
+        let text = textarea.borrow().first_child().expect("Failed to get a first child of textarea");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("\n  <p>a&b</p>", s),
+            _ => panic!("child should be a text node"),
+        }
+        assert!(text.borrow().next_sibling().is_none());
+
+        let p = textarea.borrow().next_sibling().expect("Failed to get a next sibling of textarea");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+    }
+
+    #[test]
+    fn test_xmp_and_plaintext() {
+        let html = "<html><head></head><body><xmp><p>&amp;</xmp><plaintext><p>a</plaintext></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+
+        let xmp = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Xmp), xmp.borrow().element_kind());
+        let text = xmp.borrow().first_child().expect("Failed to get a first child of xmp");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("<p>&amp;", s),
+            _ => panic!("child should be a text node"),
+        }
+
+        let plaintext = xmp.borrow().next_sibling().expect("Failed to get a next sibling of xmp");
+        assert_eq!(Some(ElementKind::Plaintext), plaintext.borrow().element_kind());
+        let text = plaintext.borrow().first_child().expect("Failed to get a first child of plaintext");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("<p>a</plaintext></body></html>", s),
+            _ => panic!("child should be a text node"),
+        }
+        assert!(plaintext.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_boolean_and_duplicate_attributes() {
+        let html = "<html><head></head><body><input disabled name=a name=b checked disabled=x></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        let input = body
+            .borrow()
+            .first_child()
+            .expect("Failed to get a first child of body")
+            .borrow()
+            .get_element()
+            .expect("Failed to get an element");
+
+        let attributes: Vec<(String, String)> = input
+            .attributes()
+            .iter()
+            .map(|a| (a.name(), a.value()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("disabled".to_string(), "".to_string()),
+                ("name".to_string(), "a".to_string()),
+                ("checked".to_string(), "".to_string()),
+            ],
+            attributes
+        );
+    }
+
+    #[test]
+    fn test_uppercase_tags() {
+        let html = "<HTML><HEAD></HEAD><BODY><DIV><A HREF=/x>a</A></DIV></BODY></HTML>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let links = window.borrow().links();
+
+        assert_eq!(1, links.len());
+        let a = links[0].borrow().get_element().expect("Failed to get an element");
+        assert_eq!(Some("/x".to_string()), a.get_attribute("href"));
+
+        let div = links[0].borrow().parent().expect("Failed to get a parent of a");
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
+    }
+
+    #[test]
+    fn test_implied_end_tags() {
+        let html = "<html><head></head><body><p>one<p>two<div>three</div></p><ul><li>a<p>b<li>c</ul></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        // 対応する<p>がない</p>は空の<p>になる
+        let expected = r#"<body>
+  <p>
+    "one"
+  <p>
+    "two"
+  <div>
+    "three"
+  <p>
+  <ul>
+    <li>
+      "a"
+      <p>
+        "b"
+    <li>
+      "c"
+"#;
+        assert_eq!(expected, dump_tree(&body));
+    }
+
+    #[test]
+    fn test_misnested_formatting_elements() {
+        let html = "<html><head></head><body><b>bold <i>both</b> italic</i><b>1<p>2</b>3</p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        let text = |node: &Rc<RefCell<Node>>| match node.borrow().kind() {
+            NodeKind::Text(s) => s,
+            _ => panic!("Failed to get a text node"),
+        };
+
+        // <b>bold <i>both</i></b><i>italic</i>
+        let b = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::B), b.borrow().element_kind());
+        assert_eq!("bold ", text(&b.borrow().first_child().expect("Failed to get a first child of b")));
+        let i = b.borrow().last_child().upgrade().expect("Failed to get a last child of b");
+        assert_eq!(Some(ElementKind::I), i.borrow().element_kind());
+        assert_eq!("both", text(&i.borrow().first_child().expect("Failed to get a first child of i")));
+        let i = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        assert_eq!(Some(ElementKind::I), i.borrow().element_kind());
+        assert_eq!("italic", text(&i.borrow().first_child().expect("Failed to get a first child of i")));
+
+        // <b>1</b><p><b>2</b>3</p>
+        let b = i.borrow().next_sibling().expect("Failed to get a next sibling of i");
+        assert_eq!(Some(ElementKind::B), b.borrow().element_kind());
+        assert_eq!("1", text(&b.borrow().first_child().expect("Failed to get a first child of b")));
+        let p = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+        let b = p.borrow().first_child().expect("Failed to get a first child of p");
+        assert_eq!(Some(ElementKind::B), b.borrow().element_kind());
+        assert_eq!("2", text(&b.borrow().first_child().expect("Failed to get a first child of b")));
+        let t = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        assert_eq!("3", text(&t));
+        assert!(p.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_formatting_element_after_html_end_tag() {
+        // </html>の後でも<html>と<body>は開いたままなので、書式要素は<body>の中に入る
+        let html = "x<html></html><b><div></b>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+
+        let body = window.borrow().body().expect("Failed to get body");
+        let expected = r#"<body>
+  "x"
+  <b>
+  <div>
+    <b>
+"#;
+        assert_eq!(expected, dump_tree(&body));
+
+        // <head>の中の空白以外の文字は<head>を閉じて、<body>を作る
+        let window = HtmlParser::new(HtmlTokenizer::new("hello".to_string())).construct_tree();
+        let body = window.borrow().body().expect("Failed to get body");
+        assert_eq!("<body>\n  \"hello\"\n", dump_tree(&body));
+    }
+
+    #[test]
+    fn test_foster_parenting() {
+        let html = "<html><head></head><body><table>a<tr><td>b</td></tr><div>c</div></table></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+
+        // 表の中に直接書かれた文字や要素は、表の前に移される
+        let text = body.borrow().first_child().expect("Failed to get a first child of body");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("a", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        let div = text.borrow().next_sibling().expect("Failed to get a next sibling of text");
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
+        match div.borrow().first_child().expect("Failed to get a first child of div").borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("c", s),
+            _ => panic!("Failed to get a text node"),
         }
+        let table = div.borrow().next_sibling().expect("Failed to get a next sibling of div");
+        assert_eq!(Some(ElementKind::Table), table.borrow().element_kind());
+        assert!(table.borrow().next_sibling().is_none());
 
-        self.window.clone()
+        let tbody = table.borrow().first_child().expect("Failed to get a first child of table");
+        assert_eq!(Some(ElementKind::Tbody), tbody.borrow().element_kind());
+        assert!(tbody.borrow().next_sibling().is_none());
     }
 
-    fn insert_element(&mut self, tag: &str, attributes: Vec<Attribute>) {
-        let window = self.window.borrow();
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n.clone(),
-            None => window.document(),
-        };
-        let node = Rc::new(RefCell::new(create_element_node(tag, attributes)));
+    #[test]
+    fn test_whitespace_characters() {
+        // タブ、改ページ、復帰も空白として扱い、暗黙の要素を作ったり表の外へ移したりしない
+        let html = "\t\x0C\r\n<html lang=ja><head></head><body><table>\t<tr>\r\n<td>a</td>\x0C</tr></table></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
 
-        let mut current_borrowed = current.borrow_mut();
-        match current_borrowed.first_child() {
-            Some(ref first_child) => {
-                let mut last_sibling = Rc::clone(first_child);
-                loop {
-                    let next = last_sibling.borrow_mut().next_sibling();
-                    match next {
-                        Some(ref n) => {
-                            last_sibling = Rc::clone(n);
-                        }
-                        None => {
-                            break;
-                        }
-                    }
-                }
+        let document = window.borrow().document();
+        let expected = r#"#document
+<html>
+  lang="ja"
+  <head>
+  <body>
+    <table>
+      <tbody>
+        <tr>
+          <td>
+            "a"
+"#;
+        assert_eq!(expected, dump_tree(&document));
+    }
 
-                last_sibling.borrow_mut().set_next_sibling(Some(node.clone()));
-                node.borrow_mut().set_previous_sibling(Rc::downgrade(first_child))
-            }
-            None => {
-                current_borrowed.set_first_child(Some(node.clone()));
-            }
-        }
+    #[test]
+    fn test_template() {
+        let html = "<html><head><template><p>a</p></template></head><body><template><span>b</span></template><div>c</div></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html.borrow().first_child().expect("Failed to get a first child of html");
+        let template = head.borrow().first_child().expect("Failed to get a first child of head");
+        assert_eq!(Some(ElementKind::Template), template.borrow().element_kind());
+        // <template>の中身は文書の木に含まれない
+        assert!(template.borrow().first_child().is_none());
+        let content = template.borrow().template_content().expect("Failed to get a template content");
+        assert_eq!(NodeKind::DocumentFragment, content.borrow().kind());
+        let p = content.borrow().first_child().expect("Failed to get a first child of template content");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
 
-        current_borrowed.set_last_child(Rc::downgrade(&node));
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
-        self.stack_of_open_elements.push(node);
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+        let template = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Template), template.borrow().element_kind());
+        assert!(template.borrow().first_child().is_none());
+        let content = template.borrow().template_content().expect("Failed to get a template content");
+        let span = content.borrow().first_child().expect("Failed to get a first child of template content");
+        assert_eq!(Some(ElementKind::Span), span.borrow().element_kind());
+
+        let div = template.borrow().next_sibling().expect("Failed to get a next sibling of template");
+        assert_eq!(Some(ElementKind::Div), div.borrow().element_kind());
     }
 
-    fn pop_current_node(&mut self, element_kind: ElementKind) -> bool {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n,
-            None => return false,
-        };
+    #[test]
+    fn test_noscript() {
+        let html = "<html><head><noscript><link rel=stylesheet></noscript></head><body><noscript><p>a</p></noscript></body></html>";
 
-        if current.borrow().element_kind() == Some(element_kind) {
-            self.stack_of_open_elements.pop();
-            return true;
+        // スクリプトを実行しない場合は、<noscript>の中身を要素として扱う
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let noscript = head.borrow().first_child().expect("Failed to get a first child of head");
+        assert_eq!(Some(ElementKind::Noscript), noscript.borrow().element_kind());
+        let link = noscript.borrow().first_child().expect("Failed to get a first child of noscript");
+        assert_eq!(Some(ElementKind::Link), link.borrow().element_kind());
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let noscript = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Noscript), noscript.borrow().element_kind());
+        let p = noscript.borrow().first_child().expect("Failed to get a first child of noscript");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+
+        // スクリプトを実行する場合は、<noscript>の中身をテキストとして扱う
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+        parser.set_scripting(true);
+        let window = parser.construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let noscript = head.borrow().first_child().expect("Failed to get a first child of head");
+        let text = noscript.borrow().first_child().expect("Failed to get a first child of noscript");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("<link rel=stylesheet>", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let noscript = body.borrow().first_child().expect("Failed to get a first child of body");
+        let text = noscript.borrow().first_child().expect("Failed to get a first child of noscript");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("<p>a</p>", s),
+            _ => panic!("Failed to get a text node"),
         }
+        assert!(noscript.borrow().next_sibling().is_none());
+    }
 
-        false
+    #[test]
+    fn test_foreign_content() {
+        let html = "<html><head></head><body><svg viewBox=\"0 0 10 10\"><lineargradient/><circle r=1></circle><foreignobject><p>a</p></foreignobject><title>t</title></svg><math><mi>x</mi></math><svg><p>b</p></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let window = HtmlParser::new(t).construct_tree();
+        let document = window.borrow().document();
+
+        let body = document.borrow()
+            .first_child()
+            .expect("Failed to get a first child of document")
+            .borrow()
+            .last_child()
+            .upgrade()
+            .expect("Failed to get a last child of html");
+        let element = |node: &Rc<RefCell<Node>>| node.borrow().get_element().expect("Failed to get an element");
+
+        let svg = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(ElementKind::Svg, element(&svg).kind());
+        assert_eq!(Namespace::Svg, element(&svg).namespace());
+        // 属性名も大文字を含む本来の名前に戻す
+        assert_eq!(Some("0 0 10 10".to_string()), element(&svg).get_attribute("viewBox"));
+        assert!(element(&svg).get_attribute("viewbox").is_none());
+
+        // 要素名は大文字を含む本来の名前に戻され、自己終了タグは子を持たない
+        let gradient = svg.borrow().first_child().expect("Failed to get a first child of svg");
+        assert_eq!("linearGradient", element(&gradient).local_name());
+        let circle = gradient.borrow().next_sibling().expect("Failed to get a next sibling of linearGradient");
+        assert_eq!("circle", element(&circle).local_name());
+        assert_eq!(ElementKind::Unknown, element(&circle).kind());
+
+        // <foreignObject>の中はHTMLとして扱う
+        let foreign_object = circle.borrow().next_sibling().expect("Failed to get a next sibling of circle");
+        assert_eq!("foreignObject", element(&foreign_object).local_name());
+        let p = foreign_object.borrow().first_child().expect("Failed to get a first child of foreignObject");
+        assert_eq!(ElementKind::P, element(&p).kind());
+        assert_eq!(Namespace::Html, element(&p).namespace());
+
+        // SVGの<title>は文書のタイトルにならない
+        let title = foreign_object.borrow().next_sibling().expect("Failed to get a next sibling of foreignObject");
+        assert_eq!(ElementKind::Unknown, element(&title).kind());
+        assert_eq!(Namespace::Svg, element(&title).namespace());
+        assert_eq!("", window.borrow().title());
+
+        let math = svg.borrow().next_sibling().expect("Failed to get a next sibling of svg");
+        assert_eq!(ElementKind::Math, element(&math).kind());
+        let mi = math.borrow().first_child().expect("Failed to get a first child of math");
+        assert_eq!(Namespace::MathMl, element(&mi).namespace());
+
+        // <p>はSVGを抜けてHTMLの要素として扱う
+        let svg = math.borrow().next_sibling().expect("Failed to get a next sibling of math");
+        assert_eq!(ElementKind::Svg, element(&svg).kind());
+        assert!(svg.borrow().first_child().is_none());
+        let p = svg.borrow().next_sibling().expect("Failed to get a next sibling of svg");
+        assert_eq!(ElementKind::P, element(&p).kind());
     }
 
-    fn pop_until(&mut self, element_kind: ElementKind) {
-        assert!(
-            self.contain_in_stack(element_kind),
-            "stack doesn't have an element {:?}",
-            element_kind,
-        );
+    #[test]
+    fn test_parse_errors() {
+        let html = "<html><head></head><body></span><table>x</table></body></html>".to_string();
+        let t = HtmlTokenizer::new(html);
+        let mut parser = HtmlParser::new(t);
+        parser.construct_tree();
 
-        loop {
-            let current = match self.stack_of_open_elements.pop() {
-                Some(n) => n,
-                None => return,
-            };
+        let errors = parser.errors();
+        let codes: Vec<String> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(vec!["unexpected-end-tag".to_string(), "unexpected-token-in-table".to_string()], codes);
+        // エラーは処理中のトークンの直後の位置に記録される
+        assert_eq!(32, errors[0].position().offset());
+        assert_eq!(1, errors[0].position().line());
+    }
 
-            if current.borrow().element_kind() == Some(element_kind) {
-                return;
+    #[test]
+    fn test_streaming() {
+        fn dump(node: &Rc<RefCell<Node>>) -> String {
+            let mut s = match node.borrow().kind() {
+                NodeKind::Element(e) => format!("<{}>", e.local_name()),
+                NodeKind::Text(t) => t,
+                _ => String::new(),
+            };
+            let mut child = node.borrow().first_child();
+            while let Some(c) = child {
+                s.push_str(&dump(&c));
+                child = c.borrow().next_sibling();
             }
+            s
         }
-    }
 
-    fn contain_in_stack(&self, element_kind: ElementKind) -> bool {
-        for i in 0..self.stack_of_open_elements.len() {
-            if self.stack_of_open_elements[i].borrow().element_kind() == Some(element_kind) {
-                return true;
+        let html = "<!DOCTYPE html><html><head><title>t</title></head><body><p class=a>one &amp; two<table><tr><td>x</td></tr></table></body></html>";
+        let window = HtmlParser::new(HtmlTokenizer::new(html.to_string())).construct_tree();
+        let expected = dump(&window.borrow().document());
+
+        let chars: Vec<char> = html.chars().collect();
+        for chunk_size in [1, 3, 7] {
+            let mut parser = HtmlParser::new(HtmlTokenizer::new_streaming());
+            for chunk in chars.chunks(chunk_size) {
+                parser.feed(&chunk.iter().collect::<String>());
             }
+            let window = parser.finish();
+            assert_eq!(expected, dump(&window.borrow().document()));
+            assert_eq!(QuirksMode::NoQuirks, parser.quirks_mode());
         }
+    }
 
-        false
+    #[test]
+    fn test_script_hook() {
+        let html = "<html><head><script>a()</script></head><body><a href=x>link</a><script>b()</script></body></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+
+        let scripts = Rc::new(RefCell::new(Vec::new()));
+        let recorded = scripts.clone();
+        parser.set_script_hook(move |text, window| {
+            // スクリプトより前の要素だけが構築されている
+            recorded.borrow_mut().push((text.to_string(), window.borrow().links().len()));
+        });
+        parser.construct_tree();
+
+        assert_eq!(vec![("a()".to_string(), 0), ("b()".to_string(), 1)], *scripts.borrow());
     }
 
-    fn insert_char(&mut self, c: char) {
-        let current = match self.stack_of_open_elements.last() {
-            Some(n) => n.clone(),
-            None => return,
-        };
+    #[test]
+    fn test_document_write() {
+        let html = "<html><head></head><body><script>w()</script><span>after</span></body></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.set_script_hook(|_, window| {
+            window.borrow_mut().document_write("<p>written</p>");
+        });
+        let window = parser.construct_tree();
 
-        if let NodeKind::Text(ref mut s) = current.borrow_mut().kind {
-            s.push(c);
-            return;
+        // 書き込まれた内容は<script>の直後に解析される
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let script = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Script), script.borrow().element_kind());
+        let p = script.borrow().next_sibling().expect("Failed to get a next sibling of script");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+        let text = p.borrow().first_child().expect("Failed to get a first child of p");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("written", s),
+            _ => panic!("Failed to get a text node"),
         }
+        let span = p.borrow().next_sibling().expect("Failed to get a next sibling of p");
+        assert_eq!(Some(ElementKind::Span), span.borrow().element_kind());
+    }
 
-        if c == ' ' || c == '\n' {
-            return;
-        }
+    #[test]
+    fn test_source_location() {
+        let html = "<html><head></head><body>\n  <p id=x>ab</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        assert_eq!(Some(SourcePosition::new(0, 1, 1)), html_element.borrow().source_location());
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        assert_eq!(Some(SourcePosition::new(19, 1, 20)), body.borrow().source_location());
+        let p = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(SourcePosition::new(28, 2, 3)), p.borrow().source_location());
+        let text = p.borrow().first_child().expect("Failed to get a first child of p");
+        assert_eq!(Some(SourcePosition::new(36, 2, 11)), text.borrow().source_location());
+    }
 
-        let node = Rc::new(RefCell::new(create_char_node(c)));
+    #[test]
+    fn test_whitespace_policy() {
+        let html = "<html><head></head><body><b>a</b> <i>b</i><p> </p></body></html>";
 
-        let mut current_borrowed = current.borrow_mut();
-        match current_borrowed.first_child() {
-            Some(first_child) => {
-                first_child.borrow_mut().set_next_sibling(Some(node.clone()));
-                node.borrow_mut().set_previous_sibling(Rc::downgrade(&first_child));
-            }
-            None => {
-                current_borrowed.set_first_child(Some(node.clone()));
-            }
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+        parser.set_whitespace_policy(WhitespacePolicy::Preserve);
+        let window = parser.construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let b = body.borrow().first_child().expect("Failed to get a first child of body");
+        let space = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        match space.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!(" ", s),
+            _ => panic!("Failed to get a text node"),
         }
+        let i = space.borrow().next_sibling().expect("Failed to get a next sibling of text");
+        let p = i.borrow().next_sibling().expect("Failed to get a next sibling of i");
+        assert!(p.borrow().first_child().is_some());
 
-        current_borrowed.set_last_child(Rc::downgrade(&node));
-        node.borrow_mut().set_parent(Rc::downgrade(&current));
-        self.stack_of_open_elements.push(node);
+        // 要素ごとの設定は文書全体の設定より優先する
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.to_string()));
+        parser.set_whitespace_override(ElementKind::P, WhitespacePolicy::Preserve);
+        let window = parser.construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let b = body.borrow().first_child().expect("Failed to get a first child of body");
+        let i = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        assert_eq!(Some(ElementKind::I), i.borrow().element_kind());
+        let p = i.borrow().next_sibling().expect("Failed to get a next sibling of i");
+        let space = p.borrow().first_child().expect("Failed to get a first child of p");
+        match space.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!(" ", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        assert!(space.borrow().next_sibling().is_none());
     }
-}
 
-fn create_element_node(tag: &str, attributes: Vec<Attribute>) -> Node {
-    Node::new(NodeKind::Element(Element::new(tag, attributes)))
-}
+    #[test]
+    fn test_encoding_change() {
+        let html = "<html><head><meta charset=\"Shift_JIS\"></head><body></body></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html.clone()));
+        parser.construct_tree();
+        assert_eq!(Some("shift_jis".to_string()), parser.encoding_change());
 
-fn create_char_node(c: char) -> Node {
-    Node::new(NodeKind::Text(String::from(c)))
-}
+        // 宣言された文字コードで復号し直した後は、もう一度解析する必要はない
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.set_encoding("shift_jis");
+        parser.construct_tree();
+        assert_eq!(None, parser.encoding_change());
 
+        let html = "<html><head><meta http-equiv=Content-Type content=\"text/html; charset='euc-jp'\"></head></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.construct_tree();
+        assert_eq!(Some("euc-jp".to_string()), parser.encoding_change());
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum InsertionMode {
-    Initial,
-    BeforeHtml,
-    BeforeHead,
-    InHead,
-    AfterHead,
-    InBody,
-    Text,
-    AfterBody,
-    AfterAfterBody,
-}
+        let html = "<html><head><meta charset=utf8></head></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.construct_tree();
+        assert_eq!(None, parser.encoding_change());
 
-#[cfg(test)]
-mod tests {
-    use crate::renderer::dom::node::{Element, Node, NodeKind};
-    use crate::renderer::html::attribute::Attribute;
-    use crate::renderer::html::parser::HtmlParser;
-    use crate::renderer::html::token::HtmlTokenizer;
-    use alloc::rc::Rc;
-    use alloc::string::ToString;
-    use alloc::vec;
-    use alloc::vec::Vec;
-    use core::cell::RefCell;
+        // 先頭1024バイトより後ろの宣言は無視する
+        let html = format!("<html><head><title>{}</title><meta charset=euc-jp></head></html>", "a".repeat(1024));
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.construct_tree();
+        assert_eq!(None, parser.encoding_change());
+    }
 
     #[test]
-    fn test_empty() {
-        let html = "".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
-        let expected = Rc::new(RefCell::new(Node::new(NodeKind::Document)));
+    fn test_resources() {
+        let html = "<html><head><link rel=\"Alternate StyleSheet\" href=a.css><link rel=icon href=favicon.ico><script src=/app.js></script></head><body><img src=b.png><img><template><img src=c.png></template></body></html>".to_string();
+        let mut parser = HtmlParser::new(HtmlTokenizer::new(html));
+        parser.construct_tree();
 
-        assert_eq!(expected, window.borrow().document());
+        assert_eq!(
+            vec![
+                SubResource::Stylesheet("a.css".to_string()),
+                SubResource::Script("/app.js".to_string()),
+                SubResource::Image("b.png".to_string()),
+            ],
+            parser.resources()
+        );
     }
+
     #[test]
-    fn test_body() {
-        let html = "<html><head></head><body></body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
+    fn test_pre() {
+        let html = "<html><head></head><body><p>a<pre>\n  line 1\n\n  <b>line</b> 2\n</pre></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
         let document = window.borrow().document();
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Document))), document);
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let p = body.borrow().first_child().expect("Failed to get a first child of body");
+        // <pre>は開いている<p>を閉じる
+        let pre = p.borrow().next_sibling().expect("Failed to get a next sibling of p");
+        assert_eq!(Some(ElementKind::Pre), pre.borrow().element_kind());
 
-        let html = document.borrow().first_child().expect("Failed to get a first child of document");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("html", Vec::new()))))), html);
+        // 最初の改行だけを取り除き、それ以外の空白は残す
+        let text = pre.borrow().first_child().expect("Failed to get a first child of pre");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("  line 1\n\n  ", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        let b = text.borrow().next_sibling().expect("Failed to get a next sibling of text");
+        let text = b.borrow().next_sibling().expect("Failed to get a next sibling of b");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!(" 2\n", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        assert!(text.borrow().next_sibling().is_none());
+    }
 
-        let head = html.borrow().first_child().expect("Failed to get a first child of html");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("head", Vec::new()))))), head);
+    #[test]
+    fn test_media() {
+        let html = "<html><head></head><body><video controls><source src=a.webm type=video/webm><source src=a.mp4><track kind=captions src=a.vtt>fallback</video><p>after</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let video = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Video), video.borrow().element_kind());
+
+        // <source>と<track>は子を持たず、兄弟として並ぶ
+        let source = video.borrow().first_child().expect("Failed to get a first child of video");
+        assert_eq!(Some(ElementKind::Source), source.borrow().element_kind());
+        assert!(source.borrow().first_child().is_none());
+        let element = source.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(Some("a.webm".to_string()), element.get_attribute("src"));
+        let source = source.borrow().next_sibling().expect("Failed to get a next sibling of source");
+        assert_eq!(Some(ElementKind::Source), source.borrow().element_kind());
+        let track = source.borrow().next_sibling().expect("Failed to get a next sibling of source");
+        assert_eq!(Some(ElementKind::Track), track.borrow().element_kind());
+        let text = track.borrow().next_sibling().expect("Failed to get a next sibling of track");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("fallback", s),
+            _ => panic!("Failed to get a text node"),
+        }
 
+        let p = video.borrow().next_sibling().expect("Failed to get a next sibling of video");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+    }
+
+    #[test]
+    fn test_iframe() {
+        let html = "<html><head></head><body><iframe src=\"/frame.html\" width=300><p>no iframes</p></iframe><p>after</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
         let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+        let iframe = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Iframe), iframe.borrow().element_kind());
+        let element = iframe.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(Some("/frame.html".to_string()), element.get_attribute("src"));
+
+        let text = iframe.borrow().first_child().expect("Failed to get a first child of iframe");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("<p>no iframes</p>", s),
+            _ => panic!("Failed to get a text node"),
+        }
+
+        let p = iframe.borrow().next_sibling().expect("Failed to get a next sibling of iframe");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
     }
 
     #[test]
-    fn test_text() {
-        let html = "<html><head></head><body>text</body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
+    fn test_select() {
+        let html = "<html><head></head><body><select name=s><option value=1>one<option selected>two<optgroup label=g><option>three</optgroup><p>ignored</p><option>four</select><p>after</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
         let document = window.borrow().document();
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Document))), document);
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let select = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Select), select.borrow().element_kind());
 
-        let html = document.borrow().first_child().expect("Failed to get a first child of document");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("html", Vec::new()))))), html);
+        // 次の<option>や<optgroup>が始まると、開いている<option>は閉じられる
+        let one = select.borrow().first_child().expect("Failed to get a first child of select");
+        assert_eq!(Some(ElementKind::Option), one.borrow().element_kind());
+        let two = one.borrow().next_sibling().expect("Failed to get a next sibling of option");
+        assert_eq!(Some(ElementKind::Option), two.borrow().element_kind());
+        let optgroup = two.borrow().next_sibling().expect("Failed to get a next sibling of option");
+        assert_eq!(Some(ElementKind::Optgroup), optgroup.borrow().element_kind());
+        let three = optgroup.borrow().first_child().expect("Failed to get a first child of optgroup");
+        assert_eq!(Some(ElementKind::Option), three.borrow().element_kind());
 
-        let body = html.borrow().first_child().expect("Failed to get a first child of html").borrow().next_sibling().expect("Failed to get a next sibling of head");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+        // <select>の中の<p>は無視するが、中身の文字は残す
+        let text = optgroup.borrow().next_sibling().expect("Failed to get a next sibling of optgroup");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("ignored", s),
+            _ => panic!("Failed to get a text node"),
+        }
+        let four = text.borrow().next_sibling().expect("Failed to get a next sibling of text");
+        assert_eq!(Some(ElementKind::Option), four.borrow().element_kind());
 
-        let text = body.borrow().first_child().expect("Failed to get a first child of body");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))), text);
+        let p = select.borrow().next_sibling().expect("Failed to get a next sibling of select");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
     }
 
     #[test]
-    fn test_multiple_nodes() {
-        let html = "<html><head></head><body><p><a foo=bar>text</a></p></body></html>".to_string();
-        let t = HtmlTokenizer::new(html);
-        let window = HtmlParser::new(t).construct_tree();
+    fn test_select_in_table() {
+        let html = "<html><head></head><body><table><tr><td><select><option>a</td><td>b</td></tr></table></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
         let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let table = body.borrow().first_child().expect("Failed to get a first child of body");
+        let tbody = table.borrow().first_child().expect("Failed to get a first child of table");
+        let tr = tbody.borrow().first_child().expect("Failed to get a first child of tbody");
+        let td = tr.borrow().first_child().expect("Failed to get a first child of tr");
+        let select = td.borrow().first_child().expect("Failed to get a first child of td");
+        assert_eq!(Some(ElementKind::Select), select.borrow().element_kind());
 
-        let body = document.borrow()
-            .first_child()
-            .expect("Failed to get a first child of document")
-            .borrow()
-            .first_child()
-            .expect("Failed to get a first child of html")
-            .borrow()
-            .next_sibling()
-            .expect("Failed to get a next sibling of head");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("body", Vec::new()))))), body);
+        // </td>は<select>を閉じてから処理される
+        let td = td.borrow().next_sibling().expect("Failed to get a next sibling of td");
+        assert_eq!(Some(ElementKind::Td), td.borrow().element_kind());
+        assert!(td.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_definition_list() {
+        let html = "<html><head></head><body><dl><dt>term<dd>one<dd><p>two<dt>next</dl><p>after</p></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
+        let dl = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!(Some(ElementKind::Dl), dl.borrow().element_kind());
+
+        // 次の<dt>や<dd>が始まると、開いている<dt>や<dd>は閉じられる
+        let mut kinds = Vec::new();
+        let mut child = dl.borrow().first_child();
+        while let Some(node) = child {
+            kinds.push(node.borrow().element_kind());
+            child = node.borrow().next_sibling();
+        }
+        assert_eq!(
+            vec![Some(ElementKind::Dt), Some(ElementKind::Dd), Some(ElementKind::Dd), Some(ElementKind::Dt)],
+            kinds
+        );
 
+        let p = dl.borrow().next_sibling().expect("Failed to get a next sibling of dl");
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
+        assert!(p.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_generic_elements() {
+        let html = "<html><head></head><body><p>intro<section><h1>title</h1><article><foo>text</foo></article></section><nav>links</nav></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+        let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+        let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+        let body = head.borrow().next_sibling().expect("Failed to get a next sibling of head");
         let p = body.borrow().first_child().expect("Failed to get a first child of body");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("p", Vec::new()))))), p);
+        assert_eq!(Some(ElementKind::P), p.borrow().element_kind());
 
-        let mut attr = Attribute::new();
-        "foo".chars().for_each(|c| attr.add_char(c, true));
-        "bar".chars().for_each(|c| attr.add_char(c, false));
-        let a = p.borrow().first_child().expect("Failed to get a first child of p");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new("a", vec![attr]))))), a);
+        // <section>は開いている<p>を閉じる
+        let section = p.borrow().next_sibling().expect("Failed to get a next sibling of p");
+        let element = section.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(ElementKind::Unknown, element.kind());
+        assert_eq!("section", element.local_name());
 
-        let text = a.borrow().first_child().expect("Failed to get a first child of a");
-        assert_eq!(Rc::new(RefCell::new(Node::new(NodeKind::Text("text".to_string())))), text);
+        let h1 = section.borrow().first_child().expect("Failed to get a first child of section");
+        assert_eq!(Some(ElementKind::H1), h1.borrow().element_kind());
+        let article = h1.borrow().next_sibling().expect("Failed to get a next sibling of h1");
+        let foo = article.borrow().first_child().expect("Failed to get a first child of article");
+        let element = foo.borrow().get_element().expect("Failed to get an element");
+        assert_eq!("foo", element.local_name());
+        let text = foo.borrow().first_child().expect("Failed to get a first child of foo");
+        match text.borrow().kind() {
+            NodeKind::Text(s) => assert_eq!("text", s),
+            _ => panic!("Failed to get a text node"),
+        }
+
+        // 終了タグで閉じられるので、<nav>は<section>の兄弟になる
+        let nav = section.borrow().next_sibling().expect("Failed to get a next sibling of section");
+        let element = nav.borrow().get_element().expect("Failed to get an element");
+        assert_eq!("nav", element.local_name());
+        assert!(nav.borrow().next_sibling().is_none());
+    }
+
+    #[test]
+    fn test_escaped_script() {
+        let cases = [
+            ("<!-- if (a < b) { x = \"</div>\"; } -->", "<!-- if (a < b) { x = \"</div>\"; } -->"),
+            // `<!--` の後の `<script>` に対応する</script>では、スクリプトを終わらせない
+            ("<!--<script>x()</script>-->", "<!--<script>x()</script>-->"),
+            // エスケープされていても、`<script>` の外側の</script>はスクリプトを終わらせる
+            ("<!-- a </script><p>", "<!-- a "),
+        ];
+
+        for (script, expected) in cases {
+            let html = format!("<html><head><script>{}</script></head><body></body></html>", script);
+            let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+            let document = window.borrow().document();
+            let html_element = document.borrow().first_child().expect("Failed to get a first child of document");
+            let head = html_element.borrow().first_child().expect("Failed to get a first child of html");
+            let script = head.borrow().first_child().expect("Failed to get a first child of head");
+            assert_eq!(Some(ElementKind::Script), script.borrow().element_kind());
+            let text = script.borrow().first_child().expect("Failed to get a first child of script");
+            let kind = text.borrow().kind();
+            match kind {
+                NodeKind::Text(s) => assert_eq!(expected, s),
+                _ => panic!("Failed to get a text node"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        let context = |name: &str| Rc::new(RefCell::new(Node::new(NodeKind::Element(Element::new(name, Vec::new())))));
+
+        // 文脈の要素に合わせた挿入モードで解析するので、<tr>の中の<td>がそのまま残る
+        let fragment = HtmlParser::parse_fragment(&context("tr"), "<td>a</td><td>b".to_string());
+        assert_eq!(NodeKind::DocumentFragment, fragment.borrow().kind());
+        let nodes: Vec<Rc<RefCell<Node>>> = fragment.borrow().child_nodes().collect();
+        assert_eq!(2, nodes.len());
+        assert!(nodes.iter().all(|n| n.borrow().element_kind() == Some(ElementKind::Td)));
+
+        let fragment = HtmlParser::parse_fragment(&context("div"), "a<p>b</p>".to_string());
+        let nodes: Vec<Rc<RefCell<Node>>> = fragment.borrow().child_nodes().collect();
+        assert_eq!(2, nodes.len());
+        assert_eq!(Some(ElementKind::P), nodes[1].borrow().element_kind());
+
+        // <title>の中身はテキストとして扱う
+        let fragment = HtmlParser::parse_fragment(&context("title"), "<b>x</b>".to_string());
+        let nodes: Vec<Rc<RefCell<Node>>> = fragment.borrow().child_nodes().collect();
+        assert_eq!(1, nodes.len());
+        let kind = nodes[0].borrow().kind();
+        match kind {
+            NodeKind::Text(s) => assert_eq!("<b>x</b>", s),
+            _ => panic!("Failed to get a text node"),
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let html = "<!--a--><html><head><!--h--></head><body><p>x<!--c-->y</p></body><!--b--></html><!--z-->".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let document = window.borrow().document();
+
+        let kinds: Vec<NodeKind> = document.borrow().child_nodes().map(|n| n.borrow().kind()).collect();
+        assert_eq!(
+            vec![
+                NodeKind::Comment("a".to_string()),
+                NodeKind::Element(Element::new("html", Vec::new())),
+                NodeKind::Comment("z".to_string()),
+            ],
+            kinds
+        );
+        let html_element = document.borrow().children().next().expect("Failed to get an html element");
+        let last = html_element.borrow().last_child().upgrade().expect("Failed to get a last child");
+        assert_eq!(Some("b".to_string()), last.borrow().text_content());
+
+        // コメントの前後のテキストは別のテキストノードになり、直列化すると元に戻る
+        assert_eq!(
+            "<head><!--h--></head><body><p>x<!--c-->y</p></body><!--b-->",
+            html_element.borrow().inner_html()
+        );
+        assert_eq!("<!--a--><html>", &document.borrow().inner_html()[..14]);
     }
 }
@@ -0,0 +1,122 @@
+use crate::renderer::dom::node::{Namespace, Node, NodeKind};
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::cell::RefCell;
+
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+/// 終了タグを書かない要素
+const VOID_ELEMENTS: [&str; 17] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input", "keygen", "link",
+    "meta", "param", "source", "track",
+];
+
+/// 中のテキストを文字参照に置き換えずにそのまま書く要素
+const RAW_TEXT_ELEMENTS: [&str; 7] = ["style", "script", "xmp", "iframe", "noembed", "noframes", "plaintext"];
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments
+/// `node` の子をHTMLの文字列にする。<template>では中身を使う
+pub fn serialize_children(node: &Node) -> String {
+    let mut html = String::new();
+    let parent_name = match node.get_element() {
+        Some(element) if element.namespace() == Namespace::Html => element.local_name(),
+        _ => String::new(),
+    };
+
+    let mut child = match node.template_content() {
+        Some(content) => content.borrow().first_child(),
+        None => node.first_child(),
+    };
+    while let Some(c) = child {
+        write_child(&c, &parent_name, &mut html);
+        child = c.borrow().next_sibling();
+    }
+    html
+}
+
+/// https://w3c.github.io/DOM-Parsing/#dom-element-outerhtml
+/// `node` 自身を含めてHTMLの文字列にする。文書と文書片では子だけを書く
+pub fn serialize_node(node: &Node) -> String {
+    let mut html = String::new();
+    match node.kind {
+        NodeKind::Text(ref s) => html.push_str(&escape(s, false)),
+        NodeKind::Element(_) => {
+            write_start_tag(node, &mut html);
+            if !is_void(node) {
+                html.push_str(&serialize_children(node));
+                write_end_tag(node, &mut html);
+            }
+        }
+        NodeKind::DocumentType(ref doctype) => {
+            html.push_str("<!DOCTYPE ");
+            html.push_str(&doctype.name());
+            html.push('>');
+        }
+        NodeKind::Comment(ref s) => {
+            html.push_str("<!--");
+            html.push_str(s);
+            html.push_str("-->");
+        }
+        NodeKind::Document | NodeKind::DocumentFragment | NodeKind::ShadowRoot(_) => {
+            html.push_str(&serialize_children(node))
+        }
+    }
+    html
+}
+
+fn write_child(node: &Rc<RefCell<Node>>, parent_name: &str, html: &mut String) {
+    let node = node.borrow();
+    match node.kind {
+        NodeKind::Text(ref s) if RAW_TEXT_ELEMENTS.contains(&parent_name) => html.push_str(s),
+        _ => html.push_str(&serialize_node(&node)),
+    }
+}
+
+fn write_start_tag(node: &Node, html: &mut String) {
+    let element = match node.get_element() {
+        Some(element) => element,
+        None => return,
+    };
+
+    html.push('<');
+    html.push_str(&element.local_name());
+    for attribute in element.attributes() {
+        html.push(' ');
+        html.push_str(&attribute.name());
+        html.push_str("=\"");
+        html.push_str(&escape(&attribute.value(), true));
+        html.push('"');
+    }
+    html.push('>');
+}
+
+fn write_end_tag(node: &Node, html: &mut String) {
+    if let Some(element) = node.get_element() {
+        html.push_str("</");
+        html.push_str(&element.local_name());
+        html.push('>');
+    }
+}
+
+fn is_void(node: &Node) -> bool {
+    match node.get_element() {
+        Some(element) => element.namespace() == Namespace::Html && VOID_ELEMENTS.contains(&element.local_name().as_str()),
+        None => false,
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#escapingString
+/// 属性値では `"` を、テキストでは `<` と `>` を置き換える
+fn escape(s: &str, attribute_mode: bool) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{a0}' => escaped.push_str("&nbsp;"),
+            '"' if attribute_mode => escaped.push_str("&quot;"),
+            '<' if !attribute_mode => escaped.push_str("&lt;"),
+            '>' if !attribute_mode => escaped.push_str("&gt;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
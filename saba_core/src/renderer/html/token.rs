@@ -1,15 +1,60 @@
 use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::character_reference::{
+    match_named_character_reference, match_numeric_character_reference,
+};
+use crate::renderer::html::parse_error::{ParseError, SourcePosition};
+use crate::renderer::html::token_string::TokenString;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::cell::Cell;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HtmlTokenizer {
     state: State,
+    /// 次に読む文字のバイト位置
     pos: usize,
     reconsume: bool,
     latest_token: Option<HtmlToken>,
-    input: Vec<char>,
+    /// 読んでいる入力。`input_start` より前は読み終えた入力の残りで、使わない。
+    /// トークンのタグ名や属性は、この入力の範囲を指す
+    input: Rc<str>,
+    input_start: usize,
+    /// `input_start` の、入力全体の中での位置
+    base: SourcePosition,
+    /// `input` の後に続く入力。それぞれ、文字列とそのうち読み始めるバイト位置の組。
+    /// `input` を読み終えたときに、読みかけのトークンがなければ複製せずにそのまま読み始める
+    chunks: VecDeque<(Rc<str>, usize)>,
     buf: String,
+    /// 文字参照を展開した結果のうち、まだ出力していない文字
+    pending_chars: Vec<char>,
+    /// 構文解析器によって切り替えられた、タグを解釈しない状態
+    text_state: State,
+    /// 最後に出力した開始タグの名前。テキストを終わらせる終了タグを判定するのに使う
+    last_start_tag: TokenString,
+    errors: Vec<ParseError>,
+    /// falseの間は、入力の終わりに達しても次の入力を待つ
+    finished: bool,
+    /// 入力が足りずにトークンを読み切れなかった
+    underflow: bool,
+    /// 最後に出力したトークンの開始位置
+    token_start: usize,
+    /// これまでに求めた中で最も後ろの位置。次の位置はここからの差分だけを数えて求める
+    last_position: Cell<SourcePosition>,
+}
+
+/// 入力が足りなかったときに、トークンを読み始める前の状態に戻すための記録
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    state: State,
+    pos: usize,
+    reconsume: bool,
+    latest_token: Option<HtmlToken>,
+    buf: String,
+    text_state: State,
+    last_start_tag: TokenString,
+    errors_len: usize,
 }
 
 impl HtmlTokenizer {
@@ -19,9 +64,197 @@ impl HtmlTokenizer {
             pos: 0,
             reconsume: false,
             latest_token: None,
-            input: html.chars().collect(),
+            input: Rc::from(html),
+            input_start: 0,
+            base: SourcePosition::new(0, 1, 1),
+            chunks: VecDeque::new(),
             buf: String::new(),
+            pending_chars: Vec::new(),
+            text_state: State::Data,
+            last_start_tag: TokenString::new(),
+            errors: Vec::new(),
+            finished: true,
+            underflow: false,
+            token_start: 0,
+            last_position: Cell::new(SourcePosition::new(0, 1, 1)),
+        }
+    }
+
+    /// `feed()` で少しずつ入力を与える字句解析器を作る。入力の終わりは `finish()` で伝える。
+    /// 入力が足りない間、`next()` はNoneを返す
+    pub fn new_streaming() -> Self {
+        let mut tokenizer = Self::new(String::new());
+        tokenizer.finished = false;
+        tokenizer
+    }
+
+    /// 受信したHTMLの一部を入力の末尾に追加する。それまでの入力は複製しない
+    pub fn feed(&mut self, chunk: &str) {
+        assert!(!self.finished, "input has already finished");
+        self.chunks.push_back((Rc::from(chunk), 0));
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#insertion-point
+    /// 次に読む位置に `text` を挿入する。`document.write()` の内容を続けて解析するのに使う。
+    /// 最後に出力したトークンの残りと `text` だけを複製し、まだ読んでいない入力はその後に続ける
+    pub fn insert_input(&mut self, text: &str) {
+        let pos = self.pos.min(self.input.len());
+        let keep = self.token_start.min(pos);
+        if pos < self.input.len() {
+            self.chunks.push_front((self.input.clone(), pos));
+        }
+
+        let mut input = String::from(&self.input[keep..pos]);
+        input.push_str(text);
+        self.switch_input(Rc::from(input), 0, keep);
+    }
+
+    /// これ以上入力がないことを伝える。読みかけのトークンは入力の終わりとして処理される
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// 続きの入力がまだあり得る間はtrue
+    fn more_input(&self) -> bool {
+        !self.finished || !self.chunks.is_empty()
+    }
+
+    /// `input` を読み終えたか読みかけのトークンの途中で足りなくなったときに、次の入力に切り替える。
+    /// 読みかけの部分があればそれだけを複製して次の入力の前につなげ、なければ次の入力をそのまま使う
+    fn pull_input(&mut self) {
+        let (next, start) = match self.chunks.pop_front() {
+            Some(chunk) => chunk,
+            None => return,
+        };
+        let keep = if self.reconsume { self.last_consumed() } else { self.pos.min(self.input.len()) };
+        if keep == self.input.len() {
+            self.switch_input(next, start, keep);
+            return;
+        }
+
+        let mut input = String::from(&self.input[keep..]);
+        input.push_str(&next[start..]);
+        self.switch_input(Rc::from(input), 0, keep);
+    }
+
+    /// `input` の `keep` 以降が `input_start` から始まる新しい入力に読む位置を移す
+    fn switch_input(&mut self, input: Rc<str>, input_start: usize, keep: usize) {
+        let base = self.position_of(keep);
+        self.pos = self.pos - keep + input_start;
+        self.token_start = self.token_start.max(keep) - keep + input_start;
+        self.input = input;
+        self.input_start = input_start;
+        self.base = base;
+        self.last_position.set(base);
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            state: self.state,
+            pos: self.pos,
+            reconsume: self.reconsume,
+            latest_token: self.latest_token.clone(),
+            buf: self.buf.clone(),
+            text_state: self.text_state,
+            last_start_tag: self.last_start_tag.clone(),
+            errors_len: self.errors.len(),
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.state = checkpoint.state;
+        self.pos = checkpoint.pos;
+        self.reconsume = checkpoint.reconsume;
+        self.latest_token = checkpoint.latest_token;
+        self.buf = checkpoint.buf;
+        self.text_state = checkpoint.text_state;
+        self.last_start_tag = checkpoint.last_start_tag;
+        self.errors.truncate(checkpoint.errors_len);
+        self.pending_chars.clear();
+        self.underflow = false;
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parsing-elements-that-contain-only-text
+    /// 構文解析器から呼ばれ、次の入力から `state` で字句解析する。<title>や<style>などの中身は、
+    /// 直前の開始タグに対応する終了タグが現れるまでタグとして解釈しない
+    pub fn switch_to(&mut self, state: State) {
+        self.text_state = state;
+        self.state = state;
+    }
+
+    pub fn errors(&self) -> Vec<ParseError> {
+        self.errors.clone()
+    }
+
+    /// 次に読む文字の位置
+    pub fn position(&self) -> SourcePosition {
+        self.position_of(self.pos)
+    }
+
+    /// 最後に出力したトークンが始まった位置
+    pub fn token_position(&self) -> SourcePosition {
+        self.position_of(self.token_start)
+    }
+
+    /// 入力の先頭から数え直さずに、前回求めた位置との間の文字だけを数える。
+    /// トークンごとに呼ばれても、入力全体で数える文字数は入力の長さに比例する
+    fn position_of(&self, index: usize) -> SourcePosition {
+        let index = index.clamp(self.input_start, self.input.len());
+        let last = self.last_position.get();
+        let last_index = last.offset() - self.base.offset() + self.input_start;
+
+        if index >= last_index {
+            let delta = &self.input[last_index..index];
+            let offset = last.offset() + delta.len();
+            let position = match delta.rfind('\n') {
+                Some(i) => SourcePosition::new(
+                    offset,
+                    last.line() + delta.matches('\n').count(),
+                    delta[i + 1..].chars().count() + 1,
+                ),
+                None => SourcePosition::new(offset, last.line(), last.column() + delta.chars().count()),
+            };
+            self.last_position.set(position);
+            return position;
+        }
+
+        // 読み直しなどで戻った位置。たいていは数文字しか戻らない
+        let delta = &self.input[index..last_index];
+        let offset = last.offset() - delta.len();
+        if !delta.contains('\n') {
+            return SourcePosition::new(offset, last.line(), last.column() - delta.chars().count());
         }
+        let line = last.line() - delta.matches('\n').count();
+        let consumed = &self.input[self.input_start..index];
+        let column = match consumed.rfind('\n') {
+            Some(i) => consumed[i + 1..].chars().count() + 1,
+            None => self.base.column() + consumed.chars().count(),
+        };
+        SourcePosition::new(offset, line, column)
+    }
+
+    /// 直前に読んだ文字のバイト位置。入力の終わりを越えて読んだ場合は入力の長さを返す
+    fn last_consumed(&self) -> usize {
+        if self.pos > self.input.len() {
+            return self.input.len();
+        }
+        self.input[self.input_start..self.pos]
+            .chars()
+            .next_back()
+            .map_or(self.input_start, |c| self.pos - c.len_utf8())
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+    /// 直前に読んだ文字の位置にエラーを記録する
+    fn parse_error(&mut self, code: &str) {
+        let position = self.position_of(self.last_consumed());
+        self.errors.push(ParseError::new(code, position));
+    }
+
+    /// タグの途中で入力が終わった場合は、読みかけのタグを捨てる
+    fn emit_eof_in_tag(&mut self) -> Option<HtmlToken> {
+        self.parse_error("eof-in-tag");
+        Some(HtmlToken::Eof)
     }
 
     fn is_eof(&self) -> bool {
@@ -29,26 +262,223 @@ impl HtmlTokenizer {
     }
 
     fn consume_next_input(&mut self) -> char {
-        let c = self.input[self.pos];
-        self.pos += 1;
-        c
+        if self.pos >= self.input.len() && self.more_input() {
+            self.underflow = true;
+        }
+
+        // 入力の終わりを越えて読んだ場合は `is_eof()` がtrueになる
+        match self.input.get(self.pos..).and_then(|rest| rest.chars().next()) {
+            Some(c) => {
+                self.pos += c.len_utf8();
+                c
+            }
+            None => {
+                self.pos += 1;
+                '\0'
+            }
+        }
+    }
+
+    /// 次の文字列が `s` と一致すればそれを消費する
+    fn consume_if_matches(&mut self, s: &str, ascii_case_insensitive: bool) -> bool {
+        let len = s.len();
+        let rest = self.input.as_bytes().get(self.pos..).unwrap_or(&[]);
+        if len > rest.len() {
+            // 続きの入力で一致する可能性がある
+            if self.more_input() && rest.iter().zip(s.bytes()).all(|(a, b)| a.eq_ignore_ascii_case(&b)) {
+                self.underflow = true;
+            }
+            return false;
+        }
+
+        let matched = rest[..len]
+            .iter()
+            .zip(s.bytes())
+            .all(|(a, b)| {
+                if ascii_case_insensitive {
+                    a.eq_ignore_ascii_case(&b)
+                } else {
+                    *a == b
+                }
+            });
+
+        if matched {
+            self.pos += len;
+        }
+        matched
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+    /// `&` の直後から文字参照を読み取る。文字参照でなければ何も消費せずに `None` を返す
+    fn consume_character_reference(&mut self, in_attribute: bool) -> Option<String> {
+        let input = self.input.clone();
+        let rest = input.get(self.pos..).unwrap_or("");
+        // 文字参照が入力の終わりまで続いている場合は、続きの入力を待つ
+        if self.more_input() && rest.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'#') {
+            self.underflow = true;
+            return None;
+        }
+        if let Some((len, c)) = match_numeric_character_reference(rest) {
+            self.pos += len;
+            if rest.as_bytes()[len - 1] != b';' {
+                self.parse_error("missing-semicolon-after-character-reference");
+            }
+            return Some(String::from(c));
+        }
+
+        let (len, value) = match_named_character_reference(rest)?;
+        // 属性値の中で `;` で終わらない名前付き文字参照の直後に `=` か英数字が続く場合は、
+        // 歴史的な理由により文字参照として扱わない（例: `?a=1&copy=2`）
+        if in_attribute
+            && rest.as_bytes()[len - 1] != b';'
+            && rest
+                .as_bytes()
+                .get(len)
+                .is_some_and(|c| *c == b'=' || c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        self.pos += len;
+        if rest.as_bytes()[len - 1] != b';' {
+            self.parse_error("missing-semicolon-after-character-reference");
+        }
+        Some(String::from(value))
+    }
+
+    /// 文字参照を展開した文字列の先頭を文字トークンとして返し、残りは次回以降に出力する
+    fn emit_character_reference(&mut self, value: String) -> Option<HtmlToken> {
+        let mut chars = value.chars();
+        let first = chars.next()?;
+        self.pending_chars.extend(chars);
+        Some(HtmlToken::Char(first))
+    }
+
+    fn append_character_reference_to_attribute(&mut self) {
+        match self.consume_character_reference(true) {
+            Some(value) => self.append_attribute_str(&value, false),
+            None => self.append_attribute('&', false),
+        }
     }
 
     fn create_tag(&mut self, start_tag_token: bool) {
         if start_tag_token {
             self.latest_token = Some(HtmlToken::StartTag {
-                tag: String::new(),
+                tag: TokenString::new(),
                 self_closing: false,
                 attributes: Vec::new(),
             })
         } else {
-            self.latest_token = Some(HtmlToken::EndTag { tag: String::new() })
+            self.latest_token = Some(HtmlToken::EndTag { tag: TokenString::new() })
         }
     }
 
     fn reconsume_input(&mut self) -> char {
         self.reconsume = false;
-        self.input[self.pos - 1]
+        self.input.get(self.last_consumed()..).and_then(|rest| rest.chars().next()).unwrap_or('\0')
+    }
+
+    fn create_comment(&mut self) {
+        self.latest_token = Some(HtmlToken::Comment(String::new()));
+    }
+
+    fn append_comment(&mut self, s: &str) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::Comment(ref mut data)) = self.latest_token.as_mut() {
+            data.push_str(s);
+        }
+    }
+
+    /// コメントの途中で入力が終わった場合は、そこまでをコメントとして出力してから入力の終わりを出力する
+    fn emit_comment_at_eof(&mut self) -> Option<HtmlToken> {
+        self.parse_error("eof-in-comment");
+        self.reconsume = true;
+        self.state = State::Data;
+        self.take_latest_token()
+    }
+
+    fn create_doctype(&mut self) {
+        self.latest_token = Some(HtmlToken::Doctype {
+            name: None,
+            public_id: None,
+            system_id: None,
+            force_quirks: false,
+        })
+    }
+
+    fn append_doctype_name(&mut self, c: char) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::Doctype { ref mut name, .. }) = self.latest_token.as_mut() {
+            name.get_or_insert_with(String::new).push(c);
+        }
+    }
+
+    /// `is_public` がtrueなら公開識別子、falseならシステム識別子に文字を追加する
+    fn append_doctype_identifier(&mut self, c: char, is_public: bool) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::Doctype { ref mut public_id, ref mut system_id, .. }) = self.latest_token.as_mut() {
+            let id = if is_public { public_id } else { system_id };
+            id.get_or_insert_with(String::new).push(c);
+        }
+    }
+
+    fn start_doctype_identifier(&mut self, is_public: bool) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::Doctype { ref mut public_id, ref mut system_id, .. }) = self.latest_token.as_mut() {
+            let id = if is_public { public_id } else { system_id };
+            *id = Some(String::new());
+        }
+    }
+
+    fn set_force_quirks_flag(&mut self) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::Doctype { ref mut force_quirks, .. }) = self.latest_token.as_mut() {
+            *force_quirks = true;
+        }
+    }
+
+    /// DOCTYPEの途中で入力が終わった場合は、quirks modeを強制したDOCTYPEトークンを出力する
+    fn emit_doctype_at_eof(&mut self) -> Option<HtmlToken> {
+        self.parse_error("eof-in-doctype");
+        if self.latest_token.is_none() {
+            self.create_doctype();
+        }
+        self.set_force_quirks_flag();
+        self.take_latest_token()
+    }
+
+    /// 直前に読んだ `c` に続いて、`is_delimiter` に当てはまらない文字が続く範囲をまとめて読む。
+    /// 1文字ずつ状態を遷移させずに済むので、長い属性値などを速く読める。読んだ範囲は入力を複製せずに指し、
+    /// `lowercase` で大文字を小文字にする必要がある場合だけ文字列を作る
+    fn consume_run(&mut self, c: char, is_delimiter: fn(char) -> bool, lowercase: bool) -> TokenString {
+        let start = self.pos - c.len_utf8();
+        let end = self.input[self.pos..]
+            .find(is_delimiter)
+            .map_or(self.input.len(), |i| self.pos + i);
+        self.pos = end;
+
+        let run = TokenString::span(&self.input, start, end);
+        if lowercase && run.bytes().any(|b| b.is_ascii_uppercase()) {
+            return TokenString::from(run.to_ascii_lowercase());
+        }
+        run
+    }
+
+    fn append_tag_name_run(&mut self, run: TokenString) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(t) = self.latest_token.as_mut() {
+            match t {
+                HtmlToken::StartTag { ref mut tag, .. } | HtmlToken::EndTag { ref mut tag } => {
+                    tag.append(run)
+                }
+                _ => panic!("`latest_token` should be either StartTag or Endtag"),
+            }
+        }
     }
 
     fn append_tag_name(&mut self, c: char) {
@@ -67,8 +497,12 @@ impl HtmlTokenizer {
     fn take_latest_token(&mut self) -> Option<HtmlToken> {
         assert!(self.latest_token.is_some());
 
-        let t = self.latest_token.as_ref().cloned();
-        self.latest_token = None;
+        let mut t = self.latest_token.take();
+
+        if let Some(HtmlToken::StartTag { ref tag, ref mut attributes, .. }) = t {
+            self.last_start_tag = tag.clone();
+            remove_duplicate_attributes(attributes);
+        }
 
         assert!(self.latest_token.is_none());
 
@@ -83,6 +517,8 @@ impl HtmlTokenizer {
                 HtmlToken::StartTag { ref mut attributes, .. } => {
                     attributes.push(Attribute::new())
                 }
+                // 終了タグの属性は捨てる
+                HtmlToken::EndTag { .. } => {}
                 _ => panic!("`latest_token` should be either StartTag")
             }
         }
@@ -101,37 +537,58 @@ impl HtmlTokenizer {
 
                     attributes[len - 1].add_char(c, is_name);
                 }
+                HtmlToken::EndTag { .. } => {}
                 _ => panic!("`latest_token` should be either StartTag")
             }
         }
     }
 
+    fn append_attribute_str(&mut self, s: &str, is_name: bool) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::StartTag { ref mut attributes, .. }) = self.latest_token.as_mut() {
+            let len = attributes.len();
+            assert!(len > 0);
+
+            attributes[len - 1].add_str(s, is_name);
+        }
+    }
+
+    fn append_attribute_run(&mut self, run: TokenString, is_name: bool) {
+        assert!(self.latest_token.is_some());
+
+        if let Some(HtmlToken::StartTag { ref mut attributes, .. }) = self.latest_token.as_mut() {
+            let len = attributes.len();
+            assert!(len > 0);
+
+            attributes[len - 1].add_token_string(run, is_name);
+        }
+    }
+
     fn set_self_closing_flag(&mut self) {
         assert!(self.latest_token.is_some());
 
         if let Some(t) = self.latest_token.as_mut() {
             match t {
                 HtmlToken::StartTag { ref mut self_closing, .. } => *self_closing = true,
+                HtmlToken::EndTag { .. } => {}
                 _ => panic!("`latest_token` should be either StartTag")
             }
         }
     }
 }
 
-impl Iterator for HtmlTokenizer {
-    type Item = HtmlToken;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos >= self.input.len() {
-            return None;
-        }
-
+impl HtmlTokenizer {
+    /// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
+    fn tokenize(&mut self) -> Option<HtmlToken> {
         loop {
             let c = match self.reconsume {
                 true => self.reconsume_input(),
                 false => self.consume_next_input(),
             };
-
+            if self.underflow {
+                return None;
+            }
 
             match self.state {
                 State::Data => {
@@ -140,6 +597,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        if let Some(value) = self.consume_character_reference(false) {
+                            return self.emit_character_reference(value);
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+
                     if self.is_eof() {
                         return Some(HtmlToken::Eof);
                     }
@@ -147,6 +611,11 @@ impl Iterator for HtmlTokenizer {
                     return Some(HtmlToken::Char(c));
                 }
                 State::TagOpen => {
+                    if c == '!' {
+                        self.state = State::MarkupDeclarationOpen;
+                        continue;
+                    }
+
                     if c == '/' {
                         self.state = State::EndTagOpen;
                         continue;
@@ -159,16 +628,32 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
-                    if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                    if c == '?' {
+                        self.parse_error("unexpected-question-mark-instead-of-tag-name");
+                        self.create_comment();
+                        self.reconsume = true;
+                        self.state = State::BogusComment;
+                        continue;
                     }
 
+                    // Failed to parse. タグとして解釈できない `<` は文字として扱う
+                    if self.is_eof() {
+                        self.parse_error("eof-before-tag-name");
+                    } else {
+                        self.parse_error("invalid-first-character-of-tag-name");
+                    }
                     self.reconsume = true;
                     self.state = State::Data;
+                    return Some(HtmlToken::Char('<'));
                 }
                 State::EndTagOpen => {
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        // Failed to parse. `</` を文字として扱う
+                        self.parse_error("eof-before-tag-name");
+                        self.pending_chars.push('/');
+                        self.reconsume = true;
+                        self.state = State::Data;
+                        return Some(HtmlToken::Char('<'));
                     }
 
                     if c.is_ascii_alphabetic() {
@@ -177,9 +662,21 @@ impl Iterator for HtmlTokenizer {
                         self.create_tag(false);
                         continue;
                     }
+
+                    // Failed to parse. `</>` は読み飛ばす
+                    if c == '>' {
+                        self.parse_error("missing-end-tag-name");
+                        self.state = State::Data;
+                        continue;
+                    }
+
+                    self.parse_error("invalid-first-character-of-tag-name");
+                    self.create_comment();
+                    self.reconsume = true;
+                    self.state = State::BogusComment;
                 }
                 State::TagName => {
-                    if c == ' ' {
+                    if is_html_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -200,24 +697,38 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
 
-                    self.append_tag_name(c);
+                    let run = self.consume_run(c, |c| is_html_whitespace(c) || c == '/' || c == '>', true);
+                    self.append_tag_name_run(run);
                 }
                 State::BeforeAttributeName => {
+                    if is_html_whitespace(c) {
+                        continue;
+                    }
+
                     if c == '/' || c == '>' || self.is_eof() {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
                     }
 
+                    if c == '=' {
+                        // Failed to parse. `=` を属性名の最初の文字として扱う
+                        self.parse_error("unexpected-equals-sign-before-attribute-name");
+                        self.start_new_attribute();
+                        self.append_attribute(c, true);
+                        self.state = State::AttributeName;
+                        continue;
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeName;
                     self.start_new_attribute();
                 }
                 State::AttributeName => {
-                    if c == ' ' || c == '/' || c == '>' || self.is_eof() {
+                    if is_html_whitespace(c) || c == '/' || c == '>' || self.is_eof() {
                         self.reconsume = true;
                         self.state = State::AfterAttributeName;
                         continue;
@@ -230,12 +741,24 @@ impl Iterator for HtmlTokenizer {
 
                     if c.is_ascii_uppercase() {
                         self.append_attribute(c.to_ascii_lowercase(), true);
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' || c == '<' {
+                        self.parse_error("unexpected-character-in-attribute-name");
+                        self.append_attribute(c, true);
+                        continue;
                     }
 
-                    self.append_attribute(c, true);
+                    let run = self.consume_run(
+                        c,
+                        |c| is_html_whitespace(c) || matches!(c, '/' | '>' | '=' | '"' | '\'' | '<'),
+                        true,
+                    );
+                    self.append_attribute_run(run, true);
                 }
                 State::AfterAttributeName => {
-                    if c == ' ' {
+                    if is_html_whitespace(c) {
                         continue;
                     }
 
@@ -255,7 +778,7 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
 
                     self.reconsume = true;
@@ -263,7 +786,7 @@ impl Iterator for HtmlTokenizer {
                     self.start_new_attribute();
                 }
                 State::BeforeAttributeValue => {
-                    if c == ' ' {
+                    if is_html_whitespace(c) {
                         continue;
                     }
 
@@ -277,6 +800,13 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '>' {
+                        // Failed to parse. 値が省略された属性は空文字列として扱う
+                        self.parse_error("missing-attribute-value");
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
                     self.reconsume = true;
                     self.state = State::AttributeValueUnquoted;
                 }
@@ -286,11 +816,17 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        self.append_character_reference_to_attribute();
+                        continue;
+                    }
+
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
 
-                    self.append_attribute(c, false)
+                    let run = self.consume_run(c, |c| c == '"' || c == '&', false);
+                    self.append_attribute_run(run, false);
                 }
                 State::AttributeValueSingleQuoted => {
                     if c == '\'' {
@@ -298,31 +834,53 @@ impl Iterator for HtmlTokenizer {
                         continue;
                     }
 
+                    if c == '&' {
+                        self.append_character_reference_to_attribute();
+                        continue;
+                    }
+
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
 
-                    self.append_attribute(c, false);
+                    let run = self.consume_run(c, |c| c == '\'' || c == '&', false);
+                    self.append_attribute_run(run, false);
                 }
                 State::AttributeValueUnquoted => {
-                    if c == ' ' {
+                    if is_html_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
 
+                    if c == '&' {
+                        self.append_character_reference_to_attribute();
+                        continue;
+                    }
+
                     if c == '>' {
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
 
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
+                    }
+
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.parse_error("unexpected-character-in-unquoted-attribute-value");
+                        self.append_attribute(c, false);
+                        continue;
                     }
 
-                    self.append_attribute(c, false)
+                    let run = self.consume_run(
+                        c,
+                        |c| is_html_whitespace(c) || matches!(c, '&' | '>' | '"' | '\'' | '<' | '=' | '`'),
+                        false,
+                    );
+                    self.append_attribute_run(run, false);
                 }
                 State::AfterAttributeValueQuoted => {
-                    if c == ' ' {
+                    if is_html_whitespace(c) {
                         self.state = State::BeforeAttributeName;
                         continue;
                     }
@@ -338,11 +896,13 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
 
+                    // Failed to parse. 属性の間に空白がない場合は、次の属性として扱う
+                    self.parse_error("missing-whitespace-between-attributes");
                     self.reconsume = true;
-                    self.state = State::BeforeAttributeValue;
+                    self.state = State::BeforeAttributeName;
                 }
                 State::SelfClosingStartTag => {
                     if c == '>' {
@@ -352,96 +912,684 @@ impl Iterator for HtmlTokenizer {
                     }
 
                     if self.is_eof() {
-                        // invalid parse error
-                        return Some(HtmlToken::Eof);
+                        return self.emit_eof_in_tag();
                     }
+
+                    // Failed to parse. `/` を無視して属性の続きとして扱う
+                    self.parse_error("unexpected-solidus-in-tag");
+                    self.reconsume = true;
+                    self.state = State::BeforeAttributeName;
                 }
-                State::ScriptData => {
-                    if c == '<' {
-                        self.state = State::ScriptDataLessThanSign;
+                State::MarkupDeclarationOpen => {
+                    // 次の数文字を先読みするため、消費した文字を戻す
+                    self.pos = self.last_consumed();
+
+                    if self.consume_if_matches("--", false) {
+                        self.create_comment();
+                        self.state = State::CommentStart;
                         continue;
                     }
 
+                    if self.consume_if_matches("DOCTYPE", true) {
+                        self.state = State::Doctype;
+                        continue;
+                    }
+
+                    // Failed to parse. `<!` から `>` までをコメントとして扱う
+                    self.parse_error("incorrectly-opened-comment");
+                    self.create_comment();
+                    self.state = State::BogusComment;
+                }
+                State::BogusComment => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
                     if self.is_eof() {
-                        return Some(HtmlToken::Eof);
+                        self.reconsume = true;
+                        self.state = State::Data;
+                        return self.take_latest_token();
                     }
 
-                    return Some(HtmlToken::Char(c));
+                    let run = self.consume_run(c, |c| c == '>', false);
+                    self.append_comment(&run);
                 }
-                State::ScriptDataLessThanSign => {
-                    if c == '/' {
-                        self.buf = String::new();
-                        self.state = State::ScriptDataEndTagOpen;
+                State::CommentStart => {
+                    if c == '-' {
+                        self.state = State::CommentStartDash;
                         continue;
                     }
 
-                    self.reconsume = true;
-                    self.state = State::ScriptData;
-                    return Some(HtmlToken::Char('<'));
-                }
-                State::ScriptDataEndTagOpen => {
-                    if c.is_ascii_alphabetic() {
-                        self.reconsume = true;
-                        self.state = State::ScriptDataEndTagName;
-                        self.create_tag(false);
+                    if c == '>' {
+                        self.parse_error("abrupt-closing-of-empty-comment");
+                        self.state = State::Data;
+                        return self.take_latest_token();
                     }
 
                     self.reconsume = true;
-                    self.state = State::ScriptData;
-                    return Some(HtmlToken::Char('<'));
+                    self.state = State::Comment;
                 }
-                State::ScriptDataEndTagName => {
+                State::CommentStartDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
                     if c == '>' {
+                        self.parse_error("abrupt-closing-of-empty-comment");
                         self.state = State::Data;
                         return self.take_latest_token();
                     }
 
-                    if c.is_ascii_alphabetic() {
-                        self.buf.push(c);
-                        self.append_tag_name(c.to_ascii_lowercase());
-                        continue;
+                    if self.is_eof() {
+                        return self.emit_comment_at_eof();
                     }
 
-                    self.state = State::TemporaryBuffer;
-                    self.buf = String::from("</") + &self.buf;
-                    self.buf.push(c);
-                    continue;
-                }
-                State::TemporaryBuffer => {
+                    self.append_comment("-");
                     self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Comment => {
+                    if c == '-' {
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
 
-                    if self.buf.chars().count() == 0 {
-                        self.state = State::ScriptData;
+                    if self.is_eof() {
+                        return self.emit_comment_at_eof();
                     }
 
-                    let c = self.buf
-                        .chars()
-                        .nth(0)
-                        .expect("self.buf should have at least 1 char");
-                    self.buf.remove(0);
-                    return Some(HtmlToken::Char(c));
+                    let run = self.consume_run(c, |c| c == '-', false);
+                    self.append_comment(&run);
                 }
-            }
+                State::CommentEndDash => {
+                    if c == '-' {
+                        self.state = State::CommentEnd;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_comment_at_eof();
+                    }
+
+                    self.append_comment("-");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEnd => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '!' {
+                        self.state = State::CommentEndBang;
+                        continue;
+                    }
+
+                    if c == '-' {
+                        self.append_comment("-");
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_comment_at_eof();
+                    }
+
+                    self.append_comment("--");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::CommentEndBang => {
+                    if c == '-' {
+                        self.append_comment("--!");
+                        self.state = State::CommentEndDash;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error("incorrectly-closed-comment");
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_comment_at_eof();
+                    }
+
+                    self.append_comment("--!");
+                    self.reconsume = true;
+                    self.state = State::Comment;
+                }
+                State::Doctype => {
+                    if is_html_whitespace(c) {
+                        self.state = State::BeforeDoctypeName;
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.parse_error("missing-whitespace-before-doctype-name");
+                    self.reconsume = true;
+                    self.state = State::BeforeDoctypeName;
+                }
+                State::BeforeDoctypeName => {
+                    if is_html_whitespace(c) {
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.create_doctype();
+
+                    if c == '>' {
+                        self.parse_error("missing-doctype-name");
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                    self.state = State::DoctypeName;
+                }
+                State::DoctypeName => {
+                    if is_html_whitespace(c) {
+                        self.state = State::AfterDoctypeName;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.append_doctype_name(c.to_ascii_lowercase());
+                }
+                State::AfterDoctypeName => {
+                    if is_html_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    let pos = self.pos;
+                    self.pos = self.last_consumed();
+                    if self.consume_if_matches("PUBLIC", true) {
+                        self.state = State::AfterDoctypePublicKeyword;
+                        continue;
+                    }
+                    if self.consume_if_matches("SYSTEM", true) {
+                        self.state = State::AfterDoctypeSystemKeyword;
+                        continue;
+                    }
+                    self.pos = pos;
+
+                    self.parse_error("invalid-character-sequence-after-doctype-name");
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypePublicKeyword
+                | State::BeforeDoctypePublicIdentifier
+                | State::AfterDoctypeSystemKeyword
+                | State::BeforeDoctypeSystemIdentifier => {
+                    let is_public = self.state == State::AfterDoctypePublicKeyword
+                        || self.state == State::BeforeDoctypePublicIdentifier;
+
+                    if is_html_whitespace(c) {
+                        self.state = match self.state {
+                            State::AfterDoctypePublicKeyword => State::BeforeDoctypePublicIdentifier,
+                            State::AfterDoctypeSystemKeyword => State::BeforeDoctypeSystemIdentifier,
+                            _ => self.state,
+                        };
+                        continue;
+                    }
+
+                    if c == '"' || c == '\'' {
+                        self.start_doctype_identifier(is_public);
+                        self.state = match (is_public, c) {
+                            (true, '"') => State::DoctypePublicIdentifierDoubleQuoted,
+                            (true, _) => State::DoctypePublicIdentifierSingleQuoted,
+                            (false, '"') => State::DoctypeSystemIdentifierDoubleQuoted,
+                            (false, _) => State::DoctypeSystemIdentifierSingleQuoted,
+                        };
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(if is_public {
+                            "missing-doctype-public-identifier"
+                        } else {
+                            "missing-doctype-system-identifier"
+                        });
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.parse_error(if is_public {
+                        "missing-quote-before-doctype-public-identifier"
+                    } else {
+                        "missing-quote-before-doctype-system-identifier"
+                    });
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::DoctypePublicIdentifierDoubleQuoted
+                | State::DoctypePublicIdentifierSingleQuoted
+                | State::DoctypeSystemIdentifierDoubleQuoted
+                | State::DoctypeSystemIdentifierSingleQuoted => {
+                    let (is_public, quote) = match self.state {
+                        State::DoctypePublicIdentifierDoubleQuoted => (true, '"'),
+                        State::DoctypePublicIdentifierSingleQuoted => (true, '\''),
+                        State::DoctypeSystemIdentifierDoubleQuoted => (false, '"'),
+                        _ => (false, '\''),
+                    };
+
+                    if c == quote {
+                        self.state = if is_public {
+                            State::AfterDoctypePublicIdentifier
+                        } else {
+                            State::AfterDoctypeSystemIdentifier
+                        };
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.parse_error(if is_public {
+                            "abrupt-doctype-public-identifier"
+                        } else {
+                            "abrupt-doctype-system-identifier"
+                        });
+                        self.set_force_quirks_flag();
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.append_doctype_identifier(c, is_public);
+                }
+                State::AfterDoctypePublicIdentifier | State::BetweenDoctypePublicAndSystemIdentifiers => {
+                    if is_html_whitespace(c) {
+                        self.state = State::BetweenDoctypePublicAndSystemIdentifiers;
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if c == '"' || c == '\'' {
+                        self.start_doctype_identifier(false);
+                        self.state = if c == '"' {
+                            State::DoctypeSystemIdentifierDoubleQuoted
+                        } else {
+                            State::DoctypeSystemIdentifierSingleQuoted
+                        };
+                        continue;
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.parse_error("missing-quote-before-doctype-system-identifier");
+                    self.set_force_quirks_flag();
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::AfterDoctypeSystemIdentifier => {
+                    if is_html_whitespace(c) {
+                        continue;
+                    }
+
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.emit_doctype_at_eof();
+                    }
+
+                    self.parse_error("unexpected-character-after-doctype-system-identifier");
+                    self.reconsume = true;
+                    self.state = State::BogusDoctype;
+                }
+                State::BogusDoctype => {
+                    if c == '>' {
+                        self.state = State::Data;
+                        return self.take_latest_token();
+                    }
+
+                    if self.is_eof() {
+                        return self.take_latest_token();
+                    }
+                }
+                State::Rcdata | State::Rawtext | State::ScriptData => {
+                    if c == '<' {
+                        self.state = State::TextLessThanSign;
+                        continue;
+                    }
+
+                    // 文字参照を展開するのはRCDATAだけ
+                    if c == '&' && self.state == State::Rcdata {
+                        if let Some(value) = self.consume_character_reference(false) {
+                            return self.emit_character_reference(value);
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if self.is_eof() {
+                        return Some(HtmlToken::Eof);
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::Plaintext => {
+                    // PLAINTEXTは終了タグでも終わらない
+                    if self.is_eof() {
+                        return Some(HtmlToken::Eof);
+                    }
+
+                    return Some(HtmlToken::Char(c));
+                }
+                State::TextLessThanSign => {
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = State::TextEndTagOpen;
+                        continue;
+                    }
+
+                    if c == '!' && self.text_state == State::ScriptData {
+                        self.state = State::ScriptDataEscapeStart;
+                        self.pending_chars.push('!');
+                        return Some(HtmlToken::Char('<'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = self.text_state;
+                    return Some(HtmlToken::Char('<'));
+                }
+                State::TextEndTagOpen => {
+                    if c.is_ascii_alphabetic() {
+                        self.reconsume = true;
+                        self.state = State::TextEndTagName;
+                        self.create_tag(false);
+                        continue;
+                    }
+
+                    self.reconsume = true;
+                    self.state = self.text_state;
+                    self.pending_chars.push('/');
+                    return Some(HtmlToken::Char('<'));
+                }
+                // https://html.spec.whatwg.org/multipage/parsing.html#script-data-escape-start-state
+                // <script>の中の `<!--` から `-->` までは、`<script>` を含んでいても終了タグで終わらせない
+                State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => {
+                    if c == '-' {
+                        if self.state == State::ScriptDataEscapeStart {
+                            self.state = State::ScriptDataEscapeStartDash;
+                        } else {
+                            self.state = State::ScriptDataEscapedDashDash;
+                            self.text_state = State::ScriptDataEscaped;
+                        }
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptData;
+                }
+                State::ScriptDataEscaped | State::ScriptDataEscapedDash | State::ScriptDataEscapedDashDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataEscaped => State::ScriptDataEscapedDash,
+                            _ => State::ScriptDataEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c == '<' {
+                        self.state = State::ScriptDataEscapedLessThanSign;
+                        continue;
+                    }
+
+                    if c == '>' && self.state == State::ScriptDataEscapedDashDash {
+                        self.state = State::ScriptData;
+                        self.text_state = State::ScriptData;
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if self.is_eof() {
+                        self.parse_error("eof-in-script-html-comment-like-text");
+                        return Some(HtmlToken::Eof);
+                    }
+
+                    self.state = State::ScriptDataEscaped;
+                    return Some(HtmlToken::Char(c));
+                }
+                State::ScriptDataEscapedLessThanSign => {
+                    // 終了タグの判定は、エスケープされていない場合と同じ状態で行う
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = State::TextEndTagOpen;
+                        continue;
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf = String::new();
+                        self.reconsume = true;
+                        self.state = State::ScriptDataDoubleEscapeStart;
+                        return Some(HtmlToken::Char('<'));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataEscaped;
+                    return Some(HtmlToken::Char('<'));
+                }
+                // https://html.spec.whatwg.org/multipage/parsing.html#script-data-double-escape-start-state
+                // `<!--` の後の `<script>` から `</script>` までは、終了タグを探さない
+                State::ScriptDataDoubleEscapeStart | State::ScriptDataDoubleEscapeEnd => {
+                    let starts = self.state == State::ScriptDataDoubleEscapeStart;
+                    if is_html_whitespace(c) || c == '/' || c == '>' {
+                        self.state = match (self.buf == "script", starts) {
+                            (true, true) | (false, false) => State::ScriptDataDoubleEscaped,
+                            (true, false) | (false, true) => State::ScriptDataEscaped,
+                        };
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf.push(c.to_ascii_lowercase());
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    self.reconsume = true;
+                    self.state = if starts { State::ScriptDataEscaped } else { State::ScriptDataDoubleEscaped };
+                }
+                State::ScriptDataDoubleEscaped
+                | State::ScriptDataDoubleEscapedDash
+                | State::ScriptDataDoubleEscapedDashDash => {
+                    if c == '-' {
+                        self.state = match self.state {
+                            State::ScriptDataDoubleEscaped => State::ScriptDataDoubleEscapedDash,
+                            _ => State::ScriptDataDoubleEscapedDashDash,
+                        };
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c == '<' {
+                        self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if c == '>' && self.state == State::ScriptDataDoubleEscapedDashDash {
+                        self.state = State::ScriptData;
+                        self.text_state = State::ScriptData;
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    if self.is_eof() {
+                        self.parse_error("eof-in-script-html-comment-like-text");
+                        return Some(HtmlToken::Eof);
+                    }
+
+                    self.state = State::ScriptDataDoubleEscaped;
+                    return Some(HtmlToken::Char(c));
+                }
+                State::ScriptDataDoubleEscapedLessThanSign => {
+                    if c == '/' {
+                        self.buf = String::new();
+                        self.state = State::ScriptDataDoubleEscapeEnd;
+                        return Some(HtmlToken::Char(c));
+                    }
+
+                    self.reconsume = true;
+                    self.state = State::ScriptDataDoubleEscaped;
+                }
+                State::TextEndTagName => {
+                    // https://html.spec.whatwg.org/multipage/parsing.html#appropriate-end-tag-token
+                    if self.buf.to_ascii_lowercase() == *self.last_start_tag {
+                        if is_html_whitespace(c) {
+                            self.state = State::BeforeAttributeName;
+                            continue;
+                        }
+
+                        if c == '/' {
+                            self.state = State::SelfClosingStartTag;
+                            continue;
+                        }
+
+                        if c == '>' {
+                            self.state = State::Data;
+                            return self.take_latest_token();
+                        }
+                    }
+
+                    if c.is_ascii_alphabetic() {
+                        self.buf.push(c);
+                        self.append_tag_name(c.to_ascii_lowercase());
+                        continue;
+                    }
+
+                    // 対応する終了タグではなかったので、読んだ文字をそのまま出力する
+                    self.latest_token = None;
+                    self.reconsume = true;
+                    self.state = self.text_state;
+                    self.pending_chars.push('/');
+                    self.pending_chars.extend(self.buf.chars());
+                    return Some(HtmlToken::Char('<'));
+                }
+            }
         }
     }
 }
 
+impl Iterator for HtmlTokenizer {
+    type Item = HtmlToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.pending_chars.is_empty() {
+            return Some(HtmlToken::Char(self.pending_chars.remove(0)));
+        }
+
+        loop {
+            // 最後の文字を読み直す場合は、入力の終わりに達していても続ける
+            if self.pos >= self.input.len() && !self.reconsume {
+                if self.chunks.is_empty() {
+                    return None;
+                }
+                self.pull_input();
+                continue;
+            }
+
+            // 入力が途中で足りなくなった場合は、読み始めた位置まで戻して次の入力を待つ。
+            // 続きの入力があり得ないときは、戻す必要がないので記録しない
+            let checkpoint = if self.more_input() { Some(self.checkpoint()) } else { None };
+            self.token_start = if self.reconsume { self.last_consumed() } else { self.pos };
+            let token = self.tokenize();
+            if let (true, Some(checkpoint)) = (self.underflow, checkpoint) {
+                self.restore(checkpoint);
+                if self.chunks.is_empty() {
+                    return None;
+                }
+                self.pull_input();
+                continue;
+            }
+            return token;
+        }
+    }
+}
+
+/// https://html.spec.whatwg.org/multipage/parsing.html#attribute-name-state
+/// 同じ名前の属性が複数ある場合は、最初のものだけを残す
+fn remove_duplicate_attributes(attributes: &mut Vec<Attribute>) {
+    let mut i = 0;
+    while i < attributes.len() {
+        let name = attributes[i].name();
+        if attributes[..i].iter().any(|a| a.name() == name) {
+            attributes.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// https://infra.spec.whatwg.org/#ascii-whitespace
+/// 字句解析と木構築の両方で空白として扱う文字
+pub fn is_html_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HtmlToken {
     StartTag {
-        tag: String,
+        tag: TokenString,
         self_closing: bool,
         attributes: Vec<Attribute>,
     },
     EndTag {
-        tag: String,
+        tag: TokenString,
+    },
+    Doctype {
+        name: Option<String>,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        force_quirks: bool,
     },
+    /// https://html.spec.whatwg.org/multipage/parsing.html#comment-state
+    Comment(String),
     Char(char),
     Eof,
 }
 
 /// https://html.spec.whatwg.org/multipage/parsing.html#tokenization
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum State {
     Data,
     TagOpen,
@@ -456,18 +1604,57 @@ pub enum State {
     AttributeValueUnquoted,
     AfterAttributeValueQuoted,
     SelfClosingStartTag,
+    MarkupDeclarationOpen,
+    BogusComment,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    Rcdata,
+    Rawtext,
     ScriptData,
-    ScriptDataLessThanSign,
-    ScriptDataEndTagOpen,
-    ScriptDataEndTagName,
-    TemporaryBuffer,
+    Plaintext,
+    TextLessThanSign,
+    TextEndTagOpen,
+    TextEndTagName,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThanSign,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThanSign,
+    ScriptDataDoubleEscapeEnd,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::renderer::html::attribute::Attribute;
-    use crate::renderer::html::token::{HtmlToken, HtmlTokenizer};
-    use alloc::string::ToString;
+    use crate::renderer::html::parse_error::SourcePosition;
+    use crate::renderer::html::token::{HtmlToken, HtmlTokenizer, State};
+    use alloc::string::{String, ToString};
     use alloc::vec;
     use alloc::vec::Vec;
 
@@ -484,11 +1671,11 @@ mod tests {
         let mut tokenizer = HtmlTokenizer::new(html);
         let expected = [
             HtmlToken::StartTag {
-                tag: "body".to_string(),
+                tag: "body".into(),
                 self_closing: false,
                 attributes: Vec::new(),
             },
-            HtmlToken::EndTag { tag: "body".to_string() }
+            HtmlToken::EndTag { tag: "body".into() }
         ];
 
         for e in expected {
@@ -514,11 +1701,11 @@ mod tests {
 
         let expected = [
             HtmlToken::StartTag {
-                tag: "p".to_string(),
+                tag: "p".into(),
                 self_closing: false,
                 attributes: vec![attr1, attr2, attr3],
             },
-            HtmlToken::EndTag { tag: "p".to_string() }
+            HtmlToken::EndTag { tag: "p".into() }
         ];
 
         for e in expected {
@@ -532,7 +1719,7 @@ mod tests {
         let mut tokenizer = HtmlTokenizer::new(html);
         let expected = [
             HtmlToken::StartTag {
-                tag: "img".to_string(),
+                tag: "img".into(),
                 self_closing: true,
                 attributes: Vec::new(),
             }
@@ -548,7 +1735,7 @@ mod tests {
         let mut tokenizer = HtmlTokenizer::new(html);
         let expected = [
             HtmlToken::StartTag {
-                tag: "script".to_string(),
+                tag: "script".into(),
                 self_closing: false,
                 attributes: Vec::new(),
             },
@@ -560,11 +1747,356 @@ mod tests {
             HtmlToken::Char('d'),
             HtmlToken::Char('e'),
             HtmlToken::Char(';'),
-            HtmlToken::EndTag { tag: "script".to_string() },
+            HtmlToken::EndTag { tag: "script".into() },
+        ];
+
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_doctype() {
+        let html = "<!DOCTYPE html><html>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = [
+            HtmlToken::Doctype {
+                name: Some("html".to_string()),
+                public_id: None,
+                system_id: None,
+                force_quirks: false,
+            },
+            HtmlToken::StartTag {
+                tag: "html".into(),
+                self_closing: false,
+                attributes: Vec::new(),
+            },
+        ];
+
+        for e in expected {
+            assert_eq!(Some(e), tokenizer.next());
+        }
+    }
+
+    #[test]
+    fn test_doctype_with_identifiers() {
+        let html = "<!doctype HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" 'http://www.w3.org/TR/html4/strict.dtd'>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = HtmlToken::Doctype {
+            name: Some("html".to_string()),
+            public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+            system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+            force_quirks: false,
+        };
+
+        assert_eq!(Some(expected), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_doctype_eof() {
+        let html = "<!DOCTYPE html".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let expected = HtmlToken::Doctype {
+            name: Some("html".to_string()),
+            public_id: None,
+            system_id: None,
+            force_quirks: true,
+        };
+
+        assert_eq!(Some(expected), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
+
+    #[test]
+    fn test_named_character_reference() {
+        let html = "a&lt;b&amp c&unknown;".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let expected: Vec<HtmlToken> = "a<b& c&unknown;".chars().map(HtmlToken::Char).collect();
+
+        assert_eq!(expected, tokenizer.collect::<Vec<HtmlToken>>());
+    }
+
+    #[test]
+    fn test_numeric_character_reference() {
+        let html = "&#x3042;&#12354 &#128;&#;".to_string();
+        let tokenizer = HtmlTokenizer::new(html);
+        let expected: Vec<HtmlToken> = "\u{3042}\u{3042} \u{20ac}&#;"
+            .chars()
+            .map(HtmlToken::Char)
+            .collect();
+
+        assert_eq!(expected, tokenizer.collect::<Vec<HtmlToken>>());
+    }
+
+    #[test]
+    fn test_named_character_reference_in_attribute() {
+        let html = "<p title=\"&copy;&nbsp;2024\" data-x=a&gt;b></p>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut attr1 = Attribute::new();
+        "title".chars().for_each(|c| attr1.add_char(c, true));
+        "\u{a9}\u{a0}2024".chars().for_each(|c| attr1.add_char(c, false));
+
+        let mut attr2 = Attribute::new();
+        "data-x".chars().for_each(|c| attr2.add_char(c, true));
+        "a>b".chars().for_each(|c| attr2.add_char(c, false));
+
+        let expected = HtmlToken::StartTag {
+            tag: "p".into(),
+            self_closing: false,
+            attributes: vec![attr1, attr2],
+        };
+        assert_eq!(Some(expected), tokenizer.next());
+    }
+
+    #[test]
+    fn test_legacy_character_reference_in_attribute() {
+        let html = "<a href=\"?a=1&amp;b=2&copy=3&not;&notit\">".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut attr = Attribute::new();
+        "href".chars().for_each(|c| attr.add_char(c, true));
+        "?a=1&b=2&copy=3\u{ac}&notit"
+            .chars()
+            .for_each(|c| attr.add_char(c, false));
+
+        let expected = HtmlToken::StartTag {
+            tag: "a".into(),
+            self_closing: false,
+            attributes: vec![attr],
+        };
+        assert_eq!(Some(expected), tokenizer.next());
+
+        // 属性値の外では従来どおり展開する
+        let tokenizer = HtmlTokenizer::new("&copy=3".to_string());
+        let expected: Vec<HtmlToken> = "\u{a9}=3".chars().map(HtmlToken::Char).collect();
+        assert_eq!(expected, tokenizer.collect::<Vec<HtmlToken>>());
+    }
+
+    #[test]
+    fn test_rawtext() {
+        let html = "<style>a</b></sty</STYLE >".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        assert_eq!(
+            Some(HtmlToken::StartTag {
+                tag: "style".into(),
+                self_closing: false,
+                attributes: Vec::new(),
+            }),
+            tokenizer.next()
+        );
+
+        tokenizer.switch_to(State::Rawtext);
+        let mut expected: Vec<HtmlToken> = "a</b></sty".chars().map(HtmlToken::Char).collect();
+        expected.push(HtmlToken::EndTag {
+            tag: "style".into(),
+        });
+        assert_eq!(expected, tokenizer.collect::<Vec<HtmlToken>>());
+    }
+
+    #[test]
+    fn test_attribute_quoting() {
+        let html = "<a href=foo.html class='x y'\ttitle=\"a 'b'\"id=\"c\"data-x = z/ / lang=>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+
+        let attributes: Vec<(&str, &str)> = vec![
+            ("href", "foo.html"),
+            ("class", "x y"),
+            ("title", "a 'b'"),
+            ("id", "c"),
+            ("data-x", "z/"),
+            ("lang", ""),
         ];
+        let attributes = attributes
+            .into_iter()
+            .map(|(name, value)| {
+                let mut attr = Attribute::new();
+                name.chars().for_each(|c| attr.add_char(c, true));
+                value.chars().for_each(|c| attr.add_char(c, false));
+                attr
+            })
+            .collect();
+
+        let expected = HtmlToken::StartTag {
+            tag: "a".into(),
+            self_closing: false,
+            attributes,
+        };
+        assert_eq!(Some(expected), tokenizer.next());
+        assert!(tokenizer.next().is_none());
+    }
 
+    #[test]
+    fn test_case_insensitive_names() {
+        let html = "<DIV Class=X HREF='A'></Div>".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        let mut attr1 = Attribute::new();
+        "class".chars().for_each(|c| attr1.add_char(c, true));
+        attr1.add_char('X', false);
+        let mut attr2 = Attribute::new();
+        "href".chars().for_each(|c| attr2.add_char(c, true));
+        attr2.add_char('A', false);
+
+        let expected = [
+            HtmlToken::StartTag {
+                tag: "div".into(),
+                self_closing: false,
+                attributes: vec![attr1, attr2],
+            },
+            HtmlToken::EndTag {
+                tag: "div".into(),
+            },
+        ];
         for e in expected {
             assert_eq!(Some(e), tokenizer.next());
         }
     }
+
+    #[test]
+    fn test_parse_errors() {
+        let html = "<div a=1 b=\"2\"c>\n<p/ x></".to_string();
+        let mut tokenizer = HtmlTokenizer::new(html);
+        while tokenizer.next().is_some() {}
+
+        let errors: Vec<(String, usize, usize, usize)> = tokenizer
+            .errors()
+            .iter()
+            .map(|e| (e.code(), e.position().offset(), e.position().line(), e.position().column()))
+            .collect();
+        let expected = [
+            ("missing-whitespace-between-attributes", 14, 1, 15),
+            ("unexpected-solidus-in-tag", 20, 2, 4),
+            ("eof-before-tag-name", 25, 2, 9),
+        ]
+        .iter()
+        .map(|(code, offset, line, column)| (code.to_string(), *offset, *line, *column))
+        .collect::<Vec<_>>();
+        assert_eq!(expected, errors);
+    }
+
+    #[test]
+    fn test_position_of() {
+        let html = "ab\ncd\n\n\u{3042}\u{3044}e\nf";
+        let tokenizer = HtmlTokenizer::new(html.to_string());
+        let expected = |index: usize| {
+            let consumed = &html[..index];
+            let line = consumed.matches('\n').count() + 1;
+            let column = consumed.chars().rev().take_while(|&c| c != '\n').count() + 1;
+            SourcePosition::new(index, line, column)
+        };
+
+        // 前に進むときも、読み直しで戻るときも、先頭から数えた位置と一致する
+        for index in [0, 1, 4, 3, 10, 7, 6, 13, 2, 14, 15, 16] {
+            assert_eq!(expected(index), tokenizer.position_of(index), "{}", index);
+        }
+    }
+
+    #[test]
+    fn test_streaming() {
+        let html = "<!DOCTYPE html><!-- a -- b -->\n<p class=\"a b\">x &amp; y&#x41;</p>\n<br/ x><p a=1b=2>";
+        let mut whole = HtmlTokenizer::new(html.to_string());
+        let expected: Vec<HtmlToken> = whole.by_ref().collect();
+        let expected_errors = whole.errors();
+
+        // タグや文字参照の途中で区切られた入力でも、まとめて与えた場合と同じトークンとエラーの位置になる
+        let chars: Vec<char> = html.chars().collect();
+        for chunk_size in 1..8 {
+            let mut tokenizer = HtmlTokenizer::new_streaming();
+            let mut tokens = Vec::new();
+            for chunk in chars.chunks(chunk_size) {
+                tokenizer.feed(&chunk.iter().collect::<String>());
+                tokens.extend(&mut tokenizer);
+            }
+            tokenizer.finish();
+            tokens.extend(&mut tokenizer);
+            assert_eq!(expected, tokens);
+            assert_eq!(expected_errors, tokenizer.errors());
+
+            // 読む前に複数の入力を与えても同じ
+            let mut tokenizer = HtmlTokenizer::new_streaming();
+            for chunk in chars.chunks(chunk_size) {
+                tokenizer.feed(&chunk.iter().collect::<String>());
+            }
+            tokenizer.finish();
+            assert_eq!(expected, tokenizer.by_ref().collect::<Vec<_>>());
+            assert_eq!(expected_errors, tokenizer.errors());
+        }
+    }
+
+    #[test]
+    fn test_insert_input() {
+        let with_positions = |tokenizer: &mut HtmlTokenizer| {
+            let mut tokens = Vec::new();
+            while let Some(token) = tokenizer.next() {
+                tokens.push((token, tokenizer.token_position()));
+            }
+            tokens
+        };
+        let expected = with_positions(&mut HtmlTokenizer::new("<p>a</p><i>x\n</i>\n<b>c</b>".to_string()));
+
+        // 最後に出力したトークンの直後に挿入した入力を読んでから、残りの入力を読む
+        let mut tokenizer = HtmlTokenizer::new("<p>a</p>\n<b>c</b>".to_string());
+        let mut tokens = Vec::new();
+        for _ in 0..3 {
+            let token = tokenizer.next().expect("Failed to get a token");
+            tokens.push((token, tokenizer.token_position()));
+        }
+        tokenizer.insert_input("<i>x\n</i>");
+        tokens.extend(with_positions(&mut tokenizer));
+        assert_eq!(expected, tokens);
+    }
+
+    #[test]
+    fn test_malformed_tags() {
+        let chars = |s: &str| s.chars().map(HtmlToken::Char).collect::<Vec<_>>();
+        let comment = |s: &str| HtmlToken::Comment(s.to_string());
+        let cases = [
+            // タグとして解釈できない `<` は文字になる
+            ("a < b", chars("a < b")),
+            ("<1>", chars("<1>")),
+            ("<", [chars("<"), vec![HtmlToken::Eof]].concat()),
+            ("</", [chars("</"), vec![HtmlToken::Eof]].concat()),
+            ("</>x", chars("x")),
+            // 不正なマークアップ宣言はコメントになる
+            ("</1 x>y", [vec![comment("1 x")], chars("y")].concat()),
+            ("<!foo>", vec![comment("foo")]),
+            ("<?xml version?>", vec![comment("?xml version?")]),
+            ("<!-- a > b -->c", [vec![comment(" a > b ")], chars("c")].concat()),
+            ("<!---->", vec![comment("")]),
+            ("<!-->x", [vec![comment("")], chars("x")].concat()),
+            ("<!-- a -- b --!>", vec![comment(" a -- b ")]),
+            ("<!-- eof", vec![comment(" eof"), HtmlToken::Eof]),
+            // タグの途中で入力が終わった場合は、読みかけのタグを捨てる
+            ("<div", vec![HtmlToken::Eof]),
+            ("<div class=a", vec![HtmlToken::Eof]),
+            // `>` がない場合は、次の `<` もタグ名の一部になる
+            (
+                "<div<p>",
+                vec![HtmlToken::StartTag {
+                    tag: "div<p".into(),
+                    self_closing: false,
+                    attributes: Vec::new(),
+                }],
+            ),
+        ];
+
+        for (html, expected) in cases {
+            let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html.to_string()).collect();
+            assert_eq!(expected, tokens, "{}", html);
+        }
+    }
+
+    #[test]
+    fn test_tag_name_spans() {
+        let html = "<div class=a></div><DIV>".to_string();
+        let tokens: Vec<HtmlToken> = HtmlTokenizer::new(html).collect();
+        let tags: Vec<(&str, bool)> = tokens
+            .iter()
+            .filter_map(|t| match t {
+                HtmlToken::StartTag { tag, .. } | HtmlToken::EndTag { tag } => Some((tag.as_str(), tag.is_span())),
+                _ => None,
+            })
+            .collect();
+        // 入力のままのタグ名は入力の範囲を指し、小文字にしたタグ名だけ文字列を作る
+        assert_eq!(vec![("div", true), ("div", true), ("div", false)], tags);
+    }
 }
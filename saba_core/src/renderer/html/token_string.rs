@@ -0,0 +1,184 @@
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::ops::Deref;
+
+/// トークンが持つタグ名や属性の文字列。入力をそのまま使える間は入力の範囲だけを持ち、
+/// 文字参照の展開や小文字への変換で入力と異なる文字列になったときに初めて文字列を確保する
+#[derive(Clone)]
+pub enum TokenString {
+    /// `input` の `start..end` バイト目
+    Span { input: Rc<str>, start: usize, end: usize },
+    Owned(String),
+}
+
+impl TokenString {
+    pub fn new() -> Self {
+        Self::Owned(String::new())
+    }
+
+    pub fn span(input: &Rc<str>, start: usize, end: usize) -> Self {
+        Self::Span {
+            input: input.clone(),
+            start,
+            end,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Span { input, start, end } => &input[*start..*end],
+            Self::Owned(s) => s,
+        }
+    }
+
+    /// 入力の範囲を指していればtrue
+    pub fn is_span(&self) -> bool {
+        matches!(self, Self::Span { .. })
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.to_mut().push(c);
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.to_mut().push_str(s);
+    }
+
+    /// `other` を末尾に追加する。空の文字列か、`other` が同じ入力で直前の範囲にそのまま続く範囲であれば、
+    /// 範囲を伸ばすだけで文字列は確保しない
+    pub fn append(&mut self, other: TokenString) {
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+        if let (
+            Self::Span { input, end, .. },
+            Self::Span {
+                input: other_input,
+                start: other_start,
+                end: other_end,
+            },
+        ) = (&mut *self, &other)
+        {
+            if Rc::ptr_eq(input, other_input) && *end == *other_start {
+                *end = *other_end;
+                return;
+            }
+        }
+        self.push_str(&other);
+    }
+
+    /// 入力の範囲を指していれば、その部分だけを複製した文字列にする。
+    /// DOMに残す前に呼び、入力全体が解放されずに残らないようにする
+    pub fn into_owned(self) -> Self {
+        match self {
+            Self::Span { .. } => Self::Owned(self.as_str().to_string()),
+            owned => owned,
+        }
+    }
+
+    fn to_mut(&mut self) -> &mut String {
+        if let Self::Span { .. } = self {
+            *self = Self::Owned(self.as_str().to_string());
+        }
+        match self {
+            Self::Owned(s) => s,
+            Self::Span { .. } => unreachable!(),
+        }
+    }
+}
+
+impl Default for TokenString {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for TokenString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// 入力の範囲か自分で持つ文字列かに関係なく、中身の文字列で比べる
+impl PartialEq for TokenString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for TokenString {}
+
+impl PartialEq<str> for TokenString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for TokenString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<TokenString> for &str {
+    fn eq(&self, other: &TokenString) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl From<&str> for TokenString {
+    fn from(s: &str) -> Self {
+        Self::Owned(s.to_string())
+    }
+}
+
+impl From<String> for TokenString {
+    fn from(s: String) -> Self {
+        Self::Owned(s)
+    }
+}
+
+/// 範囲の場合も入力全体ではなく、指している文字列だけを書く
+impl fmt::Debug for TokenString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for TokenString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append() {
+        let input: Rc<str> = Rc::from("<div class=a>");
+        let mut s = TokenString::new();
+        s.append(TokenString::span(&input, 1, 3));
+        s.append(TokenString::span(&input, 3, 4));
+        assert!(s.is_span());
+        assert_eq!("div", s);
+
+        // 離れた範囲や文字を追加すると、文字列を確保する
+        s.append(TokenString::span(&input, 5, 7));
+        assert!(!s.is_span());
+        assert_eq!("divcl", s);
+        s.push('!');
+        assert_eq!("divcl!", s);
+
+        let span = TokenString::span(&input, 5, 10);
+        assert_eq!(TokenString::from("class"), span);
+        let owned = span.into_owned();
+        assert!(!owned.is_span());
+        assert_eq!("class", owned.as_str());
+    }
+}
@@ -1,8 +1,14 @@
 use crate::browser::Browser;
-use crate::http::HttpResponse;
-use crate::renderer::dom::node::Window;
+use crate::http::{CancelToken, HttpResponse};
+use crate::navigator::Navigator;
+use crate::renderer::dom::event::{dispatch_event, Event};
+use crate::renderer::dom::node::{normalize, ElementKind, Node, Window};
+use crate::renderer::dom::selector::closest;
 use crate::renderer::html::parser::HtmlParser;
 use crate::renderer::html::token::HtmlTokenizer;
+use crate::renderer::plain_text::PlainTextParser;
+use crate::storage::{SessionStorage, Storage};
+use crate::url::Url;
 use crate::utils::convert_dom_to_string;
 use alloc::rc::{Rc, Weak};
 use alloc::string::{String, ToString};
@@ -12,6 +18,15 @@ use core::cell::RefCell;
 pub struct Page {
     browser: Weak<RefCell<Browser>>,
     frame: Option<Rc<RefCell<Window>>>,
+    /// 表示している文書のURL。相対URLを解決するのに使う
+    url: Option<Url>,
+    scheduled_navigation: Option<ScheduledNavigation>,
+    cancel_token: CancelToken,
+    session_storage: SessionStorage,
+    /// レスポンスのボディを復号した文字コード
+    encoding: String,
+    /// <meta>で宣言された、復号し直すべき文字コード
+    encoding_change: Option<String>,
 }
 
 impl Page {
@@ -19,6 +34,12 @@ impl Page {
         Self {
             browser: Weak::new(),
             frame: None,
+            url: None,
+            scheduled_navigation: None,
+            cancel_token: CancelToken::new(),
+            session_storage: SessionStorage::default(),
+            encoding: "utf-8".to_string(),
+            encoding_change: None,
         }
     }
 
@@ -26,8 +47,89 @@ impl Page {
         self.browser = browser;
     }
 
+    pub fn frame(&self) -> Option<Rc<RefCell<Window>>> {
+        self.frame.clone()
+    }
+
+    pub fn set_url(&mut self, url: Url) {
+        self.url = Some(url);
+    }
+
+    pub fn url(&self) -> Option<Url> {
+        self.url.clone()
+    }
+
+    /// https://html.spec.whatwg.org/multipage/links.html#following-hyperlinks-2
+    /// クリックされた `node` から `click` イベントを浮上させる。`prevent_default()` されず、
+    /// `node` かその祖先がhref属性を持つ<a>であれば、遷移先のURLを返す
+    pub fn handle_click(&self, node: &Rc<RefCell<Node>>) -> Option<Url> {
+        let mut event = Event::new("click", true, true);
+        if !dispatch_event(node, &mut event) {
+            return None;
+        }
+
+        let anchor = closest(node, "a[href]").ok()??;
+        let element = anchor.borrow().get_element()?;
+        match (&self.frame, &self.url) {
+            (Some(frame), Some(url)) => element.resolved_href(&frame.borrow().base_url(url)),
+            // 文書のURLがわからなければ、絶対URLだけをたどる
+            _ => Url::new(element.href()?).ok(),
+        }
+    }
+
+    /// https://html.spec.whatwg.org/multipage/system-state.html#dom-navigator
+    /// ブラウザから切り離されたページでは `None` を返す
+    pub fn navigator(&self) -> Option<Navigator> {
+        self.browser.upgrade().map(|browser| browser.borrow().navigator())
+    }
+
+    /// `origin` のsessionStorage。このページ（タブ）の中で別のページに移動しても残る
+    pub fn session_storage(&mut self, origin: &str) -> &mut Storage {
+        self.session_storage.storage(origin)
+    }
+
+    /// 新しい読み込みを始める。前の読み込みが続いていればそれを中断する
+    /// 返されたトークンは `HttpClient::send` に渡す
+    pub fn start_loading(&mut self) -> CancelToken {
+        self.cancel_token.cancel();
+        self.cancel_token = CancelToken::new();
+        self.cancel_token.clone()
+    }
+
+    /// 読み込み中の通信を中断する
+    pub fn stop(&mut self) {
+        self.cancel_token.cancel();
+    }
+
+    /// 次に受け取るレスポンスのボディを復号した文字コードを設定する
+    pub fn set_encoding(&mut self, encoding: &str) {
+        self.encoding = encoding.to_string();
+    }
+
+    /// https://html.spec.whatwg.org/multipage/parsing.html#changing-the-encoding-while-parsing
+    /// <meta>で宣言された文字コードが復号に使った文字コードと異なる場合に、その文字コードを返す。
+    /// 読み込み側はボディをその文字コードで復号し直し、`set_encoding()` してから `receive_response()` をやり直す
+    pub fn encoding_change(&self) -> Option<String> {
+        self.encoding_change.clone()
+    }
+
     pub fn receive_response(&mut self, response: HttpResponse) -> String {
-        self.create_frame(response.body);
+        if self.cancel_token.is_cancelled() {
+            return "".to_string();
+        }
+
+        self.encoding_change = None;
+        if is_plain_text(&response) {
+            self.frame = Some(PlainTextParser::new(response.body).construct_tree());
+        } else {
+            // Content-Typeヘッダで指定された文字コードは<meta>の宣言より優先する
+            let has_header_charset = header_charset(&response).is_some();
+            self.create_frame(response.body);
+            if has_header_charset {
+                self.encoding_change = None;
+            }
+        }
+        self.scheduled_navigation = self.find_meta_refresh();
 
         if let Some(frame) = &self.frame {
             let dom = frame.borrow().document().clone();
@@ -38,9 +140,279 @@ impl Page {
         "".to_string()
     }
 
+    /// `<meta http-equiv="refresh">` によって予約されたナビゲーション
+    pub fn scheduled_navigation(&self) -> Option<ScheduledNavigation> {
+        self.scheduled_navigation.clone()
+    }
+
     fn create_frame(&mut self, html: String) {
         let html_tokenizer = HtmlTokenizer::new(html);
-        let frame = HtmlParser::new(html_tokenizer).construct_tree();
+        let mut parser = HtmlParser::new(html_tokenizer);
+        parser.set_encoding(&self.encoding);
+        let frame = parser.construct_tree();
+        // 描画の前に、隣り合うテキストノードをまとめておく
+        normalize(&frame.borrow().document());
+        self.encoding_change = parser.encoding_change();
         self.frame = Some(frame);
     }
+
+    fn find_meta_refresh(&self) -> Option<ScheduledNavigation> {
+        let frame = self.frame.as_ref()?;
+        let head = frame.borrow().head()?;
+
+        let mut child = head.borrow().first_element_child();
+        while let Some(node) = child {
+            if let Some(element) = node.borrow().get_element() {
+                if element.kind() == ElementKind::Meta {
+                    let attributes = element.attributes();
+                    let is_refresh = attributes.iter().any(|a| {
+                        a.name() == "http-equiv" && a.value().eq_ignore_ascii_case("refresh")
+                    });
+                    let content = attributes.iter().find(|a| a.name() == "content");
+
+                    if let (true, Some(content)) = (is_refresh, content) {
+                        if let Some(navigation) = parse_refresh_content(&content.value()) {
+                            return Some(navigation);
+                        }
+                    }
+                }
+            }
+            child = node.borrow().next_element_sibling();
+        }
+
+        None
+    }
+}
+
+fn is_plain_text(response: &HttpResponse) -> bool {
+    match response.header_value("Content-Type") {
+        Ok(content_type) => {
+            let mime_type = content_type.split(';').next().unwrap_or("").trim();
+            mime_type.eq_ignore_ascii_case("text/plain")
+        }
+        Err(_) => false,
+    }
+}
+
+/// Content-Typeヘッダの `charset` パラメータ
+fn header_charset(response: &HttpResponse) -> Option<String> {
+    let content_type = response.header_value("Content-Type").ok()?;
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("charset") {
+            Some(value.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledNavigation {
+    /// 遷移までの秒数
+    pub delay: u64,
+    /// 遷移先のURL。`None` の場合は現在のページを再読み込みする
+    pub url: Option<String>,
+}
+
+/// https://html.spec.whatwg.org/multipage/semantics.html#shared-declarative-refresh-steps
+fn parse_refresh_content(content: &str) -> Option<ScheduledNavigation> {
+    let input = content.trim_start();
+
+    let digits_end = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let delay = match input[..digits_end].parse() {
+        Ok(delay) => delay,
+        Err(_) => {
+            if !input.starts_with('.') {
+                return None;
+            }
+            0
+        }
+    };
+
+    let input = input[digits_end..].trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if input.is_empty() {
+        return Some(ScheduledNavigation { delay, url: None });
+    }
+
+    if !input.starts_with(|c: char| c == ';' || c == ',' || c.is_ascii_whitespace()) {
+        return None;
+    }
+
+    let mut url = input
+        .trim_start_matches(|c: char| c.is_ascii_whitespace())
+        .trim_start_matches([';', ','])
+        .trim_start_matches(|c: char| c.is_ascii_whitespace());
+
+    if url.len() >= 3 && url[..3].eq_ignore_ascii_case("url") {
+        let rest = url[3..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if let Some(rest) = rest.strip_prefix('=') {
+            url = rest.trim_start_matches(|c: char| c.is_ascii_whitespace());
+        }
+    }
+
+    if let Some(quote) = url.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        url = &url[1..];
+        if let Some(end) = url.find(quote) {
+            url = &url[..end];
+        }
+    }
+
+    let url = url.trim_end();
+    if url.is_empty() {
+        return Some(ScheduledNavigation { delay, url: None });
+    }
+
+    Some(ScheduledNavigation {
+        delay,
+        url: Some(url.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn navigation(delay: u64, url: Option<&str>) -> Option<ScheduledNavigation> {
+        Some(ScheduledNavigation {
+            delay,
+            url: url.map(|u| u.to_string()),
+        })
+    }
+
+    #[test]
+    fn test_parse_refresh_content() {
+        assert_eq!(navigation(5, None), parse_refresh_content("5"));
+        assert_eq!(navigation(0, Some("http://example.com")), parse_refresh_content("0; url=http://example.com"));
+        assert_eq!(navigation(3, Some("next.html")), parse_refresh_content(" 3,URL = 'next.html' "));
+        assert_eq!(navigation(1, Some("next.html")), parse_refresh_content("1.5; next.html"));
+        assert_eq!(None, parse_refresh_content("soon"));
+        assert_eq!(None, parse_refresh_content("5x"));
+    }
+
+    #[test]
+    fn test_meta_refresh() {
+        let raw = "HTTP/1.1 200 OK\n\n<html><head><meta charset=utf-8><meta http-equiv=Refresh content=\"2; url=http://example.com/next\"></head><body></body></html>".to_string();
+        let response = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+        let mut page = Page::new();
+        page.receive_response(response);
+
+        assert_eq!(navigation(2, Some("http://example.com/next")), page.scheduled_navigation());
+
+        // DOCTYPEがあっても<html>の中の<meta>を見つける
+        let raw = "HTTP/1.1 200 OK\n\n<!DOCTYPE html><!-- c --><html><head><meta http-equiv=refresh content=0></head></html>".to_string();
+        let mut page = Page::new();
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+        assert_eq!(navigation(0, None), page.scheduled_navigation());
+    }
+
+    #[test]
+    fn test_handle_click() {
+        let raw = "HTTP/1.1 200 OK\n\n<html><head><base href=/docs/></head><body><a href=next.html><b>link</b></a><p>text</p></body></html>".to_string();
+        let mut page = Page::new();
+        page.set_url(Url::new("http://example.com/index.html".to_string()).expect("Failed to parse a URL"));
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+
+        let document = page.frame().expect("Failed to get a frame").borrow().document();
+        let find = |kind: ElementKind| {
+            let node = document.borrow().descendants().find(|n| n.borrow().element_kind() == Some(kind));
+            node.expect("Failed to find an element")
+        };
+
+        // <a>の子孫をクリックしても、<a>のリンクをたどる
+        let url = page.handle_click(&find(ElementKind::B)).expect("Failed to follow a link");
+        assert_eq!("example.com", url.host());
+        assert_eq!("docs/next.html", url.path());
+
+        assert!(page.handle_click(&find(ElementKind::P)).is_none());
+
+        // リンクのリスナーで取り消されたら遷移しない
+        let anchor = find(ElementKind::A);
+        anchor.borrow_mut().add_event_listener("click", |event: &mut Event| event.prevent_default(), false);
+        assert!(page.handle_click(&find(ElementKind::B)).is_none());
+
+        let mut page = Page::new();
+        let raw = "HTTP/1.1 200 OK\n\n<html><body><a href=http://example.org/>abs</a><a href=rel.html>rel</a></body></html>".to_string();
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+        let document = page.frame().expect("Failed to get a frame").borrow().document();
+        let anchors: Vec<Rc<RefCell<Node>>> = document.borrow().descendants().filter(|n| n.borrow().element_kind() == Some(ElementKind::A)).collect();
+        assert_eq!(Some("example.org".to_string()), page.handle_click(&anchors[0]).map(|u| u.host()));
+        assert!(page.handle_click(&anchors[1]).is_none());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let raw = "HTTP/1.1 200 OK\nContent-Type: text/plain; charset=utf-8\n\n<p>not a tag</p>".to_string();
+        let response = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+        let mut page = Page::new();
+        let dom = page.receive_response(response);
+
+        assert!(dom.contains("Text(\"<p>not a tag</p>\")"), "{}", dom);
+    }
+
+    #[test]
+    fn test_encoding_change() {
+        let raw = "HTTP/1.1 200 OK\n\n<html><head><meta charset=euc-jp></head><body></body></html>".to_string();
+        let mut page = Page::new();
+        page.receive_response(HttpResponse::new(raw.clone()).expect("Failed to parse HTTP response"));
+        assert_eq!(Some("euc-jp".to_string()), page.encoding_change());
+
+        page.set_encoding("euc-jp");
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+        assert_eq!(None, page.encoding_change());
+
+        let raw = "HTTP/1.1 200 OK\nContent-Type: text/html; charset=utf-8\n\n<html><head><meta charset=euc-jp></head></html>".to_string();
+        let mut page = Page::new();
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+        assert_eq!(None, page.encoding_change());
+    }
+
+    #[test]
+    fn test_start_loading_cancels_previous_load() {
+        let mut page = Page::new();
+        let first = page.start_loading();
+        let second = page.start_loading();
+        assert!(first.is_cancelled());
+        assert!(!second.is_cancelled());
+
+        page.stop();
+        assert!(second.is_cancelled());
+    }
+
+    #[test]
+    fn test_no_meta_refresh() {
+        let raw = "HTTP/1.1 200 OK\n\n<html><head></head><body><p>text</p></body></html>".to_string();
+        let response = HttpResponse::new(raw).expect("Failed to parse HTTP response");
+        let mut page = Page::new();
+        page.receive_response(response);
+
+        assert_eq!(None, page.scheduled_navigation());
+    }
+
+    #[test]
+    fn test_navigator() {
+        let browser = Browser::new();
+        let page = browser.borrow().current_page();
+        assert_eq!(Some(true), page.borrow().navigator().map(|n| n.on_line()));
+
+        browser.borrow_mut().set_on_line(false);
+        assert_eq!(Some(false), page.borrow().navigator().map(|n| n.on_line()));
+
+        assert_eq!(None, Page::new().navigator());
+    }
+
+    #[test]
+    fn test_session_storage_survives_navigation() {
+        let mut page = Page::new();
+        page.session_storage("http://example.com")
+            .set_item("count", "1")
+            .expect("Failed to set an item");
+
+        let raw = "HTTP/1.1 200 OK\n\n<html><head></head><body></body></html>".to_string();
+        page.receive_response(HttpResponse::new(raw).expect("Failed to parse HTTP response"));
+
+        assert_eq!(Some("1".to_string()), page.session_storage("http://example.com").get_item("count"));
+        assert_eq!(None, Page::new().session_storage("http://example.com").get_item("count"));
+    }
 }
@@ -0,0 +1,141 @@
+use crate::renderer::dom::node::{append_child, Node, NodeKind, Window};
+use crate::renderer::html::attribute::Attribute;
+use crate::renderer::html::parser::create_element_node;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+const LINK_PREFIXES: [&str; 2] = ["http://", "https://"];
+
+/// `text/plain` の文書からDOMツリーを作る。本文中のURLは<a>要素で囲む
+#[derive(Debug, Clone)]
+pub struct PlainTextParser {
+    text: String,
+}
+
+impl PlainTextParser {
+    pub fn new(text: String) -> Self {
+        Self { text }
+    }
+
+    pub fn construct_tree(&self) -> Rc<RefCell<Window>> {
+        let window = Rc::new(RefCell::new(Window::new()));
+        let document = window.borrow().document();
+
+        let html = insert(&document, create_element_node("html", Vec::new()));
+        insert(&html, create_element_node("head", Vec::new()));
+        let body = insert(&html, create_element_node("body", Vec::new()));
+
+        let mut rest = self.text.as_str();
+        while !rest.is_empty() {
+            let (before, link, after) = split_first_link(rest);
+
+            if !before.is_empty() {
+                insert(&body, Node::new(NodeKind::Text(before.to_string())));
+            }
+            if let Some(link) = link {
+                let mut href = Attribute::new();
+                href.add_str("href", true);
+                href.add_str(link, false);
+                let a = insert(&body, create_element_node("a", vec![href]));
+                insert(&a, Node::new(NodeKind::Text(link.to_string())));
+            }
+
+            rest = after;
+        }
+
+        window
+    }
+}
+
+/// 最初に現れるURLの前の文字列、URL、URLの後の文字列に分割する
+fn split_first_link(text: &str) -> (&str, Option<&str>, &str) {
+    let start = LINK_PREFIXES
+        .iter()
+        .filter_map(|prefix| text.find(prefix))
+        .min();
+
+    let start = match start {
+        Some(start) => start,
+        None => return (text, None, ""),
+    };
+
+    let candidate = &text[start..];
+    let end = candidate
+        .find(|c: char| c.is_whitespace() || c == '<' || c == '>' || c == '"' || c == '\'')
+        .unwrap_or(candidate.len());
+    // 文末の句読点や閉じ括弧はURLに含めない
+    let link = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']']);
+
+    if LINK_PREFIXES.contains(&link) {
+        let next = start + link.len();
+        let (before, link, after) = split_first_link(&text[next..]);
+        return (&text[..next + before.len()], link, after);
+    }
+
+    (&text[..start], Some(link), &text[start + link.len()..])
+}
+
+/// 作ったばかりのノードを末尾に追加する。空の木に組み立てるので失敗しない
+fn insert(parent: &Rc<RefCell<Node>>, node: Node) -> Rc<RefCell<Node>> {
+    append_child(parent, &Rc::new(RefCell::new(node))).expect("Failed to append a child")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::ElementKind;
+
+    fn text_of(node: &Rc<RefCell<Node>>) -> String {
+        match node.borrow().kind() {
+            NodeKind::Text(s) => s,
+            _ => panic!("node should be a text node"),
+        }
+    }
+
+    #[test]
+    fn test_split_first_link() {
+        assert_eq!(("no links", None, ""), split_first_link("no links"));
+        assert_eq!(
+            ("see ", Some("http://example.com/a?b=c"), ". next"),
+            split_first_link("see http://example.com/a?b=c. next")
+        );
+        assert_eq!(
+            ("(", Some("https://example.com"), ")"),
+            split_first_link("(https://example.com)")
+        );
+        assert_eq!(
+            ("http:// and ", Some("http://example.com"), ""),
+            split_first_link("http:// and http://example.com")
+        );
+    }
+
+    #[test]
+    fn test_construct_tree() {
+        let text = "log: http://example.com/a done".to_string();
+        let window = PlainTextParser::new(text).construct_tree();
+        let document = window.borrow().document();
+
+        let html = document.borrow().first_child().expect("Failed to get a first child of document");
+        let body = html.borrow().last_child().upgrade().expect("Failed to get a last child of html");
+        assert_eq!(Some(ElementKind::Body), body.borrow().element_kind());
+
+        let text1 = body.borrow().first_child().expect("Failed to get a first child of body");
+        assert_eq!("log: ", text_of(&text1));
+
+        let a = text1.borrow().next_sibling().expect("Failed to get a next sibling of text");
+        let element = a.borrow().get_element().expect("Failed to get an element");
+        assert_eq!(ElementKind::A, element.kind());
+        assert_eq!(Some("http://example.com/a".to_string()), element.get_attribute("href"));
+        assert_eq!(1, element.attributes().to_vec().len());
+
+        let link_text = a.borrow().first_child().expect("Failed to get a first child of a");
+        assert_eq!("http://example.com/a", text_of(&link_text));
+
+        let text2 = a.borrow().next_sibling().expect("Failed to get a next sibling of a");
+        assert_eq!(" done", text_of(&text2));
+        assert!(text2.borrow().next_sibling().is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::error::Error;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// 1つのオリジンが保存できる文字数の上限
+pub const DEFAULT_QUOTA: usize = 5 * 1024 * 1024;
+
+/// https://html.spec.whatwg.org/multipage/webstorage.html#the-storage-interface
+/// キーと値の文字数の合計が `quota` を超える書き込みは失敗する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Storage {
+    quota: usize,
+    items: Vec<(String, String)>,
+}
+
+impl Storage {
+    pub fn new(quota: usize) -> Self {
+        Self {
+            quota,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        self.items.len()
+    }
+
+    /// `index` 番目に追加されたキーを返す
+    pub fn key(&self, index: usize) -> Option<String> {
+        self.items.get(index).map(|(key, _)| key.clone())
+    }
+
+    pub fn get_item(&self, key: &str) -> Option<String> {
+        self.items
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn set_item(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let old_size = self.get_item(key).map_or(0, |old| item_size(key, &old));
+        if self.used() - old_size + item_size(key, value) > self.quota {
+            return Err(Error::QuotaExceeded);
+        }
+
+        match self.items.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.items.push((key.to_string(), value.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn remove_item(&mut self, key: &str) {
+        self.items.retain(|(k, _)| k != key);
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// 保存している文字数
+    pub fn used(&self) -> usize {
+        self.items.iter().map(|(k, v)| item_size(k, v)).sum()
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUOTA)
+    }
+}
+
+fn item_size(key: &str, value: &str) -> usize {
+    key.chars().count() + value.chars().count()
+}
+
+/// https://html.spec.whatwg.org/multipage/webstorage.html#dom-sessionstorage
+/// タブ（ `Page` ）ごとに持ち、同じタブ内でページを移動しても残る。タブを閉じると破棄される
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionStorage {
+    quota: usize,
+    storages: Vec<(String, Storage)>,
+}
+
+impl SessionStorage {
+    pub fn new(quota: usize) -> Self {
+        Self {
+            quota,
+            storages: Vec::new(),
+        }
+    }
+
+    /// `origin` （例: `http://example.com:8000`）のストレージを返す。なければ空のストレージを作る
+    pub fn storage(&mut self, origin: &str) -> &mut Storage {
+        let index = match self.storages.iter().position(|(o, _)| o == origin) {
+            Some(index) => index,
+            None => {
+                self.storages.push((origin.to_string(), Storage::new(self.quota)));
+                self.storages.len() - 1
+            }
+        };
+        &mut self.storages[index].1
+    }
+
+    pub fn clear(&mut self) {
+        self.storages.clear();
+    }
+}
+
+impl Default for SessionStorage {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUOTA)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage() {
+        let mut storage = Storage::default();
+        assert_eq!(Ok(()), storage.set_item("a", "1"));
+        assert_eq!(Ok(()), storage.set_item("b", "2"));
+        assert_eq!(Ok(()), storage.set_item("a", "3"));
+
+        assert_eq!(2, storage.length());
+        assert_eq!(Some("a".to_string()), storage.key(0));
+        assert_eq!(Some("3".to_string()), storage.get_item("a"));
+        assert_eq!(None, storage.get_item("c"));
+
+        storage.remove_item("a");
+        assert_eq!(Some("b".to_string()), storage.key(0));
+        storage.clear();
+        assert_eq!(0, storage.length());
+    }
+
+    #[test]
+    fn test_quota() {
+        let mut storage = Storage::new(6);
+        assert_eq!(Ok(()), storage.set_item("ab", "cd"));
+        assert_eq!(Err(Error::QuotaExceeded), storage.set_item("e", "fg"));
+        // 既存の値を置き換える場合は古い値の分を差し引く
+        assert_eq!(Ok(()), storage.set_item("ab", "cdef"));
+        assert_eq!(6, storage.used());
+        assert_eq!(Some("cdef".to_string()), storage.get_item("ab"));
+    }
+
+    #[test]
+    fn test_session_storage_per_origin() {
+        let mut session_storage = SessionStorage::new(10);
+        session_storage
+            .storage("http://a.example")
+            .set_item("k", "v")
+            .expect("Failed to set an item");
+
+        assert_eq!(Some("v".to_string()), session_storage.storage("http://a.example").get_item("k"));
+        assert_eq!(None, session_storage.storage("http://b.example").get_item("k"));
+        assert_eq!(
+            Err(Error::QuotaExceeded),
+            session_storage.storage("http://b.example").set_item("0123456789", "x")
+        );
+    }
+}
@@ -30,6 +30,115 @@ impl Url {
             }
         )
     }
+
+    pub fn host(&self) -> String {
+        self.host.clone()
+    }
+
+    pub fn port(&self) -> String {
+        self.port.clone()
+    }
+
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    pub fn searchpart(&self) -> String {
+        self.searchpart.clone()
+    }
+
+    /// https://url.spec.whatwg.org/#concept-basic-url-parser
+    /// `self` をベースURLとして相対URLを解決する。
+    pub fn join(&self, input: &str) -> Result<Self, String> {
+        let input = input.trim();
+
+        if input.is_empty() {
+            return Ok(self.clone());
+        }
+
+        if has_scheme(input) {
+            return Url::new(input.to_string());
+        }
+
+        if input.starts_with("//") {
+            return Url::new(format!("http:{}", input));
+        }
+
+        let (input_path, rest) = split_path_and_rest(input);
+        let path = if input_path.is_empty() {
+            format!("/{}", self.path)
+        } else if input_path.starts_with('/') {
+            remove_dot_segments(input_path)
+        } else {
+            let directory = match self.path.rfind('/') {
+                Some(index) => &self.path[..index + 1],
+                None => "",
+            };
+            remove_dot_segments(&format!("/{}{}", directory, input_path))
+        };
+
+        let rest = if input_path.is_empty() && rest.starts_with('#') && !self.searchpart.is_empty() {
+            format!("?{}{}", self.searchpart, rest)
+        } else {
+            rest.to_string()
+        };
+
+        Url::new(format!("http://{}{}{}", self.authority(), path, rest))
+    }
+
+    fn authority(&self) -> String {
+        if self.port == "80" {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+fn has_scheme(input: &str) -> bool {
+    match input.find(':') {
+        Some(index) => {
+            let scheme = &input[..index];
+            !scheme.is_empty()
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        }
+        None => false,
+    }
+}
+
+/// 相対URLをパス部分とそれ以降（`?`または`#`から始まる部分）に分割する。
+fn split_path_and_rest(input: &str) -> (&str, &str) {
+    match input.find(['?', '#']) {
+        Some(index) => (&input[..index], &input[index..]),
+        None => (input, ""),
+    }
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc3986#section-5.2.4
+fn remove_dot_segments(path: &str) -> String {
+    let mut output: Vec<&str> = Vec::new();
+    let segments: Vec<&str> = path.split('/').skip(1).collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let is_last = i == segments.len() - 1;
+        match *segment {
+            "." => {
+                if is_last {
+                    output.push("");
+                }
+            }
+            ".." => {
+                output.pop();
+                if is_last {
+                    output.push("");
+                }
+            }
+            s => output.push(s),
+        }
+    }
+
+    format!("/{}", output.join("/"))
 }
 
 fn is_supported_protocol(url: &str) -> bool {
@@ -95,7 +204,9 @@ fn remove_scheme(url: &str) -> String {
 #[cfg(test)]
 mod tests {
     use crate::url::Url;
-    use alloc::string::ToString;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+    use alloc::vec::Vec;
 
     #[test]
     fn test_url_host() {
@@ -162,4 +273,138 @@ mod tests {
         let expected = Err("Invalid scheme.".to_string());
         assert_eq!(expected, Url::new(url))
     }
+
+    /// WPTの urltestdata.json から抜き出したテストケース
+    /// https://github.com/web-platform-tests/wpt/blob/master/url/resources/urltestdata.json
+    struct UrlTestData {
+        input: &'static str,
+        base: Option<&'static str>,
+        /// `None` は `"failure": true` のケースを表す
+        expected: Option<ExpectedUrl>,
+    }
+
+    struct ExpectedUrl {
+        hostname: &'static str,
+        port: &'static str,
+        pathname: &'static str,
+        search: &'static str,
+    }
+
+    const fn success(
+        input: &'static str,
+        base: Option<&'static str>,
+        hostname: &'static str,
+        port: &'static str,
+        pathname: &'static str,
+        search: &'static str,
+    ) -> UrlTestData {
+        UrlTestData {
+            input,
+            base,
+            expected: Some(ExpectedUrl { hostname, port, pathname, search }),
+        }
+    }
+
+    const fn failure(input: &'static str, base: Option<&'static str>) -> UrlTestData {
+        UrlTestData { input, base, expected: None }
+    }
+
+    const BASE: Option<&str> = Some("http://example.org/foo/bar");
+
+    const URL_TEST_DATA: [UrlTestData; 30] = [
+        success("http://example.com/", None, "example.com", "", "/", ""),
+        success("http://example.com:8080/", None, "example.com", "8080", "/", ""),
+        success("http://f:0/c", BASE, "f", "0", "/c", ""),
+        success("http://f:21/b?d", BASE, "f", "21", "/b", "?d"),
+        success("http://f:/c", BASE, "f", "", "/c", ""),
+        success("http://f:00000000000000/c", BASE, "f", "0", "/c", ""),
+        failure("http://f:b/c", BASE),
+        failure("http://f: /c", BASE),
+        failure("http://f:999999/c", BASE),
+        success("http://www/foo%2Ehtml", None, "www", "", "/foo%2Ehtml", ""),
+        success("http://ExAmPlE.CoM", BASE, "example.com", "", "/", ""),
+        success("http://example.com?a=b", None, "example.com", "", "/", "?a=b"),
+        success("http://example.com/foo/bar/../ton/../../a", None, "example.com", "", "/a", ""),
+        success("http://www.google.com/foo?bar=baz#", None, "www.google.com", "", "/foo", "?bar=baz"),
+        success("http://a:b@c:29/d", None, "c", "29", "/d", ""),
+        failure("http://[www.google.com]/", None),
+        success("https://example.com/", None, "example.com", "", "/", ""),
+        success("", BASE, "example.org", "", "/foo/bar", ""),
+        success("  \t", BASE, "example.org", "", "/foo/bar", ""),
+        success("/a/b/c", BASE, "example.org", "", "/a/b/c", ""),
+        success("foo.com", BASE, "example.org", "", "/foo/foo.com", ""),
+        success("..", BASE, "example.org", "", "/", ""),
+        success(".", BASE, "example.org", "", "/foo/", ""),
+        success("./baz/../qux", BASE, "example.org", "", "/foo/qux", ""),
+        success("/a/../../b", BASE, "example.org", "", "/b", ""),
+        success("?x", BASE, "example.org", "", "/foo/bar", "?x"),
+        success("//example.com/x", BASE, "example.com", "", "/x", ""),
+        success("http://example.com/a?b", BASE, "example.com", "", "/a", "?b"),
+        success("http:/example.com/", BASE, "example.org", "", "/example.com/", ""),
+        failure("http://", BASE),
+    ];
+
+    /// まだ対応していないために失敗することが分かっているテストケースの `input` の一覧
+    const EXPECTED_FAILURES: [&str; 13] = [
+        "http://f:00000000000000/c",
+        "http://f:b/c",
+        "http://f: /c",
+        "http://f:999999/c",
+        "http://ExAmPlE.CoM",
+        "http://example.com?a=b",
+        "http://example.com/foo/bar/../ton/../../a",
+        "http://www.google.com/foo?bar=baz#",
+        "http://a:b@c:29/d",
+        "http://[www.google.com]/",
+        "https://example.com/",
+        "http:/example.com/",
+        "http://",
+    ];
+
+    fn run_url_test(test: &UrlTestData) -> bool {
+        let result = match test.base {
+            Some(base) => Url::new(base.to_string()).and_then(|b| b.join(test.input)),
+            None => Url::new(test.input.to_string()),
+        };
+
+        match (&test.expected, result) {
+            (None, Err(_)) => true,
+            (Some(expected), Ok(url)) => {
+                // デフォルトポートの場合、WPTでは空文字列になる
+                let port = if url.port == "80" { "" } else { url.port.as_str() };
+                let search = if url.searchpart.is_empty() {
+                    String::new()
+                } else {
+                    format!("?{}", url.searchpart)
+                };
+
+                url.host == expected.hostname
+                    && port == expected.port
+                    && format!("/{}", url.path) == expected.pathname
+                    && search == expected.search
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_url_test_data() {
+        let mut unexpected_failures = Vec::new();
+        let mut unexpected_passes = Vec::new();
+
+        for test in URL_TEST_DATA.iter() {
+            let passed = run_url_test(test);
+            let expected_to_fail = EXPECTED_FAILURES.contains(&test.input);
+
+            if !passed && !expected_to_fail {
+                unexpected_failures.push(test.input);
+            }
+            if passed && expected_to_fail {
+                unexpected_passes.push(test.input);
+            }
+        }
+
+        assert!(unexpected_failures.is_empty(), "unexpected failures: {:?}", unexpected_failures);
+        assert!(unexpected_passes.is_empty(), "unexpected passes (remove them from EXPECTED_FAILURES): {:?}", unexpected_passes);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::renderer::dom::node::Node;
+use crate::renderer::dom::node::{Namespace, Node, NodeKind, ShadowRootMode};
 use alloc::format;
 use alloc::rc::Rc;
 use alloc::string::String;
@@ -28,3 +28,111 @@ fn convert_dom_to_string_internal(
         None => {}
     }
 }
+
+/// テキストノードの文字列を表示する最大の文字数
+const TEXT_EXCERPT_LENGTH: usize = 40;
+
+/// `node` を根とする部分木を、1行に1ノードずつ字下げして書く。テストで期待する木と比べるのに使う
+///
+/// ```text
+/// <p>
+///   class="a"
+///   "text"
+///   <svg svg>
+/// ```
+///
+/// 属性は要素の1段下に書き、HTML以外の名前空間の要素には名前空間を付ける。
+/// <template>の中身は `content` の下に、シャドウルートは子の前に書く
+pub fn dump_tree(node: &Rc<RefCell<Node>>) -> String {
+    let mut result = String::new();
+    dump_tree_internal(node, 0, &mut result);
+    result
+}
+
+fn dump_tree_internal(node: &Rc<RefCell<Node>>, depth: usize, result: &mut String) {
+    let indent = "  ".repeat(depth);
+    let node = node.borrow();
+    match node.kind {
+        NodeKind::Document => result.push_str("#document"),
+        NodeKind::DocumentFragment => result.push_str("#document-fragment"),
+        NodeKind::ShadowRoot(ShadowRootMode::Open) => result.push_str(&format!("{}#shadow-root (open)", indent)),
+        NodeKind::ShadowRoot(ShadowRootMode::Closed) => result.push_str(&format!("{}#shadow-root (closed)", indent)),
+        NodeKind::Element(ref element) => {
+            match element.namespace() {
+                Namespace::Html => result.push_str(&format!("{}<{}>", indent, element.local_name())),
+                Namespace::Svg => result.push_str(&format!("{}<svg {}>", indent, element.local_name())),
+                Namespace::MathMl => result.push_str(&format!("{}<math {}>", indent, element.local_name())),
+            }
+            for attribute in element.attributes() {
+                result.push_str(&format!("\n{}  {}=\"{}\"", indent, attribute.name(), attribute.value()));
+            }
+        }
+        NodeKind::Text(ref s) => {
+            let excerpt: String = s.chars().take(TEXT_EXCERPT_LENGTH).collect();
+            let ellipsis = if s.chars().count() > TEXT_EXCERPT_LENGTH { "..." } else { "" };
+            result.push_str(&format!("{}{:?}{}", indent, excerpt, ellipsis));
+        }
+        NodeKind::Comment(ref s) => result.push_str(&format!("{}<!-- {} -->", indent, s)),
+        NodeKind::DocumentType(ref doctype) => result.push_str(&format!("{}<!DOCTYPE {}>", indent, doctype.name())),
+    }
+    result.push('\n');
+
+    // 文書と文書片は字下げせずに、子を同じ段から書く
+    let child_depth = match node.kind {
+        NodeKind::Document | NodeKind::DocumentFragment => depth,
+        _ => depth + 1,
+    };
+    if let Some(content) = node.template_content() {
+        result.push_str(&format!("{}  content\n", indent));
+        for child in content.borrow().child_nodes() {
+            dump_tree_internal(&child, depth + 2, result);
+        }
+    }
+    if let Some(shadow_root) = node.attached_shadow_root() {
+        dump_tree_internal(&shadow_root, depth + 1, result);
+    }
+    for child in node.child_nodes() {
+        dump_tree_internal(&child, child_depth, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::dom::node::{attach_shadow, set_inner_html};
+    use crate::renderer::html::parser::HtmlParser;
+    use crate::renderer::html::token::HtmlTokenizer;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_dump_tree() {
+        let html = "<!DOCTYPE html><html><head><template><i>t</i></template></head><body><!--c--><p class=a id=b>text<svg><circle r=1></circle></svg></p><pre>abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyz\n</pre></body></html>".to_string();
+        let window = HtmlParser::new(HtmlTokenizer::new(html)).construct_tree();
+        let expected = r#"#document
+<!DOCTYPE html>
+<html>
+  <head>
+    <template>
+      content
+        <i>
+          "t"
+  <body>
+    <!-- c -->
+    <p>
+      class="a"
+      id="b"
+      "text"
+      <svg svg>
+        <svg circle>
+          r="1"
+    <pre>
+      "abcdefghijklmnopqrstuvwxyzabcdefghijklmn"...
+"#;
+        assert_eq!(expected, dump_tree(&window.borrow().document()));
+
+        let host = window.borrow().body().expect("Failed to get body");
+        let shadow_root = attach_shadow(&host, ShadowRootMode::Closed).expect("Failed to attach a shadow root");
+        set_inner_html(&shadow_root, "<b>s</b>");
+        assert!(dump_tree(&host).starts_with("<body>\n  #shadow-root (closed)\n    <b>\n      \"s\"\n  <!-- c -->\n"));
+    }
+}